@@ -1,5 +1,4 @@
-import { useState , useMemo } from 'react'
-import debounce from 'lodash.debounce'
+import { useState , useRef , useEffect } from 'react'
 import {
   Autocomplete ,
   AutocompleteInput ,
@@ -10,9 +9,11 @@ import {
   AutocompleteEmpty ,
   AutocompleteCollection ,
 } from './components/ui/autocomplete'
-import { searchBooks } from './api/search'
+import { openAutocomplete , type Autocomplete as AutocompleteClient } from './api/autocomplete'
 import type { SearchResult } from './types/search'
 import { getBook } from "@/api/get.ts";
+import { getLoadStatus } from './api/status'
+import type { LoadStatus } from './types/status'
 
 // Helper function to highlight search term in text
 const highlightTerm = ( text: string , term: string ) => {
@@ -32,23 +33,42 @@ function App() {
   const [ searchTerm , setSearchTerm ] = useState( '' )
   const [ book , setBook ] = useState<Record<string , string>>( {} )
   const [ portalContainer , setPortalContainer ] = useState<HTMLDivElement | null>( null )
+  const [ loadStatus , setLoadStatus ] = useState<LoadStatus | null>( null )
 
-  const debouncedSearch = useMemo(
-    () =>
-      debounce( async ( query: string ) => {
-        setLoading( true )
-        try {
-          const response = await searchBooks( query )
-          setResults( response.results )
-        } catch ( error ) {
-          console.error( 'Search failed:' , error )
-          setResults( [] )
-        } finally {
-          setLoading( false )
-        }
-      } , 300 ) ,
-    []
-  )
+  // Poll the indexing progress until the server finished loading the data
+  useEffect( () => {
+    let timer: ReturnType<typeof setTimeout>
+    const poll = async () => {
+      try {
+        const status = await getLoadStatus()
+        setLoadStatus( status )
+        if ( status.state !== 'loading' ) return
+      } catch ( error ) {
+        console.error( 'Status failed:' , error )
+      }
+      timer = setTimeout( poll , 1000 )
+    }
+    poll()
+    return () => clearTimeout( timer )
+  } , [] )
+
+  // Searches as the user types, over one WebSocket connection
+  const autocomplete = useRef<AutocompleteClient | null>( null )
+  useEffect( () => {
+    const client = openAutocomplete(
+      ( response ) => {
+        setResults( response.results )
+        setLoading( false )
+      } ,
+      ( error ) => {
+        console.error( 'Search failed:' , error )
+        setResults( [] )
+        setLoading( false )
+      }
+    )
+    autocomplete.current = client
+    return () => client.close()
+  } , [] )
 
 
   const handleItemClick =async  ( result: SearchResult ) => {
@@ -59,14 +79,21 @@ function App() {
         setSearchTerm( currentTerm )
       }
     } , 0 )
-    const res_book = await getBook( result.dictionary_index as unknown as string)
-    console.log( res_book )
+    const res_book = result.record ?? await getBook( result.dictionary_index as unknown as string)
     setBook( res_book)
   }
 
   return (
     <div className="min-h-screen bg-gray-50 px-4 sm:px-6 lg:px-8">
       <div className="mx-auto max-w-7xl pt-8 relative z-50" ref={ setPortalContainer }>
+        { loadStatus?.state === 'loading' && (
+          <p className="mb-2 text-sm text-gray-500">
+            Indexing { Math.floor( loadStatus.percent ) }% ({ loadStatus.rows } books)
+          </p>
+        ) }
+        { loadStatus?.state === 'failed' && (
+          <p className="mb-2 text-sm text-red-600">Loading failed: { loadStatus.error }</p>
+        ) }
         <Autocomplete items={ results }>
           <AutocompleteInput
             placeholder="Search books..."
@@ -77,11 +104,13 @@ function App() {
 
               if ( !value?.trim() ) {
                 setResults( [] )
-                debouncedSearch.cancel()
+                setLoading( false )
+                autocomplete.current?.cancel()
                 return
               }
 
-              debouncedSearch( value )
+              setLoading( true )
+              autocomplete.current?.search( value )
             } }
           />
           <AutocompletePositioner container={ portalContainer }>
@@ -90,7 +119,7 @@ function App() {
                 <AutocompleteCollection>
                   { ( result: SearchResult ) => (
                     <AutocompleteItem
-                      key={ `${result.dictionary_index}-${result.position}` }
+                      key={ `${result.dictionary_index}-${result.attribute_index}-${result.value_index}-${result.position}` }
                       value={ result.original_entry }
                       onClick={ ( ev ) => {
                         ev.preventDefault();
@@ -0,0 +1,60 @@
+import type { SearchResponse } from '../types/search';
+
+// The server waits for 150ms without a newer keystroke before it searches
+const AUTOCOMPLETE_URL = 'ws://127.0.0.1:8080/dictionaries/books/autocomplete?debounce_ms=150';
+
+interface AutocompleteMessage extends Partial<SearchResponse> {
+  id?: number;
+  error?: string;
+}
+
+export interface Autocomplete {
+  search: (term: string) => void;
+  cancel: () => void; // skips the answer to the last query
+  close: () => void;
+}
+
+// One connection for all the keystrokes. The server cancels the queries that were typed over,
+// and the answers to anything but the last query are skipped here as well
+export function openAutocomplete(
+  onResults: (response: SearchResponse) => void,
+  onError: (error: string) => void,
+): Autocomplete {
+  const socket = new WebSocket(AUTOCOMPLETE_URL);
+  let lastId = 0;
+  let queued: string | null = null;
+
+  socket.onopen = () => {
+    if (queued) {
+      socket.send(queued);
+      queued = null;
+    }
+  };
+  socket.onerror = () => onError('Autocomplete connection failed');
+  socket.onmessage = (event) => {
+    const message: AutocompleteMessage = JSON.parse(event.data);
+    if (message.error) {
+      onError(message.error);
+    } else if (message.id === lastId) {
+      onResults(message as SearchResponse);
+    }
+  };
+
+  return {
+    search: (term: string) => {
+      lastId += 1;
+      // the records come with the results, so showing a book doesn't need another request
+      const query = JSON.stringify({ id: lastId, term, include_record: true });
+      if (socket.readyState === WebSocket.OPEN) {
+        socket.send(query);
+      } else {
+        queued = query;
+      }
+    },
+    cancel: () => {
+      lastId += 1;
+      queued = null;
+    },
+    close: () => socket.close(),
+  };
+}
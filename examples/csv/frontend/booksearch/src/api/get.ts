@@ -1,4 +1,4 @@
-const API_BASE_URL = 'http://127.0.0.1:8080';
+const API_BASE_URL = 'http://127.0.0.1:8080/dictionaries/books';
 
 export async function getBook(id:string) :Promise<Record<string,string>> {
   const response = await fetch(`${API_BASE_URL}/get?id=${encodeURIComponent(id)}`);
@@ -1,9 +1,11 @@
 import type { SearchResponse } from '../types/search';
 
-const API_BASE_URL = 'http://127.0.0.1:8080';
+const API_BASE_URL = 'http://127.0.0.1:8080/dictionaries/books';
 
+// The records come with the results, so showing a book doesn't need another request
 export async function searchBooks(term: string): Promise<SearchResponse> {
-  const response = await fetch(`${API_BASE_URL}/search?term=${encodeURIComponent(term)}`);
+  const params = new URLSearchParams({ term, include_record: 'true' });
+  const response = await fetch(`${API_BASE_URL}/search?${params}`);
   
   if (!response.ok) {
     throw new Error(`Search failed: ${response.statusText}`);
@@ -0,0 +1,13 @@
+import type { LoadStatus } from '../types/status';
+
+const API_BASE_URL = 'http://127.0.0.1:8080/dictionaries/books';
+
+export async function getLoadStatus(): Promise<LoadStatus> {
+  const response = await fetch(`${API_BASE_URL}/status`);
+
+  if (!response.ok) {
+    throw new Error(`Status failed: ${response.statusText}`);
+  }
+
+  return response.json();
+}
@@ -3,10 +3,22 @@ export interface SearchResult {
   attribute: string;
   original_entry: string;
   attribute_index: number;
+  value_index: number;
   position: number;
   dictionary_index: number;
+  record?: Record<string, string>;
+}
+
+export interface FacetValue {
+  value: string;
+  count: number;
 }
 
 export interface SearchResponse {
   results: SearchResult[];
+  offset: number;
+  next_cursor: string | null;
+  // only for searches with facets or filters
+  total?: number;
+  facets?: Record<string, FacetValue[]>;
 }
@@ -0,0 +1,16 @@
+export interface MalformedRow {
+  line: number;
+  error: string;
+}
+
+export interface LoadStatus {
+  state: 'loading' | 'done' | 'failed';
+  rows: number;
+  bytes: number;
+  total_bytes: number;
+  percent: number;
+  elapsed_secs: number;
+  malformed_rows: number;
+  malformed: MalformedRow[];
+  error: string | null;
+}
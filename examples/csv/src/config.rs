@@ -0,0 +1,155 @@
+use crate::jsonl::JsonLinesSchema;
+use crate::schema::CsvSchema;
+use crate::CsvDictionary;
+use serde::Deserialize;
+use std::collections::HashSet;
+use std::error::Error;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+// The server config lists the dictionaries the server hosts, with the schema of each of them:
+// {
+//   "dictionaries": [
+//     {"name": "books", "schema": "books.schema.json"},
+//     {"name": "reviews", "schema": "reviews.schema.json", "format": "jsonl"}
+//   ]
+// }
+// The schema files are relative to the config file, the data files to their schema.
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DataFormat {
+    #[default]
+    Csv,
+    Jsonl,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct DictionaryConfig {
+    pub name: String, // ASCII letters, digits, '-' and '_', it is a part of the URLs
+    pub schema: PathBuf,
+    #[serde(default)]
+    pub format: DataFormat,
+}
+
+impl DictionaryConfig {
+    /// A new dictionary with the attributes of the schema, to be filled with `CsvDictionary::load`
+    pub fn open(&self) -> Result<CsvDictionary, Box<dyn Error>> {
+        Ok(match self.format {
+            DataFormat::Csv => CsvDictionary::from_schema(CsvSchema::load(&self.schema)?),
+            DataFormat::Jsonl => {
+                CsvDictionary::from_json_lines_schema(JsonLinesSchema::load(&self.schema)?)
+            }
+        })
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ServerConfig {
+    pub dictionaries: Vec<DictionaryConfig>,
+}
+
+impl ServerConfig {
+    pub fn load(path: &Path) -> Result<Self, Box<dyn Error>> {
+        let with_path = |e: &dyn std::fmt::Display| format!("{}: {}", path.display(), e);
+        let data = fs::read(path).map_err(|e| with_path(&e))?;
+        let mut config: ServerConfig = serde_json::from_slice(&data).map_err(|e| with_path(&e))?;
+        for dictionary in &mut config.dictionaries {
+            if dictionary.schema.is_relative() {
+                if let Some(dir) = path.parent() {
+                    dictionary.schema = dir.join(&dictionary.schema);
+                }
+            }
+        }
+        config.validate().map_err(|e| with_path(&e))?;
+        Ok(config)
+    }
+
+    /// The config of a single CSV schema, the dictionary is named after the file, `books.schema.json` is `books`
+    pub fn single(schema: &Path) -> Self {
+        let file_name = schema
+            .file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        let stem = file_name.split('.').next().unwrap_or_default();
+        let name: String = stem
+            .chars()
+            .map(|c| if valid_name_char(c) { c } else { '_' })
+            .collect();
+        ServerConfig {
+            dictionaries: vec![DictionaryConfig {
+                name: if name.is_empty() {
+                    "data".to_string()
+                } else {
+                    name
+                },
+                schema: schema.to_path_buf(),
+                format: DataFormat::Csv,
+            }],
+        }
+    }
+
+    fn validate(&self) -> Result<(), String> {
+        if self.dictionaries.is_empty() {
+            return Err("No dictionaries".to_string());
+        }
+        let mut names = HashSet::new();
+        for dictionary in &self.dictionaries {
+            if dictionary.name.is_empty() || !dictionary.name.chars().all(valid_name_char) {
+                return Err(format!(
+                    "Invalid dictionary name '{}', only ASCII letters, digits, '-' and '_' are allowed",
+                    dictionary.name
+                ));
+            }
+            if !names.insert(dictionary.name.as_str()) {
+                return Err(format!("Dictionary '{}' is defined twice", dictionary.name));
+            }
+        }
+        Ok(())
+    }
+}
+
+fn valid_name_char(c: char) -> bool {
+    c.is_ascii_alphanumeric() || c == '-' || c == '_'
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_server_config() {
+        let config: ServerConfig = serde_json::from_str(
+            r#"{"dictionaries": [
+                {"name": "books", "schema": "books.schema.json"},
+                {"name": "reviews", "schema": "reviews.schema.json", "format": "jsonl"}
+            ]}"#,
+        )
+        .unwrap();
+        config.validate().unwrap();
+        assert_eq!(config.dictionaries[0].format, DataFormat::Csv);
+        assert_eq!(config.dictionaries[1].format, DataFormat::Jsonl);
+
+        let parse = |json: &str| {
+            serde_json::from_str::<ServerConfig>(json)
+                .unwrap()
+                .validate()
+        };
+        assert!(parse(r#"{"dictionaries": []}"#).is_err());
+        assert!(parse(r#"{"dictionaries": [{"name": "a/b", "schema": "a.json"}]}"#).is_err());
+        assert!(parse(
+            r#"{"dictionaries": [{"name": "a", "schema": "a.json"}, {"name": "a", "schema": "b.json"}]}"#
+        )
+        .is_err());
+
+        let single = ServerConfig::single(Path::new("data/books.schema.json"));
+        assert_eq!(single.dictionaries[0].name, "books");
+        single.validate().unwrap();
+        assert_eq!(
+            ServerConfig::single(Path::new("my books.json")).dictionaries[0].name,
+            "my_books"
+        );
+    }
+}
@@ -0,0 +1,267 @@
+use crate::schema::SearchMode;
+use dictionary_bitmap_trie::dictionary::AttributeSearch;
+use serde::Deserialize;
+use serde_json::Value;
+use std::error::Error;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+// JSON Lines files have one JSON document per line. The attributes are picked from the documents with paths:
+// `title`, `author.name`, `tags[*]` for all the elements of an array, `editions[0].year` for one of them.
+// Arrays give one value per element, numbers and booleans are stringified, nulls, objects and missing paths are skipped.
+// {
+//   "path": "books.jsonl",
+//   "attributes": [
+//     {"path": "title", "search": "multiple"},
+//     {"path": "author.name", "name": "author", "search": "exact"},
+//     {"path": "tags[*]", "name": "tag", "search": "exact"}
+//   ]
+// }
+
+#[derive(Debug, Clone, PartialEq)]
+enum Segment {
+    Key(String),
+    Index(usize),
+    Each,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct JsonPath {
+    source: String,
+    segments: Vec<Segment>,
+}
+
+impl JsonPath {
+    pub fn parse(path: &str) -> Result<Self, String> {
+        let invalid = |reason: &str| format!("Invalid path '{}': {}", path, reason);
+        let mut segments = Vec::new();
+        for (i, part) in path.split('.').enumerate() {
+            let (key, mut rest) = part.split_at(part.find('[').unwrap_or(part.len()));
+            if !key.is_empty() {
+                segments.push(Segment::Key(key.to_string()));
+            } else if i > 0 || rest.is_empty() {
+                // only the path itself can start with an index, `[*].name`
+                return Err(invalid("empty key"));
+            }
+            while !rest.is_empty() {
+                let end = rest
+                    .find(']')
+                    .filter(|_| rest.starts_with('['))
+                    .ok_or_else(|| invalid("expected [index] or [*]"))?;
+                segments.push(match &rest[1..end] {
+                    "*" => Segment::Each,
+                    index => Segment::Index(
+                        index
+                            .parse()
+                            .map_err(|_| invalid("the index is not a number"))?,
+                    ),
+                });
+                rest = &rest[end + 1..];
+            }
+        }
+        Ok(JsonPath {
+            source: path.to_string(),
+            segments,
+        })
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.source
+    }
+
+    /// All the values on the path in the document, in the document order
+    pub fn values(&self, document: &Value) -> Vec<String> {
+        let mut nodes = vec![document];
+        for segment in &self.segments {
+            nodes = nodes
+                .into_iter()
+                .flat_map(|node| -> Vec<&Value> {
+                    match (segment, node) {
+                        (Segment::Key(key), Value::Object(o)) => o.get(key).into_iter().collect(),
+                        (Segment::Index(i), Value::Array(a)) => a.get(*i).into_iter().collect(),
+                        (Segment::Each, Value::Array(a)) => a.iter().collect(),
+                        _ => Vec::new(),
+                    }
+                })
+                .collect();
+        }
+        let mut values = Vec::new();
+        for node in nodes {
+            match node {
+                // an array at the end of the path gives its elements, as with [*]
+                Value::Array(a) => values.extend(a.iter().filter_map(scalar)),
+                node => values.extend(scalar(node)),
+            }
+        }
+        values
+    }
+}
+
+fn scalar(value: &Value) -> Option<String> {
+    match value {
+        Value::String(s) => Some(s.clone()),
+        Value::Number(n) => Some(n.to_string()),
+        Value::Bool(b) => Some(b.to_string()),
+        Value::Null | Value::Array(_) | Value::Object(_) => None,
+    }
+}
+
+impl<'de> Deserialize<'de> for JsonPath {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let path = String::deserialize(deserializer)?;
+        JsonPath::parse(&path).map_err(serde::de::Error::custom)
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct JsonAttribute {
+    pub path: JsonPath,
+    #[serde(default)]
+    pub name: Option<String>, // the attribute name, the path if not set
+    #[serde(default)]
+    pub search: SearchMode,
+    #[serde(default)]
+    pub trim: bool,
+}
+
+impl JsonAttribute {
+    pub fn attribute_name(&self) -> &str {
+        self.name.as_deref().unwrap_or(self.path.as_str())
+    }
+
+    /// The values of the attribute in the document, empty if the path is missing
+    pub fn values(&self, document: &Value) -> Vec<String> {
+        self.path
+            .values(document)
+            .into_iter()
+            .map(|v| if self.trim { v.trim().to_string() } else { v })
+            .filter(|v| !v.is_empty())
+            .collect()
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct JsonLinesSchema {
+    pub path: PathBuf, // relative to the schema file
+    pub attributes: Vec<JsonAttribute>,
+}
+
+impl JsonLinesSchema {
+    pub fn load(path: &Path) -> Result<Self, Box<dyn Error>> {
+        let with_path = |e: &dyn std::fmt::Display| format!("{}: {}", path.display(), e);
+        let data = fs::read(path).map_err(|e| with_path(&e))?;
+        let mut schema: JsonLinesSchema =
+            serde_json::from_slice(&data).map_err(|e| with_path(&e))?;
+        if schema.path.is_relative() {
+            if let Some(dir) = path.parent() {
+                schema.path = dir.join(&schema.path);
+            }
+        }
+        schema.validate().map_err(|e| with_path(&e))?;
+        Ok(schema)
+    }
+
+    fn validate(&self) -> Result<(), String> {
+        let mut names = std::collections::HashSet::new();
+        for attribute in &self.attributes {
+            if !names.insert(attribute.attribute_name().to_string()) {
+                return Err(format!(
+                    "Attribute '{}' is defined twice",
+                    attribute.attribute_name()
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    /// The dictionary attributes, in the order of the schema
+    pub fn attributes(&self) -> Vec<(String, AttributeSearch)> {
+        self.attributes
+            .iter()
+            .map(|a| (a.attribute_name().to_string(), a.search.into()))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_parse_path() {
+        assert_eq!(
+            JsonPath::parse("author.name").unwrap().segments,
+            vec![
+                Segment::Key("author".to_string()),
+                Segment::Key("name".to_string())
+            ]
+        );
+        assert_eq!(
+            JsonPath::parse("[*].editions[0]").unwrap().segments,
+            vec![
+                Segment::Each,
+                Segment::Key("editions".to_string()),
+                Segment::Index(0)
+            ]
+        );
+        for invalid in ["", "a..b", "a.[0]", "a[x]", "a[0", "a]0["] {
+            assert!(JsonPath::parse(invalid).is_err(), "{}", invalid);
+        }
+    }
+
+    #[test]
+    fn test_path_values() {
+        let book = json!({
+            "title": "The Hobbit",
+            "year": 1937,
+            "in_print": true,
+            "author": {"name": "J. R. R. Tolkien"},
+            "tags": ["fantasy", null, "classic"],
+            "editions": [{"year": 1937}, {"year": 1951, "notes": {"x": 1}}],
+            "missing": null
+        });
+        let values = |path: &str| JsonPath::parse(path).unwrap().values(&book);
+        assert_eq!(values("title"), vec!["The Hobbit"]);
+        assert_eq!(values("author.name"), vec!["J. R. R. Tolkien"]);
+        assert_eq!(values("year"), vec!["1937"]);
+        assert_eq!(values("in_print"), vec!["true"]);
+        assert_eq!(values("tags[*]"), vec!["fantasy", "classic"]);
+        assert_eq!(values("tags"), vec!["fantasy", "classic"]);
+        assert_eq!(values("tags[1]"), Vec::<String>::new());
+        assert_eq!(values("editions[*].year"), vec!["1937", "1951"]);
+        assert_eq!(values("editions[1].year"), vec!["1951"]);
+        assert!(values("editions[1].notes").is_empty());
+        assert!(values("author.birth.year").is_empty());
+        assert!(values("missing").is_empty());
+        assert!(values("title[*]").is_empty());
+    }
+
+    #[test]
+    fn test_schema() {
+        let schema: JsonLinesSchema = serde_json::from_str(
+            r#"{"path": "books.jsonl", "attributes": [
+                {"path": "title", "search": "multiple"},
+                {"path": "author.name", "name": "author", "search": "exact"}
+            ]}"#,
+        )
+        .unwrap();
+        schema.validate().unwrap();
+        assert_eq!(
+            schema.attributes(),
+            vec![
+                ("title".to_string(), AttributeSearch::Multiple),
+                ("author".to_string(), AttributeSearch::Exact),
+            ]
+        );
+        let parse = |json: &str| serde_json::from_str::<JsonLinesSchema>(json);
+        assert!(parse(r#"{"path": "b.jsonl", "attributes": [{"path": "a[x]"}]}"#).is_err());
+        let schema = parse(
+            r#"{"path": "b.jsonl", "attributes": [{"path": "a.b", "name": "a"}, {"path": "a"}]}"#,
+        )
+        .unwrap();
+        assert!(schema.validate().is_err());
+    }
+}
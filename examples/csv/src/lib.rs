@@ -1,30 +1,191 @@
-use dictionary_bitmap_trie::dictionary::{AttributeSearch, Dictionary,};
+pub mod config;
+pub mod jsonl;
+pub mod query;
+pub mod schema;
+
+use csv::{ByteRecord, ReaderBuilder};
 use dictionary_bitmap_trie::constants::SearchConfig;
-use csv::ReaderBuilder;
+use dictionary_bitmap_trie::dictionary::{AttributeSearch, Dictionary};
+use dictionary_bitmap_trie::metrics::MetricsSnapshot;
+use jsonl::JsonAttribute;
+use schema::{default_columns, CsvSchema, FileEncoding, ResolvedColumn};
 
 use std::collections::HashMap;
 use std::error::Error;
-use std::io::Read;
+use std::fs::File;
+use std::io::{BufRead, BufReader, Read};
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+/// Number of CSV records handed to the dictionary in one parallel ingestion batch
+const LOAD_BATCH_SIZE: usize = 10_000;
+
+/// Malformed rows kept in the load report, the rest are only counted
+pub const MAX_REPORTED_MALFORMED_ROWS: usize = 1000;
+
+/// Progress of a load, reported after every batch is indexed
+#[derive(Debug, Clone, Copy, Default)]
+pub struct LoadProgress {
+    pub rows: usize, // rows indexed so far
+    pub bytes: u64,  // bytes of the input read so far
+    pub elapsed: Duration,
+}
+
+/// A row that couldn't be parsed and was skipped
+#[derive(Debug, Clone, PartialEq)]
+pub struct MalformedRow {
+    pub line: u64,
+    pub error: String,
+}
+
+#[derive(Debug, Default)]
+pub struct LoadReport {
+    pub rows: usize,
+    pub malformed_rows: usize,
+    pub malformed: Vec<MalformedRow>, // the first MAX_REPORTED_MALFORMED_ROWS of them
+    pub elapsed: Duration,
+}
+
+// Collects the entries of a load into batches, and keeps the report and the progress up to date
+struct Ingest<'a, P: FnMut(LoadProgress)> {
+    dictionary: &'a Dictionary,
+    batch: Vec<HashMap<String, Vec<String>>>,
+    report: LoadReport,
+    start: Instant,
+    progress: P,
+}
+
+impl<'a, P: FnMut(LoadProgress)> Ingest<'a, P> {
+    fn new(dictionary: &'a Dictionary, start: Instant, progress: P) -> Self {
+        Self {
+            dictionary,
+            batch: Vec::with_capacity(LOAD_BATCH_SIZE),
+            report: LoadReport::default(),
+            start,
+            progress,
+        }
+    }
+
+    fn malformed(&mut self, line: u64, error: String) {
+        self.report.malformed_rows += 1;
+        if self.report.malformed.len() < MAX_REPORTED_MALFORMED_ROWS {
+            self.report.malformed.push(MalformedRow { line, error });
+        }
+    }
+
+    // bytes is the position in the input after the entry
+    fn push(&mut self, entry_data: HashMap<String, Vec<String>>, bytes: u64) {
+        self.batch.push(entry_data);
+        if self.batch.len() >= LOAD_BATCH_SIZE {
+            self.flush(bytes);
+        }
+    }
+
+    fn flush(&mut self, bytes: u64) {
+        // batches are indexed on all cores
+        self.report.rows += self
+            .dictionary
+            .add_multi_valued_entries(std::mem::take(&mut self.batch))
+            .iter()
+            .flatten()
+            .count();
+        (self.progress)(LoadProgress {
+            rows: self.report.rows,
+            bytes,
+            elapsed: self.start.elapsed(),
+        });
+    }
+
+    fn finish(mut self, bytes: u64) -> LoadReport {
+        self.flush(bytes);
+        self.report.elapsed = self.start.elapsed();
+        self.report
+    }
+}
 
 /// A simple CSV-based dictionary loader that can read CSV files
 /// and populate bitmap_trie dictionaries for fast searching
 pub struct CsvDictionary {
     dictionary: Dictionary,
+    schema: Option<CsvSchema>,
+    json_lines_schema: Option<jsonl::JsonLinesSchema>,
 }
 
 impl CsvDictionary {
     /// Create a new CSV dictionary with specified attribute configurations
     pub fn new(attributes: Vec<(String, AttributeSearch)>) -> Self {
         let dictionary = Dictionary::new(attributes, SearchConfig::default());
-        Self { dictionary }
+        Self {
+            dictionary,
+            schema: None,
+            json_lines_schema: None,
+        }
+    }
+
+    /// Create a CSV dictionary with the attributes of the schema, to be loaded with `load`
+    pub fn from_schema(schema: CsvSchema) -> Self {
+        let dictionary = Dictionary::new(schema.attributes(), SearchConfig::default());
+        Self {
+            dictionary,
+            schema: Some(schema),
+            json_lines_schema: None,
+        }
+    }
+
+    /// Create a dictionary with the attributes of the JSON Lines schema, to be loaded with `load`
+    pub fn from_json_lines_schema(schema: jsonl::JsonLinesSchema) -> Self {
+        let dictionary = Dictionary::new(schema.attributes(), SearchConfig::default());
+        Self {
+            dictionary,
+            schema: None,
+            json_lines_schema: Some(schema),
+        }
+    }
+
+    pub fn schema(&self) -> Option<&CsvSchema> {
+        self.schema.as_ref()
+    }
+
+    /// The data file of the schema, CSV or JSON Lines
+    pub fn data_path(&self) -> Option<&Path> {
+        match (&self.schema, &self.json_lines_schema) {
+            (Some(schema), _) => Some(&schema.path),
+            (None, Some(schema)) => Some(&schema.path),
+            (None, None) => None,
+        }
     }
 
-    /// Load data from CSV reader into the dictionary
+    /// Load the file of the schema, only the columns of the schema are kept, after their transforms
+    pub fn load(&self, progress: impl FnMut(LoadProgress)) -> Result<LoadReport, Box<dyn Error>> {
+        let start = Instant::now();
+        if let Some(schema) = &self.json_lines_schema {
+            return self.load_json_lines_file(schema, progress);
+        }
+        let schema = self
+            .schema
+            .as_ref()
+            .ok_or("The dictionary was not created from a schema")?;
+        let file =
+            File::open(&schema.path).map_err(|e| format!("{}: {}", schema.path.display(), e))?;
+        let mut csv_reader = schema.reader_builder().from_reader(BufReader::new(file));
+        let headers = if schema.has_headers {
+            csv_reader.byte_headers()?.clone()
+        } else {
+            ByteRecord::new()
+        };
+        let columns = schema.resolve(&headers)?;
+        self.load_records(csv_reader, &columns, schema.encoding, start, progress)
+    }
+
+    /// Load data from CSV reader into the dictionary, streaming it in batches.
+    /// Malformed rows are skipped and collected in the report, only I/O errors abort the load
     pub fn load_from_csv<R: Read>(
         &self,
         reader: R,
         has_headers: bool,
-    ) -> Result<usize, Box<dyn Error>> {
+        progress: impl FnMut(LoadProgress),
+    ) -> Result<LoadReport, Box<dyn Error>> {
+        let start = Instant::now();
         let mut csv_reader = ReaderBuilder::new()
             .has_headers(has_headers)
             .from_reader(reader);
@@ -41,22 +202,121 @@ impl CsvDictionary {
             )
         };
 
-        let mut count = 0;
-        for result in csv_reader.records() {
-            let record = result?;
-            let mut entry_data = HashMap::new();
+        let columns = default_columns(&headers);
+        self.load_records(csv_reader, &columns, FileEncoding::Utf8, start, progress)
+    }
 
-            for (i, field) in record.iter().enumerate() {
-                if let Some(header) = headers.get(i) {
-                    entry_data.insert(header.to_string(), field.to_string());
-                }
+    // The entry of a record, the values of each column after its transforms
+    fn record_entry(
+        record: &ByteRecord,
+        columns: &[ResolvedColumn],
+        encoding: FileEncoding,
+    ) -> Result<HashMap<String, Vec<String>>, String> {
+        let mut entry_data = HashMap::new();
+        for column in columns {
+            let Some(field) = record.get(column.index) else {
+                continue;
+            };
+            let values = column.schema.transform(&encoding.decode(field)?);
+            if !values.is_empty() {
+                entry_data.insert(column.attribute.clone(), values);
+            }
+        }
+        Ok(entry_data)
+    }
+
+    fn load_records<R: Read>(
+        &self,
+        mut csv_reader: csv::Reader<R>,
+        columns: &[ResolvedColumn],
+        encoding: FileEncoding,
+        start: Instant,
+        progress: impl FnMut(LoadProgress),
+    ) -> Result<LoadReport, Box<dyn Error>> {
+        let mut ingest = Ingest::new(&self.dictionary, start, progress);
+        let mut record = ByteRecord::new();
+        loop {
+            match csv_reader.read_byte_record(&mut record) {
+                Ok(true) => match Self::record_entry(&record, columns, encoding) {
+                    Ok(entry_data) => ingest.push(entry_data, csv_reader.position().byte()),
+                    Err(e) => ingest.malformed(record.position().map_or(0, |p| p.line()), e),
+                },
+                Ok(false) => break,
+                Err(e) if e.is_io_error() => return Err(e.into()),
+                Err(e) => ingest.malformed(
+                    e.position()
+                        .map_or(csv_reader.position().line(), |p| p.line()),
+                    e.to_string(),
+                ),
             }
+        }
+        Ok(ingest.finish(csv_reader.position().byte()))
+    }
 
-            self.dictionary.add_dictionary_entry(entry_data);
-            count += 1;
+    /// Load JSON Lines, one JSON object per line, picking the attributes from each document by their paths.
+    /// The values of an attribute found on several paths, like `tags[*]`, are indexed as separate values.
+    /// Lines that aren't JSON objects are skipped and collected in the report, blank lines are ignored
+    pub fn load_json_lines<R: BufRead>(
+        &self,
+        mut reader: R,
+        attributes: &[JsonAttribute],
+        progress: impl FnMut(LoadProgress),
+    ) -> Result<LoadReport, Box<dyn Error>> {
+        let mut ingest = Ingest::new(&self.dictionary, Instant::now(), progress);
+        let (mut line, mut bytes) = (String::new(), 0u64);
+        for number in 1.. {
+            line.clear();
+            let read = reader.read_line(&mut line)?;
+            if read == 0 {
+                break;
+            }
+            bytes += read as u64;
+            if line.trim().is_empty() {
+                continue;
+            }
+            let document = match serde_json::from_str::<serde_json::Value>(&line) {
+                Ok(document) if document.is_object() => document,
+                Ok(_) => {
+                    ingest.malformed(number, "expected a JSON object".to_string());
+                    continue;
+                }
+                Err(e) => {
+                    ingest.malformed(number, e.to_string());
+                    continue;
+                }
+            };
+            let entry_data = attributes
+                .iter()
+                .filter_map(|attribute| {
+                    let values = attribute.values(&document);
+                    (!values.is_empty()).then(|| (attribute.attribute_name().to_string(), values))
+                })
+                .collect();
+            ingest.push(entry_data, bytes);
         }
+        Ok(ingest.finish(bytes))
+    }
 
-        Ok(count)
+    /// Load the JSON Lines file of the schema
+    pub fn load_json_lines_file(
+        &self,
+        schema: &jsonl::JsonLinesSchema,
+        progress: impl FnMut(LoadProgress),
+    ) -> Result<LoadReport, Box<dyn Error>> {
+        let file =
+            File::open(&schema.path).map_err(|e| format!("{}: {}", schema.path.display(), e))?;
+        self.load_json_lines(BufReader::new(file), &schema.attributes, progress)
+    }
+
+    /// Load data from a CSV file, without reading it in memory first
+    pub fn load_from_file(
+        &self,
+        path: impl AsRef<Path>,
+        has_headers: bool,
+        progress: impl FnMut(LoadProgress),
+    ) -> Result<LoadReport, Box<dyn Error>> {
+        let file = File::open(path)?;
+        self.load_from_csv(BufReader::new(file), has_headers, progress)
     }
 
     /// Search the dictionary
@@ -64,6 +324,11 @@ impl CsvDictionary {
         self.dictionary.search(term)
     }
 
+    /// Counters, latencies and sizes of the dictionary
+    pub fn metrics(&self) -> MetricsSnapshot {
+        self.dictionary.metrics()
+    }
+
     pub fn get(&self, id: &str) -> HashMap<String, String> {
         if let Ok(id) = id.parse::<usize>() {
             self.dictionary.get(id)
@@ -88,17 +353,119 @@ mod tests {
             ("country".to_string(), AttributeSearch::Exact),
         ];
 
-        let mut dict = CsvDictionary::new(attributes);
+        let dict = CsvDictionary::new(attributes);
         let reader = Cursor::new(csv_data);
 
-        let count = dict.load_from_csv(reader, true).unwrap();
+        let count = dict.load_from_csv(reader, true, |_| {}).unwrap().rows;
         assert_eq!(count, 3);
 
         // Test searching
         let results = dict.search("John");
-        assert!(results.len() > 0);
+        assert!(!results.is_empty());
 
         let results = dict.search("New");
-        assert!(results.len() > 0);
+        assert!(!results.is_empty());
+    }
+
+    #[test]
+    fn test_malformed_rows_and_progress() {
+        let csv_data =
+            "name,city\nJohn Doe,New York\nJane Smith\nBob Johnson,Paris\nAnn Lee,Rome,Italy\n";
+        let dict = CsvDictionary::new(vec![
+            ("name".to_string(), AttributeSearch::Multiple),
+            ("city".to_string(), AttributeSearch::Exact),
+        ]);
+
+        let mut reported = Vec::new();
+        let report = dict
+            .load_from_csv(Cursor::new(csv_data), true, |p| reported.push(p))
+            .unwrap();
+        assert_eq!(report.rows, 2);
+        assert_eq!(report.malformed_rows, 2);
+        let lines: Vec<u64> = report.malformed.iter().map(|r| r.line).collect();
+        assert_eq!(lines, vec![3, 5]);
+        let last = reported.last().unwrap();
+        assert_eq!(last.rows, 2);
+        assert_eq!(last.bytes, csv_data.len() as u64);
+        assert!(!dict.search("Bob").is_empty());
+    }
+
+    #[test]
+    fn test_load_with_schema() {
+        let dir = std::env::temp_dir().join(format!("csvexample-schema-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(
+            dir.join("books.csv"),
+            b"1|The Hobbit| Tolkien ; Someone Else |x\n2|Caf\xe9 Stories|Anon|y\n",
+        )
+        .unwrap();
+        let schema_path = dir.join("books.schema.json");
+        std::fs::write(
+            &schema_path,
+            r#"{
+                "path": "books.csv",
+                "delimiter": "|",
+                "has_headers": false,
+                "encoding": "latin1",
+                "columns": [
+                    {"column": 1, "name": "title", "search": "multiple"},
+                    {"column": 2, "name": "authors", "search": "exact", "split": ";", "trim": true},
+                    {"column": 0, "name": "id"}
+                ]
+            }"#,
+        )
+        .unwrap();
+
+        let dict = CsvDictionary::from_schema(schema::CsvSchema::load(&schema_path).unwrap());
+        let report = dict.load(|_| {}).unwrap();
+        assert_eq!(report.rows, 2);
+        assert_eq!(report.malformed_rows, 0);
+        let book = dict.get("0");
+        assert_eq!(book["authors"], "Tolkien; Someone Else");
+        assert_eq!(book["id"], "1");
+        assert!(!book.contains_key("column_3"));
+        assert_eq!(dict.get("1")["title"], "Café Stories");
+        assert!(!dict.search("Hobbit").is_empty());
+        assert!(!dict.search("Tolkien").is_empty());
+        // each part of a split cell is its own value, the exact search matches the start of every part
+        let someone = dict.search("Someone Else");
+        assert_eq!(someone[0].value_index, 1);
+        assert_eq!(someone[0].original_entry, "Someone Else");
+        assert!(dict.search("Tolkien; Someone").is_empty());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_load_json_lines() {
+        let schema: jsonl::JsonLinesSchema = serde_json::from_str(
+            r#"{"path": "books.jsonl", "attributes": [
+                {"path": "title", "search": "multiple"},
+                {"path": "authors[*].name", "name": "author", "search": "exact"},
+                {"path": "year"}
+            ]}"#,
+        )
+        .unwrap();
+        let data = r#"{"title": "Good Omens", "authors": [{"name": "Terry Pratchett"}, {"name": "Neil Gaiman"}], "year": 1990}
+
+[1, 2]
+{"title": "Untitled", "authors": []
+{"title": "Mort", "authors": [{"name": "Terry Pratchett"}]}
+"#;
+        let dict = CsvDictionary::new(schema.attributes());
+        let mut reported = Vec::new();
+        let report = dict
+            .load_json_lines(Cursor::new(data), &schema.attributes, |p| reported.push(p))
+            .unwrap();
+        assert_eq!(report.rows, 2);
+        let lines: Vec<u64> = report.malformed.iter().map(|r| r.line).collect();
+        assert_eq!(lines, vec![3, 4]);
+        assert_eq!(reported.last().unwrap().bytes, data.len() as u64);
+
+        let book = dict.get("0");
+        assert_eq!(book["author"], "Terry Pratchett; Neil Gaiman");
+        assert_eq!(dict.search("Neil")[0].original_entry, "Neil Gaiman");
+        assert_eq!(book["year"], "1990");
+        assert!(!dict.get("1").contains_key("year"));
+        assert!(!dict.search("Mort").is_empty());
     }
 }
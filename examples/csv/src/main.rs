@@ -1,148 +1,593 @@
 use actix_cors::Cors;
-use actix_web::{get, web, App, HttpResponse, HttpServer, Responder};
-use dictionary_bitmap_trie::dictionary::AttributeSearch;
-use csvexample::CsvDictionary;
-use serde::Deserialize;
-use serde::Serialize;
-use serde_json;
-use std::error::Error;
-use std::io::{Cursor, Read};
-use std::sync::Arc;
-use std::time::SystemTime;
-
-// Define a serializable wrapper for the search response
-#[derive(Serialize)]
-struct SearchResponse<'a> {
-    results: Vec<SearchResultJson<'a>>,
+use actix_web::{
+    delete, error, get, post, put, rt, web, App, HttpRequest, HttpResponse, HttpServer, Responder,
+};
+use actix_ws::Message;
+use csvexample::config::{DictionaryConfig, ServerConfig};
+use csvexample::query::{
+    federated_search, AutocompleteRequest, AutocompleteResponse, EntryValue, GetParams, QueryError,
+    SearchParams,
+};
+use csvexample::{CsvDictionary, LoadProgress, LoadReport};
+use dictionary_bitmap_trie::metrics::prometheus_text;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, RwLock};
+use std::time::{Duration, SystemTime};
+
+#[derive(Serialize, Clone, Copy, Default, PartialEq)]
+#[serde(rename_all = "lowercase")]
+enum LoadState {
+    #[default]
+    Loading,
+    Done,
+    Failed,
 }
 
-#[derive(Serialize)]
-struct SearchResultJson<'a> {
-    term: &'a str,
-    attribute: &'a str,
-    original_entry: &'a str,
-    attribute_index: usize,
-    position: usize,
-    dictionary_index: usize,
+#[derive(Serialize, Clone)]
+struct MalformedRowJson {
+    line: u64,
+    error: String,
 }
 
-struct AppState {
-    dict: Arc<CsvDictionary>,
+// Progress of the data load, polled by the UI while the dictionary is indexed
+#[derive(Serialize, Clone, Default)]
+struct LoadStatus {
+    state: LoadState,
+    rows: usize,
+    bytes: u64,
+    total_bytes: u64,
+    percent: f64,
+    elapsed_secs: f64,
+    malformed_rows: usize,
+    malformed: Vec<MalformedRowJson>, // the first of the malformed rows, with their line numbers
+    error: Option<String>,
+    reload: bool, // a reload fills a fresh dictionary, the current one is served until it is done
 }
 
-#[derive(Deserialize)]
-struct SearchQuery {
-    term: String,
+impl LoadStatus {
+    fn update(&mut self, progress: LoadProgress) {
+        self.rows = progress.rows;
+        self.bytes = progress.bytes;
+        self.elapsed_secs = progress.elapsed.as_secs_f64();
+        if self.total_bytes > 0 {
+            self.percent = (progress.bytes as f64 * 100.0 / self.total_bytes as f64).min(100.0);
+        }
+    }
 }
 
-#[derive(Deserialize)]
-struct IdGet {
-    id: String,
+// One of the hosted dictionaries. The dictionary is swapped by the reloads, handlers take the current one,
+// and keep it for the whole request
+struct Dataset {
+    config: DictionaryConfig,
+    dict: RwLock<Arc<CsvDictionary>>,
+    status: RwLock<LoadStatus>,
 }
 
-fn read_from_file(path: &str) -> Result<String, std::io::Error> {
-    let mut file = std::fs::File::open(path)?;
-    let mut contents = String::new();
-    file.read_to_string(&mut contents)?;
-    Ok(contents)
+impl Dataset {
+    fn dict(&self) -> Arc<CsvDictionary> {
+        Arc::clone(&self.dict.read().unwrap())
+    }
 }
 
-#[get("/search")]
-async fn srca(data: web::Data<AppState>, query: web::Query<SearchQuery>) -> impl Responder {
-    let dict = Arc::clone(&data.dict);
-    let resp = dict.search(&query.term);
+// the datasets in the order of the config
+struct AppState {
+    datasets: Vec<Arc<Dataset>>,
+}
 
-    // Wrap the response in a serializable structure
-    let json_response = SearchResponse {
-        results: resp
+impl AppState {
+    fn dataset(&self, name: &str) -> Result<&Arc<Dataset>, HttpResponse> {
+        self.datasets
             .iter()
-            .map(|r| SearchResultJson {
-                term: &r.term,
-                attribute: &r.attribute,
-                original_entry: &r.original_entry,
-                attribute_index: r.attribute_index,
-                position: r.position,
-                dictionary_index: r.dictionary_index,
+            .find(|dataset| dataset.config.name == name)
+            .ok_or_else(|| {
+                error_response(QueryError::NotFound(format!("No dictionary '{}'", name)))
             })
-            .collect(),
-    };
+    }
+}
+
+// the dictionaries the server hosts, can be changed with --config, or replaced by a single schema file
+const CONFIG_FILE: &str = "server.json";
 
-    let json = serde_json::to_string(&json_response).unwrap();
+// how often the watcher checks the schema and the data file for changes
+const WATCH_INTERVAL: Duration = Duration::from_secs(2);
+
+// longest wait for the next keystroke an autocomplete connection can ask for
+const MAX_DEBOUNCE_MS: u64 = 1000;
+
+const USAGE: &str = "Usage: csvexample [--config server.json | schema file] [--watch]";
+
+// errors are returned as {"error": "..."}, with the status of the error
+fn error_response(e: QueryError) -> HttpResponse {
+    let body = serde_json::json!({ "error": e.to_string() });
+    match e {
+        QueryError::BadRequest(_) => HttpResponse::BadRequest().json(body),
+        QueryError::NotFound(_) => HttpResponse::NotFound().json(body),
+    }
+}
+
+fn conflict(message: &str) -> HttpResponse {
+    HttpResponse::Conflict().json(serde_json::json!({ "error": message }))
+}
+
+fn json_response<T: Serialize>(res: Result<T, QueryError>) -> HttpResponse {
+    match res {
+        Ok(body) => HttpResponse::Ok().json(body),
+        Err(e) => error_response(e),
+    }
+}
+
+// Runs the handler on the dataset of the path, 404 if there is no such dictionary
+fn with_dataset(
+    data: &AppState,
+    name: &str,
+    handler: impl FnOnce(&Arc<Dataset>) -> HttpResponse,
+) -> HttpResponse {
+    match data.dataset(name) {
+        Ok(dataset) => handler(dataset),
+        Err(response) => response,
+    }
+}
+
+// Runs a write on the current dictionary. Writes are refused during a reload, the swap would drop them,
+// the status lock keeps a reload from starting while the write runs
+fn write_entry<T: Serialize>(
+    dataset: &Dataset,
+    write: impl FnOnce(&CsvDictionary) -> Result<T, QueryError>,
+) -> Result<Result<T, QueryError>, HttpResponse> {
+    let status = dataset.status.read().unwrap();
+    if status.reload && status.state == LoadState::Loading {
+        return Err(conflict(
+            "The data is being reloaded, try again when it's done",
+        ));
+    }
+    Ok(write(&dataset.dict()))
+}
+
+#[derive(Serialize)]
+struct DictionaryInfo {
+    name: String,
+    state: LoadState,
+    rows: usize,
+}
+
+#[get("/dictionaries")]
+async fn list_dictionaries(data: web::Data<AppState>) -> impl Responder {
+    let dictionaries: Vec<DictionaryInfo> = data
+        .datasets
+        .iter()
+        .map(|dataset| {
+            let status = dataset.status.read().unwrap();
+            DictionaryInfo {
+                name: dataset.config.name.clone(),
+                state: status.state,
+                rows: status.rows,
+            }
+        })
+        .collect();
+    HttpResponse::Ok().json(serde_json::json!({ "dictionaries": dictionaries }))
+}
+
+// Prometheus text exposition of all the dictionaries. A reload starts the counters of the dictionary over
+#[get("/metrics")]
+async fn metrics(data: web::Data<AppState>) -> impl Responder {
+    let metrics: Vec<_> = data
+        .datasets
+        .iter()
+        .map(|dataset| (dataset.config.name.as_str(), dataset.dict().metrics()))
+        .collect();
+    let metrics: Vec<_> = metrics.iter().map(|(name, m)| (*name, m)).collect();
     HttpResponse::Ok()
-        .content_type("application/json")
-        .body(json)
+        .content_type("text/plain; version=0.0.4")
+        .body(prometheus_text(&metrics))
+}
+
+#[derive(Deserialize)]
+struct FederatedParams {
+    dictionaries: Option<String>, // comma separated names, all the dictionaries by default
+}
+
+// Searches several dictionaries at once, every hit is tagged with the name of its dictionary
+#[get("/search")]
+async fn federated(
+    data: web::Data<AppState>,
+    query: web::Query<SearchParams>,
+    names: web::Query<FederatedParams>,
+    pairs: web::Query<Vec<(String, String)>>,
+) -> impl Responder {
+    let params = match query.into_inner().with_filters(&pairs) {
+        Ok(params) => params,
+        Err(e) => return error_response(e),
+    };
+    let datasets = match names.dictionaries.as_deref() {
+        Some(names) => {
+            let mut datasets = Vec::new();
+            for name in names
+                .split(',')
+                .map(str::trim)
+                .filter(|name| !name.is_empty())
+            {
+                match data.dataset(name) {
+                    Ok(dataset) => datasets.push(dataset),
+                    Err(response) => return response,
+                }
+            }
+            datasets
+        }
+        None => data.datasets.iter().collect(),
+    };
+    let dicts: Vec<_> = datasets
+        .iter()
+        .map(|dataset| (dataset.config.name.as_str(), dataset.dict()))
+        .collect();
+    let dicts: Vec<_> = dicts
+        .iter()
+        .map(|(name, dict)| (*name, dict.as_ref()))
+        .collect();
+    json_response(federated_search(&dicts, &params))
+}
+
+#[get("/dictionaries/{name}/search")]
+async fn srca(
+    data: web::Data<AppState>,
+    name: web::Path<String>,
+    query: web::Query<SearchParams>,
+    pairs: web::Query<Vec<(String, String)>>,
+) -> impl Responder {
+    with_dataset(&data, &name, |dataset| {
+        let params = query.into_inner().with_filters(&pairs);
+        json_response(params.and_then(|params| dataset.dict().search_page(&params)))
+    })
 }
 
-#[get("/get")]
+#[get("/dictionaries/{name}/get")]
 async fn get(
     data: web::Data<AppState>,
-    query: web::Query<IdGet>,
+    name: web::Path<String>,
+    query: web::Query<GetParams>,
 ) -> impl Responder {
-    let dict = Arc::clone(&data.dict);
-    let resp = dict.get(&query.id);
-    let json = serde_json::to_string(&resp).unwrap();
-    HttpResponse::Ok()
-        .content_type("application/json")
-        .body(json)
+    with_dataset(&data, &name, |dataset| {
+        json_response(dataset.dict().get_record(&query))
+    })
+}
+
+#[post("/dictionaries/{name}/entries")]
+async fn add_entry(
+    data: web::Data<AppState>,
+    name: web::Path<String>,
+    entry: web::Json<HashMap<String, EntryValue>>,
+) -> impl Responder {
+    with_dataset(&data, &name, |dataset| {
+        match write_entry(dataset, |dict| dict.add_entry(entry.into_inner())) {
+            Ok(Ok(id)) => HttpResponse::Created().json(serde_json::json!({ "id": id })),
+            Ok(Err(e)) => error_response(e),
+            Err(response) => response,
+        }
+    })
+}
+
+#[put("/dictionaries/{name}/entries/{id}")]
+async fn replace_entry(
+    data: web::Data<AppState>,
+    path: web::Path<(String, String)>,
+    entry: web::Json<HashMap<String, EntryValue>>,
+) -> impl Responder {
+    let (name, id) = path.into_inner();
+    with_dataset(&data, &name, |dataset| {
+        match write_entry(dataset, |dict| dict.replace_entry(&id, entry.into_inner())) {
+            Ok(res) => json_response(res.map(|id| serde_json::json!({ "id": id }))),
+            Err(response) => response,
+        }
+    })
+}
+
+#[delete("/dictionaries/{name}/entries/{id}")]
+async fn delete_entry(
+    data: web::Data<AppState>,
+    path: web::Path<(String, String)>,
+) -> impl Responder {
+    let (name, id) = path.into_inner();
+    with_dataset(&data, &name, |dataset| {
+        match write_entry(dataset, |dict| dict.delete_entry(&id)) {
+            Ok(Ok(())) => HttpResponse::NoContent().finish(),
+            Ok(Err(e)) => error_response(e),
+            Err(response) => response,
+        }
+    })
+}
+
+#[post("/dictionaries/{name}/reload")]
+async fn reload_data(data: web::Data<AppState>, name: web::Path<String>) -> impl Responder {
+    with_dataset(&data, &name, |dataset| {
+        if spawn_load(Arc::clone(dataset), true) {
+            HttpResponse::Accepted().json(serde_json::json!({ "state": LoadState::Loading }))
+        } else {
+            conflict("The data is already being loaded")
+        }
+    })
+}
+
+#[derive(Deserialize)]
+struct AutocompleteParams {
+    #[serde(default)]
+    debounce_ms: u64, // waits for this long without a newer query before searching
+}
+
+// The client sends a query on every keystroke, {"id": 3, "term": "tolk"}, with the parameters of /search,
+// and gets back the page of the results with the same id. A newer query cancels the one before it,
+// the results of stale queries are never sent.
+#[get("/dictionaries/{name}/autocomplete")]
+async fn autocomplete(
+    req: HttpRequest,
+    body: web::Payload,
+    data: web::Data<AppState>,
+    name: web::Path<String>,
+    query: web::Query<AutocompleteParams>,
+) -> actix_web::Result<HttpResponse> {
+    let dataset = match data.dataset(&name) {
+        Ok(dataset) => Arc::clone(dataset),
+        Err(response) => return Ok(response),
+    };
+    let (response, mut session, mut stream) = actix_ws::handle(&req, body)?;
+    let debounce = Duration::from_millis(query.debounce_ms.min(MAX_DEBOUNCE_MS));
+    rt::spawn(async move {
+        let mut running: Option<rt::task::JoinHandle<()>> = None;
+        while let Some(Ok(msg)) = stream.recv().await {
+            match msg {
+                Message::Text(text) => {
+                    let request = match serde_json::from_str::<AutocompleteRequest>(&text) {
+                        Ok(request) => request,
+                        Err(e) => {
+                            let error = serde_json::json!({ "error": e.to_string() });
+                            if session.text(error.to_string()).await.is_err() {
+                                break;
+                            }
+                            continue;
+                        }
+                    };
+                    if let Some(task) = running.take() {
+                        task.abort();
+                    }
+                    let dataset = Arc::clone(&dataset);
+                    let mut session = session.clone();
+                    running = Some(rt::spawn(async move {
+                        if !debounce.is_zero() {
+                            rt::time::sleep(debounce).await;
+                        }
+                        // an aborted task stops at the next await, a search that already runs is finished
+                        // on the blocking pool, and its results dropped
+                        let dict = dataset.dict();
+                        let res = web::block(move || dict.search_page(&request.params)).await;
+                        if let Ok(res) = res {
+                            let response = AutocompleteResponse::new(request.id, res);
+                            let json = serde_json::to_string(&response).unwrap();
+                            let _ = session.text(json).await;
+                        }
+                    }));
+                }
+                Message::Ping(bytes) if session.pong(&bytes).await.is_err() => break,
+                Message::Close(reason) => {
+                    let _ = session.clone().close(reason).await;
+                    break;
+                }
+                _ => {}
+            }
+        }
+        if let Some(task) = running {
+            task.abort();
+        }
+    });
+    Ok(response)
+}
+
+#[get("/dictionaries/{name}/status")]
+async fn load_status(data: web::Data<AppState>, name: web::Path<String>) -> impl Responder {
+    with_dataset(&data, &name, |dataset| {
+        let status = dataset.status.read().unwrap().clone();
+        HttpResponse::Ok().json(status)
+    })
 }
 
 #[actix_web::main]
 async fn main() -> std::io::Result<()> {
     println!("CSV Dictionary Example");
     println!("======================");
-    let attributes = vec![
-        ("Title".to_string(), AttributeSearch::Multiple),
-        ("Authors".to_string(), AttributeSearch::Multiple),
-        ("Description".to_string(), AttributeSearch::Multiple),
-        ("Publisher".to_string(), AttributeSearch::Exact),
-        ("Publish Date".to_string(), AttributeSearch::None),
-        ("Price".to_string(), AttributeSearch::None),
-    ];
-
-    // Create and populate dictionary
-    let dict = Arc::new(CsvDictionary::new(attributes));
-    let app_state = web::Data::new(AppState {
-        dict: Arc::clone(&dict), // your CsvDictionary instance
-    });
-    tokio::task::spawn(async move {
-        let dict = Arc::clone(&dict);
-        if let Err(e) = load_data(&dict) {
-            eprintln!("Failed to load data: {}", e);
+    let mut config_file = None;
+    let mut schema_file = None;
+    let mut watch = false;
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--watch" => watch = true,
+            "--config" => match args.next() {
+                Some(path) => config_file = Some(PathBuf::from(path)),
+                None => {
+                    eprintln!("--config needs a file\n{}", USAGE);
+                    std::process::exit(2);
+                }
+            },
+            arg if arg.starts_with("--") => {
+                eprintln!("Unknown option {}\n{}", arg, USAGE);
+                std::process::exit(2);
+            }
+            arg => schema_file = Some(PathBuf::from(arg)),
         }
-    });
+    }
+    let config = match (config_file, schema_file) {
+        (Some(_), Some(_)) => {
+            eprintln!("Either a config or a schema file\n{}", USAGE);
+            std::process::exit(2);
+        }
+        (None, Some(schema_file)) => ServerConfig::single(&schema_file),
+        (config_file, None) => {
+            let config_file = config_file.unwrap_or_else(|| PathBuf::from(CONFIG_FILE));
+            ServerConfig::load(&config_file)
+                .map_err(|e| std::io::Error::other(format!("Failed to load the config: {}", e)))?
+        }
+    };
+
+    let mut datasets = Vec::with_capacity(config.dictionaries.len());
+    for config in config.dictionaries {
+        let dict = config.open().map_err(|e| {
+            std::io::Error::other(format!(
+                "Failed to load the schema of {}: {}",
+                config.name, e
+            ))
+        })?;
+        datasets.push(Arc::new(Dataset {
+            config,
+            dict: RwLock::new(Arc::new(dict)),
+            status: RwLock::new(LoadStatus::default()),
+        }));
+    }
+    for dataset in &datasets {
+        spawn_load(Arc::clone(dataset), false);
+    }
+    if watch {
+        let datasets = datasets.clone();
+        std::thread::spawn(move || watch_files(datasets));
+    }
+    let app_state = Arc::new(AppState { datasets });
+    let app_state = web::Data::from(app_state);
 
     HttpServer::new(move || {
+        // missing or malformed query parameters, like limit=abc, and entries that aren't JSON objects
+        let query_config = web::QueryConfig::default().error_handler(|e, _| {
+            let response = error_response(QueryError::BadRequest(e.to_string()));
+            error::InternalError::from_response(e, response).into()
+        });
+        let json_config = web::JsonConfig::default().error_handler(|e, _| {
+            let response = error_response(QueryError::BadRequest(e.to_string()));
+            error::InternalError::from_response(e, response).into()
+        });
         let cors = Cors::default()
             .allowed_origin("http://localhost:5173")
-            .allowed_methods(vec!["GET"])
+            .allowed_methods(vec!["GET", "POST", "PUT", "DELETE"])
             .allow_any_header();
         App::new()
             .wrap(cors)
             .app_data(app_state.clone())
+            .app_data(query_config)
+            .app_data(json_config)
+            .service(list_dictionaries)
+            .service(metrics)
+            .service(federated)
             .service(srca)
             .service(get)
+            .service(add_entry)
+            .service(replace_entry)
+            .service(delete_entry)
+            .service(reload_data)
+            .service(autocomplete)
+            .service(load_status)
     })
     .bind(("127.0.0.1", 8080))?
     .run()
     .await
 }
 
-fn load_data(dict: &CsvDictionary) -> Result<(), Box<dyn Error>> {
-    println!("Loading data...");
-    let now = SystemTime::now();
+// Loads the data in its own thread, the server answers searches meanwhile. The first load fills the served
+// dictionary, so the rows are searchable as soon as they are indexed. A reload reads the schema again, fills
+// a fresh dictionary and swaps it in when it's complete, searches never see a half loaded index.
+// Returns false if a load is already running.
+fn spawn_load(dataset: Arc<Dataset>, reload: bool) -> bool {
+    {
+        let mut status = dataset.status.write().unwrap();
+        if reload && status.state == LoadState::Loading {
+            return false;
+        }
+        *status = LoadStatus {
+            reload,
+            ..LoadStatus::default()
+        };
+    }
+    std::thread::spawn(move || {
+        let dict = if reload {
+            dataset.config.open().map(Arc::new)
+        } else {
+            Ok(dataset.dict())
+        };
+        match dict.and_then(|dict| load_data(&dict, &dataset.status).map(|report| (dict, report))) {
+            Ok((dict, report)) => {
+                if reload {
+                    *dataset.dict.write().unwrap() = dict;
+                }
+                let mut status = dataset.status.write().unwrap();
+                status.state = LoadState::Done;
+                status.malformed_rows = report.malformed_rows;
+                status.malformed = report
+                    .malformed
+                    .into_iter()
+                    .map(|r| MalformedRowJson {
+                        line: r.line,
+                        error: r.error,
+                    })
+                    .collect();
+            }
+            Err(e) => {
+                eprintln!("Failed to load {}: {}", dataset.config.name, e);
+                let mut status = dataset.status.write().unwrap();
+                status.state = LoadState::Failed;
+                status.error = Some(e.to_string());
+            }
+        }
+    });
+    true
+}
 
-    // Sample CSV data (in a real application, this would come from a file)
+// modification times of the schema and of the data file it points to
+fn modified_times(dataset: &Dataset) -> Vec<Option<SystemTime>> {
+    let data_file = dataset.dict().data_path().map(Path::to_path_buf);
+    [Some(dataset.config.schema.clone()), data_file]
+        .into_iter()
+        .map(|path| path.and_then(|path| std::fs::metadata(path).and_then(|m| m.modified()).ok()))
+        .collect()
+}
 
-    let csv_data = read_from_file("BooksDataset.csv")?;
+// Reloads a dictionary when its schema or data file change. A change has to last one more interval,
+// so a file that is still being written isn't loaded half way.
+fn watch_files(datasets: Vec<Arc<Dataset>>) {
+    // (loaded, changed) times of every dataset
+    let mut times: Vec<_> = datasets
+        .iter()
+        .map(|dataset| (modified_times(dataset), None))
+        .collect();
+    loop {
+        std::thread::sleep(WATCH_INTERVAL);
+        for (dataset, (loaded, changed)) in datasets.iter().zip(times.iter_mut()) {
+            let current = modified_times(dataset);
+            if current == *loaded {
+                *changed = None;
+            } else if changed.as_ref() != Some(&current) {
+                *changed = Some(current);
+            } else if spawn_load(Arc::clone(dataset), true) {
+                println!("The data of {} changed, reloading", dataset.config.name);
+                *loaded = current;
+                *changed = None;
+            }
+        }
+    }
+}
 
-    // Configure attributes for different types of searching
+fn load_data(
+    dict: &CsvDictionary,
+    status: &RwLock<LoadStatus>,
+) -> Result<LoadReport, Box<dyn std::error::Error>> {
+    let path = dict.data_path().ok_or("No schema")?;
+    println!("Loading data from {}...", path.display());
+    status.write().unwrap().total_bytes = std::fs::metadata(path)?.len();
 
-    let reader = Cursor::new(csv_data);
-    let count = dict.load_from_csv(reader, true)?;
+    let report = dict.load(|progress| status.write().unwrap().update(progress))?;
+    for row in &report.malformed {
+        eprintln!("Skipped line {}: {}", row.line, row.error);
+    }
     println!(
-        "Loaded {} records from CSV, in {} seconds\n",
-        count,
-        now.elapsed()?.as_secs_f64()
+        "Loaded {} records from {}, skipped {} malformed rows, in {} seconds\n",
+        report.rows,
+        path.display(),
+        report.malformed_rows,
+        report.elapsed.as_secs_f64()
     );
-    Ok(())
+    Ok(report)
 }
@@ -0,0 +1,827 @@
+use crate::CsvDictionary;
+use dictionary_bitmap_trie::constants::SearchConfig;
+use dictionary_bitmap_trie::dictionary::SearchResult;
+use dictionary_bitmap_trie::facets::{FacetCount, FacetQuery};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fmt;
+
+// The queries of the HTTP API, kept out of the handlers so they can be tested without a server.
+// Lists of attributes are comma separated: `attributes=Title,Authors`.
+// Facet filters are repeated, one per value: `filter=Publisher:Penguin&filter=Publisher:Gallimard`.
+
+/// Largest page a search can ask for
+pub const MAX_LIMIT: usize = 100;
+
+/// Deepest result a search can page to, every page searches all the results before it
+pub const MAX_OFFSET: usize = 10_000;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum QueryError {
+    BadRequest(String),
+    NotFound(String),
+}
+
+impl fmt::Display for QueryError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            QueryError::BadRequest(e) | QueryError::NotFound(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl std::error::Error for QueryError {}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct SearchParams {
+    pub term: String,
+    pub limit: Option<usize>, // the configured max_search_results if not set
+    pub offset: Option<usize>,
+    pub cursor: Option<String>, // next_cursor of the previous page, instead of offset
+    pub attributes: Option<String>, // only these attributes are matched
+    pub fields: Option<String>, // the attributes of the returned records, all if not set
+    #[serde(default)]
+    pub include_record: bool, // returns the record of every hit, no need for a /get per hit
+    pub facets: Option<String>, // attributes to count the values of, over all the matches
+    #[serde(skip)]
+    pub filters: Vec<(String, String)>, // (attribute, value), added by with_filters
+}
+
+impl SearchParams {
+    /// Adds the `filter=Attribute:Value` parameters of the query string
+    pub fn with_filters(mut self, query: &[(String, String)]) -> Result<Self, QueryError> {
+        for (key, filter) in query {
+            if key != "filter" {
+                continue;
+            }
+            let Some((attribute, value)) = filter.split_once(':') else {
+                return Err(QueryError::BadRequest(format!(
+                    "Invalid filter '{}', expected attribute:value",
+                    filter
+                )));
+            };
+            self.filters
+                .push((attribute.trim().to_string(), value.to_string()));
+        }
+        Ok(self)
+    }
+
+    fn faceted(&self) -> bool {
+        self.facets.is_some() || !self.filters.is_empty()
+    }
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct GetParams {
+    pub id: String,
+    pub fields: Option<String>,
+}
+
+/// A value of an entry sent to the API, a string or a list of values for the multi-valued attributes
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(untagged)]
+pub enum EntryValue {
+    One(String),
+    Many(Vec<String>),
+}
+
+impl From<EntryValue> for Vec<String> {
+    fn from(value: EntryValue) -> Self {
+        match value {
+            EntryValue::One(value) => vec![value],
+            EntryValue::Many(values) => values,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct SearchHit {
+    pub term: String,
+    pub attribute: String,
+    pub original_entry: String,
+    pub attribute_index: usize,
+    pub value_index: usize,
+    pub position: usize,
+    pub dictionary_index: usize,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub record: Option<HashMap<String, String>>,
+}
+
+impl From<SearchResult> for SearchHit {
+    fn from(sr: SearchResult) -> Self {
+        Self {
+            term: sr.term,
+            attribute: sr.attribute,
+            original_entry: sr.original_entry,
+            attribute_index: sr.attribute_index,
+            value_index: sr.value_index,
+            position: sr.position,
+            dictionary_index: sr.dictionary_index,
+            record: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct FacetValue {
+    pub value: String,
+    pub count: usize,
+}
+
+impl From<FacetCount> for FacetValue {
+    fn from(count: FacetCount) -> Self {
+        Self {
+            value: count.value,
+            count: count.count,
+        }
+    }
+}
+
+// the value counts of every facet attribute, the most common values first
+pub type Facets = BTreeMap<String, Vec<FacetValue>>;
+
+#[derive(Debug, Clone, Serialize)]
+pub struct SearchPage {
+    pub results: Vec<SearchHit>,
+    pub offset: usize,
+    pub next_cursor: Option<String>, // None on the last page
+    // with facets or filters: the matched entries that pass the filters, and the counts of the facets
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub total: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub facets: Option<Facets>,
+}
+
+// hits with the total and facet counts of a faceted search
+type Hits = (Vec<SearchHit>, Option<(usize, Facets)>);
+
+/// A hit of a search over several dictionaries, with the name of its dictionary
+#[derive(Debug, Clone, Serialize)]
+pub struct FederatedHit {
+    pub dictionary: String,
+    #[serde(flatten)]
+    pub hit: SearchHit,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct FederatedPage {
+    pub results: Vec<FederatedHit>,
+    pub offset: usize,
+    pub next_cursor: Option<String>, // None on the last page
+}
+
+/// A query of the autocomplete WebSocket, the search parameters with the id the results are returned with
+#[derive(Debug, Clone, Deserialize)]
+pub struct AutocompleteRequest {
+    #[serde(default)]
+    pub id: u64,
+    #[serde(flatten)]
+    pub params: SearchParams,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(untagged)]
+pub enum AutocompleteResult {
+    Page(SearchPage),
+    Error { error: String },
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct AutocompleteResponse {
+    pub id: u64,
+    #[serde(flatten)]
+    pub result: AutocompleteResult,
+}
+
+impl AutocompleteResponse {
+    pub fn new(id: u64, res: Result<SearchPage, QueryError>) -> Self {
+        let result = match res {
+            Ok(page) => AutocompleteResult::Page(page),
+            Err(e) => AutocompleteResult::Error {
+                error: e.to_string(),
+            },
+        };
+        Self { id, result }
+    }
+}
+
+// The cursor is the offset of the next page. It is only stable while the dictionary doesn't change,
+// like the offset, but clients shouldn't rely on its format.
+fn decode_cursor(cursor: &str) -> Result<usize, QueryError> {
+    cursor
+        .parse()
+        .map_err(|_| QueryError::BadRequest(format!("Invalid cursor '{}'", cursor)))
+}
+
+// limit and offset of the page
+fn page_bounds(params: &SearchParams, default_limit: usize) -> Result<(usize, usize), QueryError> {
+    let limit = params.limit.unwrap_or(default_limit);
+    if limit == 0 || limit > MAX_LIMIT {
+        return Err(QueryError::BadRequest(format!(
+            "The limit must be between 1 and {}",
+            MAX_LIMIT
+        )));
+    }
+    let offset = match (&params.cursor, params.offset) {
+        (Some(_), Some(_)) => {
+            return Err(QueryError::BadRequest(
+                "Use either cursor or offset, not both".to_string(),
+            ))
+        }
+        (Some(cursor), None) => decode_cursor(cursor)?,
+        (None, offset) => offset.unwrap_or(0),
+    };
+    if offset > MAX_OFFSET {
+        return Err(QueryError::BadRequest(format!(
+            "The offset can't be over {}",
+            MAX_OFFSET
+        )));
+    }
+    Ok((limit, offset))
+}
+
+fn parse_id(id: &str) -> Result<usize, QueryError> {
+    id.parse()
+        .map_err(|_| QueryError::BadRequest(format!("Invalid id '{}'", id)))
+}
+
+fn project(
+    record: HashMap<String, String>,
+    fields: Option<&HashSet<String>>,
+) -> HashMap<String, String> {
+    match fields {
+        Some(fields) => record
+            .into_iter()
+            .filter(|(k, _)| fields.contains(k))
+            .collect(),
+        None => record,
+    }
+}
+
+/// Searches the named dictionaries and merges their results. The dictionaries take turns, the first hit of each
+/// of them, then the second ones, so a big dictionary doesn't crowd out the others. The attributes and fields
+/// of the search must be in all the dictionaries. Without a limit, the page has the smallest max_search_results
+/// of the dictionaries
+pub fn federated_search(
+    dictionaries: &[(&str, &CsvDictionary)],
+    params: &SearchParams,
+) -> Result<FederatedPage, QueryError> {
+    let default_limit = dictionaries
+        .iter()
+        .map(|(_, dict)| dict.dictionary.config().max_search_results)
+        .min()
+        .unwrap_or(SearchConfig::default().max_search_results);
+    let (limit, offset) = page_bounds(params, default_limit)?;
+    if params.facets.is_some() {
+        return Err(QueryError::BadRequest(
+            "Facets are counted per dictionary, search a single dictionary for them".to_string(),
+        ));
+    }
+    let mut searches = Vec::with_capacity(dictionaries.len());
+    for (name, dict) in dictionaries {
+        let (hits, _) = dict.search_hits(params, offset + limit + 1)?;
+        let hits = hits.into_iter();
+        let fields = dict.attribute_list(params.fields.as_deref())?;
+        searches.push((*name, *dict, fields, hits));
+    }
+    // (search, hit), the records are only added to the hits of the page
+    let mut merged = Vec::new();
+    while merged.len() <= offset + limit {
+        let before = merged.len();
+        for (i, (_, _, _, hits)) in searches.iter_mut().enumerate() {
+            merged.extend(hits.next().map(|hit| (i, hit)));
+        }
+        if merged.len() == before {
+            break;
+        }
+    }
+    let next_cursor = (merged.len() > offset + limit).then(|| (offset + limit).to_string());
+    let results = merged
+        .into_iter()
+        .skip(offset)
+        .take(limit)
+        .map(|(i, hit)| {
+            let (name, dict, fields, _) = &searches[i];
+            FederatedHit {
+                dictionary: name.to_string(),
+                hit: dict.with_record(hit, params, fields.as_ref()),
+            }
+        })
+        .collect();
+    Ok(FederatedPage {
+        results,
+        offset,
+        next_cursor,
+    })
+}
+
+impl CsvDictionary {
+    // the attributes of a comma separated list, None for no list, unknown attributes are an error
+    fn attribute_list(&self, list: Option<&str>) -> Result<Option<HashSet<String>>, QueryError> {
+        let Some(list) = list else {
+            return Ok(None);
+        };
+        let names = list
+            .split(',')
+            .map(str::trim)
+            .filter(|name| !name.is_empty());
+        self.check_attributes(names).map(Some)
+    }
+
+    fn check_attributes<'a>(
+        &self,
+        names: impl Iterator<Item = &'a str>,
+    ) -> Result<HashSet<String>, QueryError> {
+        let known: HashSet<String> = self
+            .dictionary
+            .attributes()
+            .into_iter()
+            .map(|(name, _)| name)
+            .collect();
+        names
+            .map(|name| {
+                if known.contains(name) {
+                    Ok(name.to_string())
+                } else {
+                    Err(QueryError::BadRequest(format!(
+                        "Unknown attribute '{}'",
+                        name
+                    )))
+                }
+            })
+            .collect()
+    }
+
+    // the first count matches of the search, without their records, and the facets if the search has any
+    fn search_hits(&self, params: &SearchParams, count: usize) -> Result<Hits, QueryError> {
+        let attributes = self.attribute_list(params.attributes.as_deref())?;
+        let matches = |sr: &SearchResult| {
+            attributes
+                .as_ref()
+                .is_none_or(|attributes| attributes.contains(&sr.attribute))
+        };
+        if !params.faceted() {
+            let hits = self
+                .dictionary
+                .search_filtered(&params.term, count, matches)
+                .into_iter()
+                .map(SearchHit::from)
+                .collect();
+            return Ok((hits, None));
+        }
+        let facets = match params.facets.as_deref() {
+            Some(list) => list
+                .split(',')
+                .map(str::trim)
+                .filter(|name| !name.is_empty())
+                .map(String::from)
+                .collect(),
+            None => Vec::new(),
+        };
+        self.check_attributes(facets.iter().map(String::as_str))?;
+        self.check_attributes(params.filters.iter().map(|(attr, _)| attr.as_str()))?;
+        let query = FacetQuery {
+            facets,
+            filters: params.filters.clone(),
+        };
+        let res = self
+            .dictionary
+            .search_faceted(&params.term, count, &query, matches);
+        let facets = res
+            .facets
+            .into_iter()
+            .map(|(attr, counts)| (attr, counts.into_iter().map(FacetValue::from).collect()))
+            .collect();
+        let hits = res.results.into_iter().map(SearchHit::from).collect();
+        Ok((hits, Some((res.total, facets))))
+    }
+
+    // adds the record to the hit, if the search asked for them
+    fn with_record(
+        &self,
+        mut hit: SearchHit,
+        params: &SearchParams,
+        fields: Option<&HashSet<String>>,
+    ) -> SearchHit {
+        if params.include_record {
+            hit.record = Some(project(self.dictionary.get(hit.dictionary_index), fields));
+        }
+        hit
+    }
+
+    /// One page of the search results, with the cursor of the next page
+    pub fn search_page(&self, params: &SearchParams) -> Result<SearchPage, QueryError> {
+        let (limit, offset) = page_bounds(params, self.dictionary.config().max_search_results)?;
+        // one result over the page tells if there is a next one
+        let (hits, faceted) = self.search_hits(params, offset + limit + 1)?;
+        let (total, facets) = faceted.unzip();
+        let fields = self.attribute_list(params.fields.as_deref())?;
+        let next_cursor = (hits.len() > offset + limit).then(|| (offset + limit).to_string());
+        let results = hits
+            .into_iter()
+            .skip(offset)
+            .take(limit)
+            .map(|hit| self.with_record(hit, params, fields.as_ref()))
+            .collect();
+        Ok(SearchPage {
+            results,
+            offset,
+            next_cursor,
+            total,
+            facets,
+        })
+    }
+
+    /// The record of the id, NotFound for the deleted and the never added ones
+    pub fn get_record(&self, params: &GetParams) -> Result<HashMap<String, String>, QueryError> {
+        let index = parse_id(&params.id)?;
+        let fields = self.attribute_list(params.fields.as_deref())?;
+        if !self.dictionary.contains(index) {
+            return Err(QueryError::NotFound(format!("No entry with id {}", index)));
+        }
+        Ok(project(self.dictionary.get(index), fields.as_ref()))
+    }
+
+    // the values of an entry sent to the API, all its attributes must be in the dictionary
+    fn entry_values(
+        &self,
+        data: HashMap<String, EntryValue>,
+    ) -> Result<HashMap<String, Vec<String>>, QueryError> {
+        self.check_attributes(data.keys().map(String::as_str))?;
+        let values: HashMap<String, Vec<String>> = data
+            .into_iter()
+            .map(|(k, v)| (k, Vec::from(v)))
+            .filter(|(_, v)| !v.is_empty())
+            .collect();
+        if values.is_empty() {
+            return Err(QueryError::BadRequest(
+                "The entry has no values".to_string(),
+            ));
+        }
+        Ok(values)
+    }
+
+    /// Adds the entry, returns its id
+    pub fn add_entry(&self, data: HashMap<String, EntryValue>) -> Result<usize, QueryError> {
+        let values = self.entry_values(data)?;
+        self.dictionary
+            .add_multi_valued_entry(values)
+            .ok_or_else(|| QueryError::BadRequest("The entry has no values".to_string()))
+    }
+
+    /// Replaces the entry of the id, the entry keeps its id
+    pub fn replace_entry(
+        &self,
+        id: &str,
+        data: HashMap<String, EntryValue>,
+    ) -> Result<usize, QueryError> {
+        let index = parse_id(id)?;
+        let values = self.entry_values(data)?;
+        if self.dictionary.replace_multi_valued_entry(index, values) {
+            Ok(index)
+        } else if !self.dictionary.contains(index) {
+            Err(QueryError::NotFound(format!("No entry with id {}", index)))
+        } else {
+            Err(QueryError::BadRequest(
+                "The entry has no values".to_string(),
+            ))
+        }
+    }
+
+    pub fn delete_entry(&self, id: &str) -> Result<(), QueryError> {
+        let index = parse_id(id)?;
+        // no remap, the dictionaries are created without the automatic compaction
+        self.dictionary
+            .delete(index)
+            .map(|_| ())
+            .map_err(|_| QueryError::NotFound(format!("No entry with id {}", index)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use dictionary_bitmap_trie::dictionary::AttributeSearch;
+    use std::io::Cursor;
+
+    fn books() -> CsvDictionary {
+        let dict = CsvDictionary::new(vec![
+            ("title".to_string(), AttributeSearch::Multiple),
+            ("publisher".to_string(), AttributeSearch::Exact),
+            ("price".to_string(), AttributeSearch::None),
+        ]);
+        let data =
+            "title,publisher,price\nPenguin Island,Gallimard,10\nThe Penguin Lessons,Penguin,12\n\
+                    Penguins Stopped Play,Penguin,9\nEmperor Penguin,Bloomsbury,15\n";
+        dict.load_from_csv(Cursor::new(data), true, |_| {}).unwrap();
+        dict
+    }
+
+    fn search(term: &str) -> SearchParams {
+        SearchParams {
+            term: term.to_string(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_search_facets() {
+        let dict = books();
+        let params = SearchParams {
+            facets: Some("publisher".to_string()),
+            ..search("Peng")
+        };
+        let page = dict.search_page(&params).unwrap();
+        // an entry matching through several words is a single hit, as in the total
+        assert_eq!(page.results.len(), 4);
+        assert_eq!(page.total, Some(4));
+        let count = |value: &str, count| FacetValue {
+            value: value.to_string(),
+            count,
+        };
+        assert_eq!(
+            page.facets.unwrap()["publisher"],
+            vec![
+                count("Penguin", 2),
+                count("Bloomsbury", 1),
+                count("Gallimard", 1)
+            ]
+        );
+
+        let query = [
+            ("term".to_string(), "Peng".to_string()),
+            ("filter".to_string(), "publisher:Penguin".to_string()),
+        ];
+        let params = SearchParams {
+            attributes: Some("title".to_string()),
+            ..search("Peng")
+        }
+        .with_filters(&query)
+        .unwrap();
+        assert_eq!(
+            params.filters,
+            vec![("publisher".to_string(), "Penguin".to_string())]
+        );
+        let page = dict.search_page(&params).unwrap();
+        assert_eq!(page.total, Some(2));
+        assert!(page.facets.unwrap().is_empty());
+        let mut ids: Vec<_> = page
+            .results
+            .iter()
+            .map(|hit| hit.dictionary_index)
+            .collect();
+        ids.sort();
+        assert_eq!(ids, vec![1, 2]);
+        // plain searches have neither
+        let page = dict.search_page(&search("Peng")).unwrap();
+        assert!(page.total.is_none() && page.facets.is_none());
+
+        let invalid = [("filter".to_string(), "publisher".to_string())];
+        assert!(search("Peng").with_filters(&invalid).is_err());
+        let unknown = SearchParams {
+            facets: Some("year".to_string()),
+            ..search("Peng")
+        };
+        assert!(matches!(
+            dict.search_page(&unknown),
+            Err(QueryError::BadRequest(_))
+        ));
+        let unknown = search("Peng")
+            .with_filters(&[("filter".to_string(), "year:2001".to_string())])
+            .unwrap();
+        assert!(dict.search_page(&unknown).is_err());
+        let facets = SearchParams {
+            facets: Some("publisher".to_string()),
+            ..search("Peng")
+        };
+        assert!(federated_search(&[("books", &dict)], &facets).is_err());
+    }
+
+    #[test]
+    fn test_search_pages() {
+        let dict = books();
+        let all = dict.search_page(&search("Peng")).unwrap();
+        assert_eq!(all.results.len(), 6);
+        assert!(all.next_cursor.is_none());
+
+        let mut params = SearchParams {
+            limit: Some(4),
+            ..search("Peng")
+        };
+        let first = dict.search_page(&params).unwrap();
+        assert_eq!(first.results.len(), 4);
+        assert_eq!(first.next_cursor.as_deref(), Some("4"));
+        params.cursor = first.next_cursor;
+        let second = dict.search_page(&params).unwrap();
+        assert_eq!(second.offset, 4);
+        assert_eq!(second.results.len(), 2);
+        assert!(second.next_cursor.is_none());
+        let paged: Vec<(usize, String)> = first
+            .results
+            .iter()
+            .chain(&second.results)
+            .map(|hit| (hit.dictionary_index, hit.attribute.clone()))
+            .collect();
+        let expected: Vec<(usize, String)> = all
+            .results
+            .iter()
+            .map(|hit| (hit.dictionary_index, hit.attribute.clone()))
+            .collect();
+        assert_eq!(paged, expected);
+        assert!(second.results.iter().all(|hit| hit.record.is_none()));
+    }
+
+    #[test]
+    fn test_search_attributes_and_fields() {
+        let dict = books();
+        let page = dict
+            .search_page(&SearchParams {
+                attributes: Some("publisher".to_string()),
+                fields: Some("title, price".to_string()),
+                include_record: true,
+                ..search("Peng")
+            })
+            .unwrap();
+        assert_eq!(page.results.len(), 2);
+        for hit in &page.results {
+            assert_eq!(hit.attribute, "publisher");
+            let record = hit.record.as_ref().unwrap();
+            assert_eq!(record.len(), 2);
+            assert!(record.contains_key("title") && record.contains_key("price"));
+        }
+
+        let bad = |params: SearchParams| dict.search_page(&params).unwrap_err();
+        assert_eq!(
+            bad(SearchParams {
+                attributes: Some("author".to_string()),
+                ..search("Peng")
+            }),
+            QueryError::BadRequest("Unknown attribute 'author'".to_string())
+        );
+        for params in [
+            SearchParams {
+                limit: Some(0),
+                ..search("Peng")
+            },
+            SearchParams {
+                limit: Some(MAX_LIMIT + 1),
+                ..search("Peng")
+            },
+            SearchParams {
+                cursor: Some("x".to_string()),
+                ..search("Peng")
+            },
+            SearchParams {
+                cursor: Some("2".to_string()),
+                offset: Some(2),
+                ..search("Peng")
+            },
+            SearchParams {
+                offset: Some(MAX_OFFSET + 1),
+                ..search("Peng")
+            },
+        ] {
+            assert!(matches!(bad(params), QueryError::BadRequest(_)));
+        }
+    }
+
+    #[test]
+    fn test_get_record() {
+        let dict = books();
+        let get = |id: &str, fields: Option<&str>| {
+            dict.get_record(&GetParams {
+                id: id.to_string(),
+                fields: fields.map(str::to_string),
+            })
+        };
+        assert_eq!(get("1", None).unwrap()["publisher"], "Penguin");
+        assert_eq!(
+            get("1", Some("price")).unwrap(),
+            HashMap::from([("price".to_string(), "12".to_string())])
+        );
+        assert!(matches!(get("x", None), Err(QueryError::BadRequest(_))));
+        assert!(matches!(
+            get("1", Some("isbn")),
+            Err(QueryError::BadRequest(_))
+        ));
+        assert!(matches!(get("4", None), Err(QueryError::NotFound(_))));
+    }
+
+    #[test]
+    fn test_write_entries() {
+        let dict = books();
+        let entry = |json: &str| serde_json::from_str::<HashMap<String, EntryValue>>(json).unwrap();
+        let id = dict
+            .add_entry(entry(
+                r#"{"title": "Penguin Modern", "publisher": ["Penguin", "Pelican"]}"#,
+            ))
+            .unwrap();
+        assert_eq!(id, 4);
+        assert_eq!(dict.get("4")["publisher"], "Penguin; Pelican");
+        assert!(dict
+            .search("Pelic")
+            .iter()
+            .any(|sr| sr.dictionary_index == 4));
+
+        assert_eq!(
+            dict.replace_entry("1", entry(r#"{"title": "Lessons"}"#)),
+            Ok(1)
+        );
+        assert!(!dict.get("1").contains_key("publisher"));
+        assert!(dict.search("Penguin Les").is_empty());
+        assert_eq!(dict.search("Lessons")[0].dictionary_index, 1);
+
+        assert_eq!(dict.delete_entry("1"), Ok(()));
+        assert!(dict.search("Lessons").is_empty());
+        assert!(matches!(
+            dict.delete_entry("1"),
+            Err(QueryError::NotFound(_))
+        ));
+        assert!(matches!(
+            dict.replace_entry("1", entry(r#"{"title": "Lessons"}"#)),
+            Err(QueryError::NotFound(_))
+        ));
+        assert!(matches!(
+            dict.add_entry(entry(r#"{"isbn": "123"}"#)),
+            Err(QueryError::BadRequest(_))
+        ));
+        assert!(matches!(
+            dict.add_entry(entry(r#"{"title": []}"#)),
+            Err(QueryError::BadRequest(_))
+        ));
+    }
+
+    #[test]
+    fn test_autocomplete_messages() {
+        let dict = books();
+        let request: AutocompleteRequest = serde_json::from_str(
+            r#"{"id": 7, "term": "Pengu", "limit": 1, "include_record": true}"#,
+        )
+        .unwrap();
+        assert_eq!(request.params.limit, Some(1));
+        let response = AutocompleteResponse::new(request.id, dict.search_page(&request.params));
+        let json = serde_json::to_value(&response).unwrap();
+        assert_eq!(json["id"], 7);
+        assert_eq!(json["results"].as_array().unwrap().len(), 1);
+        assert_eq!(json["next_cursor"], "1");
+
+        let response = AutocompleteResponse::new(
+            8,
+            dict.search_page(&SearchParams {
+                limit: Some(0),
+                ..search("Pengu")
+            }),
+        );
+        let json = serde_json::to_value(&response).unwrap();
+        assert_eq!(json["id"], 8);
+        assert!(json["error"].is_string());
+        assert!(serde_json::from_str::<AutocompleteRequest>(r#"{"id": 1}"#).is_err());
+    }
+
+    #[test]
+    fn test_federated_search() {
+        let books = books();
+        let birds = CsvDictionary::new(vec![("name".to_string(), AttributeSearch::Exact)]);
+        birds
+            .load_from_csv(Cursor::new("name\nPenguin\nPelican\n"), true, |_| {})
+            .unwrap();
+        let dictionaries = [("books", &books), ("birds", &birds)];
+
+        let page = federated_search(&dictionaries, &search("Pen")).unwrap();
+        let names: Vec<&str> = page.results.iter().map(|r| r.dictionary.as_str()).collect();
+        // the dictionaries take turns until one of them runs out
+        assert_eq!(
+            names,
+            vec!["books", "birds", "books", "books", "books", "books", "books"]
+        );
+        assert_eq!(page.results[1].hit.original_entry, "Penguin");
+        assert!(page.next_cursor.is_none());
+
+        let params = SearchParams {
+            limit: Some(2),
+            offset: Some(1),
+            include_record: true,
+            ..search("Pen")
+        };
+        let page = federated_search(&dictionaries, &params).unwrap();
+        assert_eq!(page.results.len(), 2);
+        assert_eq!(page.results[0].dictionary, "birds");
+        assert_eq!(
+            page.results[0].hit.record.as_ref().unwrap()["name"],
+            "Penguin"
+        );
+        assert_eq!(page.next_cursor.as_deref(), Some("3"));
+
+        let json = serde_json::to_value(&page.results[0]).unwrap();
+        assert_eq!(json["dictionary"], "birds");
+        assert_eq!(json["original_entry"], "Penguin");
+
+        // every dictionary must have the attributes of the search
+        let params = SearchParams {
+            attributes: Some("title".to_string()),
+            ..search("Pen")
+        };
+        assert!(federated_search(&dictionaries, &params).is_err());
+        assert!(federated_search(&dictionaries[..1], &params).is_ok());
+    }
+}
@@ -0,0 +1,317 @@
+use csv::{ByteRecord, ReaderBuilder, StringRecord};
+use dictionary_bitmap_trie::dictionary::AttributeSearch;
+use serde::Deserialize;
+use std::error::Error;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+// A schema file describes the CSV file and how its columns become dictionary attributes:
+// {
+//   "path": "BooksDataset.csv",
+//   "delimiter": ",",
+//   "columns": [
+//     {"column": "Title", "search": "multiple"},
+//     {"column": "Authors", "search": "multiple", "split": ";", "trim": true},
+//     {"column": 6, "name": "price"}
+//   ]
+// }
+// The columns not listed are left out of the dictionary.
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SearchMode {
+    Exact,
+    Multiple,
+    #[default]
+    None, // stored in the dictionary, not searchable
+}
+
+impl From<SearchMode> for AttributeSearch {
+    fn from(mode: SearchMode) -> Self {
+        match mode {
+            SearchMode::Exact => AttributeSearch::Exact,
+            SearchMode::Multiple => AttributeSearch::Multiple,
+            SearchMode::None => AttributeSearch::None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Deserialize)]
+pub enum FileEncoding {
+    #[default]
+    #[serde(rename = "utf-8", alias = "utf8")]
+    Utf8, // rows that aren't valid UTF-8 are reported as malformed
+    #[serde(rename = "latin1", alias = "iso-8859-1")]
+    Latin1,
+}
+
+impl FileEncoding {
+    pub fn decode(&self, bytes: &[u8]) -> Result<String, String> {
+        match self {
+            FileEncoding::Utf8 => String::from_utf8(bytes.to_vec()).map_err(|e| e.to_string()),
+            // every latin1 byte is the unicode code point of the same value
+            FileEncoding::Latin1 => Ok(bytes.iter().map(|b| *b as char).collect()),
+        }
+    }
+}
+
+/// A column by its name in the header row, or by its position, starting at 0
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(untagged)]
+pub enum ColumnRef {
+    Index(usize),
+    Name(String),
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ColumnSchema {
+    pub column: ColumnRef,
+    #[serde(default)]
+    pub name: Option<String>, // the attribute name, the column name if not set
+    #[serde(default)]
+    pub search: SearchMode,
+    #[serde(default)]
+    pub trim: bool, // trims the whitespace around the value, and around each value of a split cell
+    #[serde(default)]
+    pub split: Option<String>, // separator of a multi-valued cell, each value is indexed on its own
+}
+
+impl ColumnSchema {
+    pub fn attribute_name(&self) -> String {
+        match (&self.name, &self.column) {
+            (Some(name), _) => name.clone(),
+            (None, ColumnRef::Name(name)) => name.clone(),
+            (None, ColumnRef::Index(i)) => format!("column_{}", i),
+        }
+    }
+
+    /// The values of a cell after the transforms, empty if there is nothing to store
+    pub fn transform(&self, value: &str) -> Vec<String> {
+        let values: Vec<&str> = match &self.split {
+            Some(separator) => value.split(separator.as_str()).collect(),
+            None => vec![value],
+        };
+        values
+            .into_iter()
+            .map(|v| if self.trim { v.trim() } else { v })
+            .filter(|v| !v.is_empty())
+            .map(str::to_string)
+            .collect()
+    }
+}
+
+fn default_delimiter() -> char {
+    ','
+}
+
+fn default_quote() -> char {
+    '"'
+}
+
+fn default_true() -> bool {
+    true
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct CsvSchema {
+    pub path: PathBuf, // relative to the schema file
+    #[serde(default = "default_delimiter")]
+    pub delimiter: char,
+    #[serde(default = "default_true")]
+    pub quoting: bool, // false reads the quote characters as part of the values
+    #[serde(default = "default_quote")]
+    pub quote: char,
+    #[serde(default = "default_true")]
+    pub has_headers: bool, // without headers, the columns can only be referenced by index
+    #[serde(default)]
+    pub encoding: FileEncoding,
+    pub columns: Vec<ColumnSchema>,
+}
+
+fn ascii_byte(c: char, what: &str) -> Result<u8, String> {
+    if c.is_ascii() {
+        Ok(c as u8)
+    } else {
+        Err(format!("The {} '{}' is not an ASCII character", what, c))
+    }
+}
+
+/// A column of the schema, matched to its position in the file
+#[derive(Debug, Clone)]
+pub struct ResolvedColumn {
+    pub index: usize,
+    pub attribute: String,
+    pub schema: ColumnSchema,
+}
+
+impl CsvSchema {
+    pub fn load(path: &Path) -> Result<Self, Box<dyn Error>> {
+        let with_path = |e: &dyn std::fmt::Display| format!("{}: {}", path.display(), e);
+        let data = fs::read(path).map_err(|e| with_path(&e))?;
+        let mut schema: CsvSchema = serde_json::from_slice(&data).map_err(|e| with_path(&e))?;
+        if schema.path.is_relative() {
+            if let Some(dir) = path.parent() {
+                schema.path = dir.join(&schema.path);
+            }
+        }
+        schema.validate()?;
+        Ok(schema)
+    }
+
+    fn validate(&self) -> Result<(), String> {
+        ascii_byte(self.delimiter, "delimiter")?;
+        ascii_byte(self.quote, "quote")?;
+        let mut names = std::collections::HashSet::new();
+        for column in &self.columns {
+            if !self.has_headers && matches!(column.column, ColumnRef::Name(_)) {
+                return Err(format!(
+                    "Column {:?} is referenced by name, but the file has no headers",
+                    column.column
+                ));
+            }
+            if !names.insert(column.attribute_name()) {
+                return Err(format!(
+                    "Attribute '{}' is defined twice",
+                    column.attribute_name()
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    /// The dictionary attributes, in the order of the columns
+    pub fn attributes(&self) -> Vec<(String, AttributeSearch)> {
+        self.columns
+            .iter()
+            .map(|c| (c.attribute_name(), c.search.into()))
+            .collect()
+    }
+
+    pub fn reader_builder(&self) -> ReaderBuilder {
+        let mut builder = ReaderBuilder::new();
+        builder
+            .has_headers(self.has_headers)
+            .delimiter(self.delimiter as u8)
+            .quoting(self.quoting)
+            .quote(self.quote as u8);
+        builder
+    }
+
+    /// Matches the columns to their positions in the header row
+    pub fn resolve(&self, headers: &ByteRecord) -> Result<Vec<ResolvedColumn>, String> {
+        let headers: Vec<String> = headers
+            .iter()
+            .map(|h| self.encoding.decode(h))
+            .collect::<Result<_, _>>()?;
+        self.columns
+            .iter()
+            .map(|column| {
+                let index = match &column.column {
+                    ColumnRef::Index(i) => *i,
+                    ColumnRef::Name(name) => headers
+                        .iter()
+                        .position(|h| h.trim() == name)
+                        .ok_or_else(|| format!("Column '{}' not found in the header row", name))?,
+                };
+                Ok(ResolvedColumn {
+                    index,
+                    attribute: column.attribute_name(),
+                    schema: column.clone(),
+                })
+            })
+            .collect()
+    }
+}
+
+/// Columns of a file without a schema, every column is indexed as it is, named after its header
+pub fn default_columns(headers: &StringRecord) -> Vec<ResolvedColumn> {
+    headers
+        .iter()
+        .enumerate()
+        .map(|(index, header)| ResolvedColumn {
+            index,
+            attribute: header.to_string(),
+            schema: ColumnSchema {
+                column: ColumnRef::Index(index),
+                name: Some(header.to_string()),
+                search: SearchMode::None, // the dictionary decides
+                trim: false,
+                split: None,
+            },
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_schema() {
+        let schema: CsvSchema = serde_json::from_str(
+            r#"{
+                "path": "books.csv",
+                "delimiter": ";",
+                "encoding": "latin1",
+                "columns": [
+                    {"column": "Title", "search": "multiple"},
+                    {"column": "Authors", "name": "author", "search": "exact", "split": ",", "trim": true},
+                    {"column": 4}
+                ]
+            }"#,
+        )
+        .unwrap();
+        schema.validate().unwrap();
+        assert_eq!(schema.delimiter, ';');
+        assert!(schema.quoting);
+        assert_eq!(schema.encoding, FileEncoding::Latin1);
+        assert_eq!(
+            schema.attributes(),
+            vec![
+                ("Title".to_string(), AttributeSearch::Multiple),
+                ("author".to_string(), AttributeSearch::Exact),
+                ("column_4".to_string(), AttributeSearch::None),
+            ]
+        );
+        assert_eq!(
+            schema.columns[1].transform(" Author A, Author B ,, "),
+            vec!["Author A", "Author B"]
+        );
+        assert_eq!(schema.columns[0].transform(" A "), vec![" A "]);
+
+        let headers = ByteRecord::from(vec!["Title", "Year", "Authors"]);
+        let resolved = schema.resolve(&headers).unwrap();
+        let indices: Vec<usize> = resolved.iter().map(|c| c.index).collect();
+        assert_eq!(indices, vec![0, 2, 4]);
+        let headers = ByteRecord::from(vec!["Title"]);
+        assert!(schema.resolve(&headers).is_err());
+    }
+
+    #[test]
+    fn test_invalid_schema() {
+        let parse = |json: &str| serde_json::from_str::<CsvSchema>(json);
+        assert!(
+            parse(r#"{"path": "a.csv", "columns": [{"column": "a", "serach": "exact"}]}"#).is_err()
+        );
+        assert!(parse(r#"{"path": "a.csv", "encoding": "ebcdic", "columns": []}"#).is_err());
+
+        let schema = parse(r#"{"path": "a.csv", "delimiter": "§", "columns": []}"#).unwrap();
+        assert!(schema.validate().is_err());
+        let schema =
+            parse(r#"{"path": "a.csv", "has_headers": false, "columns": [{"column": "a"}]}"#)
+                .unwrap();
+        assert!(schema.validate().is_err());
+        let schema =
+            parse(r#"{"path": "a.csv", "columns": [{"column": 0, "name": "a"}, {"column": "a"}]}"#)
+                .unwrap();
+        assert!(schema.validate().is_err());
+    }
+
+    #[test]
+    fn test_latin1() {
+        assert_eq!(FileEncoding::Latin1.decode(b"caf\xe9").unwrap(), "café");
+        assert!(FileEncoding::Utf8.decode(b"caf\xe9").is_err());
+    }
+}
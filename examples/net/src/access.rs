@@ -1,7 +1,10 @@
-use dictionary_bitmap_trie::dictionary::{AttributeSearch, Dictionary};
-use std::collections::HashMap;
-use std::sync::{Arc, RwLock};
+use crate::persistence;
 use dictionary_bitmap_trie::constants::SearchConfig;
+use dictionary_bitmap_trie::dictionary::{AttributeSearch, Dictionary};
+use dictionary_bitmap_trie::metrics::prometheus_text;
+use std::collections::{HashMap, HashSet};
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex, RwLock};
 
 #[derive(Debug)]
 pub struct OwnedSearchResult {
@@ -9,76 +12,330 @@ pub struct OwnedSearchResult {
     pub attribute: String,
     pub original_entry: String,
     pub attribute_index: usize,
+    pub value_index: usize,
     pub position: usize,
+    pub dictionary_index: usize,
+}
+
+pub fn search_type_name(search: &AttributeSearch) -> &'static str {
+    match search {
+        AttributeSearch::Exact => "exact",
+        AttributeSearch::Multiple => "multiple",
+        AttributeSearch::None => "none",
+    }
+}
+
+pub fn parse_search_type(name: &str) -> Option<AttributeSearch> {
+    match name.to_lowercase().as_str() {
+        "exact" => Some(AttributeSearch::Exact),
+        "multiple" => Some(AttributeSearch::Multiple),
+        "none" => Some(AttributeSearch::None),
+        _ => None,
+    }
 }
 
+// ceiling of the results of one search, whatever the dictionary or the request asks for
+pub const DEFAULT_MAX_SEARCH_RESULTS: usize = 1000;
+
+// The map is only write locked to create or drop a dictionary. Everything else runs on a
+// dictionary taken out of the map, under the dictionary's own locks, so the operations on
+// different dictionaries don't wait for each other, and searches run during ingestion.
 pub struct DictionaryMap {
-    map: HashMap<String, Arc<RwLock<Dictionary>>>,
+    map: RwLock<HashMap<String, Arc<Dictionary>>>,
+    max_search_results: usize,
+    data_dir: Option<PathBuf>,
+    dirty: Mutex<HashSet<String>>, // ids of the dictionaries changed or dropped since they were last saved
+    saving: Mutex<()>,             // one save at a time, the periodic snapshot or the Save command
+}
+
+impl Default for DictionaryMap {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl DictionaryMap {
     pub fn new() -> Self {
+        Self::with_max_search_results(DEFAULT_MAX_SEARCH_RESULTS)
+    }
+
+    pub fn with_max_search_results(max_search_results: usize) -> Self {
         Self {
-            map: HashMap::new(),
+            map: RwLock::new(HashMap::new()),
+            max_search_results,
+            data_dir: None,
+            dirty: Mutex::new(HashSet::new()),
+            saving: Mutex::new(()),
+        }
+    }
+
+    // the directory the dictionaries are saved to and restored from
+    pub fn with_data_dir(mut self, data_dir: PathBuf) -> Self {
+        self.data_dir = Some(data_dir);
+        self
+    }
+
+    fn mark_dirty(&self, dictionary_id: &str) {
+        if self.data_dir.is_some() {
+            self.dirty.lock().unwrap().insert(dictionary_id.to_string());
+        }
+    }
+
+    // Loads the dictionaries of the data directory, returns how many were restored
+    pub fn restore(&self) -> Result<usize, String> {
+        let Some(data_dir) = &self.data_dir else {
+            return Ok(0);
+        };
+        let check = |dictionary_id: &str, config: &mut SearchConfig| {
+            // the server limit may have been lowered since the dictionary was saved
+            if config.max_search_results > self.max_search_results {
+                eprintln!(
+                    "Dictionary '{}': max_search_results {} is over the server limit, lowered to {}",
+                    dictionary_id, config.max_search_results, self.max_search_results
+                );
+                config.max_search_results = self.max_search_results;
+            }
+            self.validate_config(config)
+        };
+        let dictionaries = persistence::load_dictionaries(data_dir, check).map_err(|e| {
+            format!(
+                "Failed to restore dictionaries from {}: {}",
+                data_dir.display(),
+                e
+            )
+        })?;
+        let count = dictionaries.len();
+        let mut map = self.map.write().unwrap();
+        for (dictionary_id, dictionary) in dictionaries {
+            map.insert(dictionary_id, Arc::new(dictionary));
+        }
+        Ok(count)
+    }
+
+    // Writes the dictionaries changed since the last save, and removes the files of the dropped ones.
+    // Returns the ids of the dictionaries saved or removed.
+    pub fn save(&self) -> Result<Vec<String>, String> {
+        let Some(data_dir) = &self.data_dir else {
+            return Err("The server has no data directory".to_string());
+        };
+        let _saving = self.saving.lock().unwrap();
+        let mut dirty: Vec<String> = self.dirty.lock().unwrap().drain().collect();
+        dirty.sort();
+        for (i, dictionary_id) in dirty.iter().enumerate() {
+            let dict = self.map.read().unwrap().get(dictionary_id).cloned();
+            let res = match dict {
+                Some(dict) => persistence::save_dictionary(data_dir, dictionary_id, &dict),
+                None => persistence::remove_dictionary(data_dir, dictionary_id),
+            };
+            if let Err(e) = res {
+                // the rest is retried with the next save
+                self.dirty
+                    .lock()
+                    .unwrap()
+                    .extend(dirty[i..].iter().cloned());
+                return Err(format!(
+                    "Failed to save dictionary '{}': {}",
+                    dictionary_id, e
+                ));
+            }
+        }
+        Ok(dirty)
+    }
+
+    fn validate_config(&self, config: &SearchConfig) -> Result<(), String> {
+        if config.max_search_results == 0 || config.max_search_results > self.max_search_results {
+            return Err(format!(
+                "max_search_results must be between 1 and {}",
+                self.max_search_results
+            ));
+        }
+        if config.min_term_length == 0 {
+            return Err("min_term_length must be at least 1".to_string());
+        }
+        // otherwise a node would be demoted right after it was promoted
+        if config.max_direct_entries <= config.min_bitmap_entries {
+            return Err(format!(
+                "max_direct_entries must be greater than {}",
+                config.min_bitmap_entries
+            ));
+        }
+        if config.default_multiple_search_length == 0 {
+            return Err("default_multiple_search_length must be at least 1".to_string());
+        }
+        // it would renumber the entries behind the ids the clients hold
+        if config.compact_threshold.is_some() {
+            return Err(
+                "compact_threshold is not supported, the entries keep their ids".to_string(),
+            );
         }
+        Ok(())
+    }
+
+    // the map lock is only held to find the dictionary
+    fn dictionary(&self, dictionary_id: &str) -> Result<Arc<Dictionary>, String> {
+        self.map
+            .read()
+            .unwrap()
+            .get(dictionary_id)
+            .cloned()
+            .ok_or_else(|| format!("Dictionary with id '{}' not found", dictionary_id))
     }
 
     pub fn create_dictionary(
-        &mut self,
+        &self,
         dictionary_id: &str,
         attributes: Vec<(String, AttributeSearch)>,
+        config: SearchConfig,
     ) -> Result<(), String> {
-        if self.map.contains_key(dictionary_id) {
+        self.validate_config(&config)?;
+        let mut map = self.map.write().unwrap();
+        if map.contains_key(dictionary_id) {
             return Err(format!(
                 "Dictionary with id '{}' already exists",
                 dictionary_id
             ));
         }
 
-        let dictionary = Dictionary::new(attributes,SearchConfig::default());
-        self.map
-            .insert(dictionary_id.to_string(), Arc::new(RwLock::new(dictionary)));
+        let dictionary = Dictionary::new(attributes, config);
+        map.insert(dictionary_id.to_string(), Arc::new(dictionary));
+        self.mark_dirty(dictionary_id);
         Ok(())
     }
 
-    pub fn add_entry(
-        &mut self,
-        dictionary_id: &str,
-        entry_data: HashMap<String, String>,
-    ) -> Result<(), String> {
-        match self.map.get(dictionary_id) {
-            Some(dict_arc) => {
-                let mut dict = dict_arc.write().unwrap();
-                dict.add_dictionary_entry(entry_data);
+    // operations still running on the dictionary finish on their own reference to it
+    pub fn drop_dictionary(&self, dictionary_id: &str) -> Result<(), String> {
+        match self.map.write().unwrap().remove(dictionary_id) {
+            Some(_) => {
+                self.mark_dirty(dictionary_id);
                 Ok(())
             }
             None => Err(format!("Dictionary with id '{}' not found", dictionary_id)),
         }
     }
 
+    pub fn list_dictionaries(&self) -> Vec<String> {
+        let mut ids: Vec<String> = self.map.read().unwrap().keys().cloned().collect();
+        ids.sort();
+        ids
+    }
+
+    // metrics of all the dictionaries, in the order of their ids
+    pub fn metrics(&self) -> String {
+        let mut dictionaries: Vec<_> = self
+            .map
+            .read()
+            .unwrap()
+            .iter()
+            .map(|(id, dict)| (id.clone(), Arc::clone(dict)))
+            .collect();
+        dictionaries.sort_by(|a, b| a.0.cmp(&b.0));
+        let metrics: Vec<_> = dictionaries
+            .iter()
+            .map(|(id, dict)| (id.as_str(), dict.metrics()))
+            .collect();
+        let metrics: Vec<_> = metrics.iter().map(|(id, m)| (*id, m)).collect();
+        prometheus_text(&metrics)
+    }
+
+    pub fn describe_dictionary(
+        &self,
+        dictionary_id: &str,
+    ) -> Result<Vec<(String, AttributeSearch)>, String> {
+        Ok(self.dictionary(dictionary_id)?.attributes())
+    }
+
+    pub fn add_entry(
+        &self,
+        dictionary_id: &str,
+        entry_data: HashMap<String, String>,
+    ) -> Result<usize, String> {
+        let dict = self.dictionary(dictionary_id)?;
+        let res = dict.add_dictionary_entry(entry_data).ok_or_else(|| {
+            format!(
+                "Entry has none of the attributes of dictionary '{}'",
+                dictionary_id
+            )
+        });
+        self.mark_dirty(dictionary_id);
+        res
+    }
+
+    // The entries are indexed together by the bulk add, the searches only wait for the merge of the batch.
+    // An entry that can't be added doesn't stop the others, its error is returned in its place.
+    pub fn add_entries(
+        &self,
+        dictionary_id: &str,
+        entries: Vec<HashMap<String, String>>,
+    ) -> Result<Vec<Result<usize, String>>, String> {
+        let dict = self.dictionary(dictionary_id)?;
+        let results = dict
+            .add_dictionary_entries(entries)
+            .into_iter()
+            .map(|index| {
+                index.ok_or_else(|| {
+                    format!(
+                        "Entry has none of the attributes of dictionary '{}'",
+                        dictionary_id
+                    )
+                })
+            })
+            .collect();
+        self.mark_dirty(dictionary_id);
+        Ok(results)
+    }
+
+    pub fn delete_entry(&self, dictionary_id: &str, dictionary_index: usize) -> Result<(), String> {
+        let dict = self.dictionary(dictionary_id)?;
+        // no remap, validate_config refuses the automatic compaction
+        dict.delete(dictionary_index).map_err(|_| {
+            format!(
+                "Entry {} not found in dictionary '{}'",
+                dictionary_index, dictionary_id
+            )
+        })?;
+        self.mark_dirty(dictionary_id);
+        Ok(())
+    }
+
+    pub fn get_entry(
+        &self,
+        dictionary_id: &str,
+        dictionary_index: usize,
+    ) -> Result<HashMap<String, String>, String> {
+        let dict = self.dictionary(dictionary_id)?;
+        if !dict.contains(dictionary_index) {
+            return Err(format!(
+                "Entry {} not found in dictionary '{}'",
+                dictionary_index, dictionary_id
+            ));
+        }
+        Ok(dict.get(dictionary_index))
+    }
+
+    // limit overrides the max_search_results of the dictionary, up to the ceiling of the map
     pub fn search(
         &self,
         dictionary_id: &str,
         term: &str,
+        limit: Option<usize>,
     ) -> Result<Vec<OwnedSearchResult>, String> {
-        match self.map.get(dictionary_id) {
-            Some(dict_arc) => {
-                let dict = dict_arc.read().unwrap();
-                let results = dict.search(term);
-                let owned_results = results
-                    .into_iter()
-                    .map(|sr| OwnedSearchResult {
-                        term: sr.term.to_string(),
-                        attribute: sr.attribute.to_string(),
-                        original_entry: sr.original_entry.to_string(),
-                        attribute_index: sr.attribute_index,
-                        position: sr.position,
-                    })
-                    .collect();
-                Ok(owned_results)
-            }
-            None => Err(format!("Dictionary with id '{}' not found", dictionary_id)),
-        }
+        let dict = self.dictionary(dictionary_id)?;
+        let results = match limit {
+            Some(limit) => dict.search_with_limit(term, limit.min(self.max_search_results)),
+            None => dict.search(term),
+        };
+        Ok(results
+            .into_iter()
+            .map(|sr| OwnedSearchResult {
+                term: sr.term.to_string(),
+                attribute: sr.attribute.to_string(),
+                original_entry: sr.original_entry.to_string(),
+                attribute_index: sr.attribute_index,
+                value_index: sr.value_index,
+                position: sr.position,
+                dictionary_index: sr.dictionary_index,
+            })
+            .collect())
     }
 }
 
@@ -89,7 +346,7 @@ mod tests {
 
     #[test]
     fn test_dictionary_map_basic_functionality() {
-        let mut dict_map = DictionaryMap::new();
+        let dict_map = DictionaryMap::new();
 
         // Test creating a dictionary
         let attributes = vec![
@@ -98,7 +355,7 @@ mod tests {
             ("id".to_string(), AttributeSearch::None),
         ];
 
-        let result = dict_map.create_dictionary("test_dict", attributes);
+        let result = dict_map.create_dictionary("test_dict", attributes, SearchConfig::default());
         assert!(result.is_ok());
 
         // Test adding an entry
@@ -111,17 +368,261 @@ mod tests {
         assert!(result.is_ok());
 
         // Test searching
-        let search_results = dict_map.search("test_dict", "John");
+        let search_results = dict_map.search("test_dict", "John", None);
         assert!(search_results.is_ok());
         let results = search_results.unwrap();
-        assert!(results.len() > 0);
+        assert!(!results.is_empty());
         assert_eq!(results[0].term, "John Doe");
     }
 
+    #[test]
+    fn test_entry_and_dictionary_management() {
+        let dict_map = DictionaryMap::new();
+        let attributes = vec![
+            ("name".to_string(), AttributeSearch::Multiple),
+            ("id".to_string(), AttributeSearch::None),
+        ];
+        dict_map
+            .create_dictionary("test_dict", attributes, SearchConfig::default())
+            .unwrap();
+        dict_map
+            .create_dictionary("other", vec![], SearchConfig::default())
+            .unwrap();
+        assert_eq!(dict_map.list_dictionaries(), vec!["other", "test_dict"]);
+
+        let index = dict_map
+            .add_entry(
+                "test_dict",
+                HashMap::from([("name".to_string(), "John Doe".to_string())]),
+            )
+            .unwrap();
+        assert_eq!(
+            dict_map.search("test_dict", "John", None).unwrap()[0].dictionary_index,
+            index
+        );
+        assert_eq!(
+            dict_map.get_entry("test_dict", index).unwrap()["name"],
+            "John Doe"
+        );
+        assert!(dict_map
+            .add_entry(
+                "test_dict",
+                HashMap::from([("x".to_string(), "y".to_string())])
+            )
+            .is_err());
+
+        dict_map.delete_entry("test_dict", index).unwrap();
+        assert!(dict_map
+            .search("test_dict", "John", None)
+            .unwrap()
+            .is_empty());
+        assert!(dict_map.get_entry("test_dict", index).is_err());
+        assert!(dict_map.delete_entry("test_dict", index).is_err());
+
+        // of concurrent deletes of an entry only one succeeds
+        let index = dict_map
+            .add_entry(
+                "test_dict",
+                HashMap::from([("name".to_string(), "Jane Doe".to_string())]),
+            )
+            .unwrap();
+        let deleted = std::thread::scope(|s| {
+            let deletes: Vec<_> = (0..8)
+                .map(|_| s.spawn(|| dict_map.delete_entry("test_dict", index).is_ok()))
+                .collect();
+            deletes
+                .into_iter()
+                .map(|d| d.join().unwrap())
+                .filter(|ok| *ok)
+                .count()
+        });
+        assert_eq!(deleted, 1);
+
+        let described = dict_map.describe_dictionary("test_dict").unwrap();
+        assert_eq!(
+            described
+                .iter()
+                .map(|(name, search)| (name.as_str(), search_type_name(search)))
+                .collect::<Vec<_>>(),
+            vec![("name", "multiple"), ("id", "none")]
+        );
+
+        dict_map.drop_dictionary("other").unwrap();
+        assert!(dict_map.drop_dictionary("other").is_err());
+        assert_eq!(dict_map.list_dictionaries(), vec!["test_dict"]);
+    }
+
+    #[test]
+    fn test_add_entries() {
+        let dict_map = DictionaryMap::new();
+        let attributes = vec![("name".to_string(), AttributeSearch::Multiple)];
+        dict_map
+            .create_dictionary("test_dict", attributes, SearchConfig::default())
+            .unwrap();
+        let results = dict_map
+            .add_entries(
+                "test_dict",
+                vec![
+                    HashMap::from([("name".to_string(), "John Doe".to_string())]),
+                    HashMap::from([("x".to_string(), "y".to_string())]),
+                    HashMap::from([("name".to_string(), "Jane Doe".to_string())]),
+                ],
+            )
+            .unwrap();
+        assert_eq!(results.len(), 3);
+        assert!(results[1].is_err());
+        let jane = *results[2].as_ref().unwrap();
+        assert_eq!(
+            dict_map.search("test_dict", "Jane", None).unwrap()[0].dictionary_index,
+            jane
+        );
+        assert_ne!(results[0].as_ref().unwrap(), &jane);
+        assert!(dict_map.add_entries("non_existent", vec![]).is_err());
+    }
+
+    #[test]
+    fn test_metrics() {
+        let dict_map = DictionaryMap::new();
+        for dictionary_id in ["names", "cities"] {
+            let attributes = vec![("name".to_string(), AttributeSearch::Multiple)];
+            dict_map
+                .create_dictionary(dictionary_id, attributes, SearchConfig::default())
+                .unwrap();
+        }
+        let entry = HashMap::from([("name".to_string(), "John Doe".to_string())]);
+        let index = dict_map.add_entry("names", entry).unwrap();
+        dict_map.search("names", "John", None).unwrap();
+        dict_map.delete_entry("names", index).unwrap();
+
+        let metrics = dict_map.metrics();
+        assert!(metrics.contains("bitmap_trie_searches_total{dictionary=\"names\"} 1\n"));
+        assert!(metrics.contains("bitmap_trie_search_results_total{dictionary=\"names\"} 1\n"));
+        assert!(metrics.contains("bitmap_trie_entries_deleted_total{dictionary=\"names\"} 1\n"));
+        assert!(metrics.contains("bitmap_trie_free_slots{dictionary=\"names\"} 1\n"));
+        assert!(metrics.contains("bitmap_trie_entries{dictionary=\"cities\"} 0\n"));
+        // sorted by id
+        assert!(metrics.find("dictionary=\"cities\"") < metrics.find("dictionary=\"names\""));
+    }
+
+    #[test]
+    fn test_search_config_and_limit() {
+        let dict_map = DictionaryMap::with_max_search_results(3);
+        let attributes = vec![("name".to_string(), AttributeSearch::Exact)];
+        let config = |max_search_results, max_direct_entries| SearchConfig {
+            max_search_results,
+            max_direct_entries,
+            ..SearchConfig::default()
+        };
+        assert!(dict_map
+            .create_dictionary("test_dict", attributes.clone(), config(4, 5))
+            .is_err());
+        assert!(dict_map
+            .create_dictionary("test_dict", attributes.clone(), config(2, 1))
+            .is_err());
+        dict_map
+            .create_dictionary("test_dict", attributes, config(2, 5))
+            .unwrap();
+        let entries = ["Anna", "Annabel", "Annette", "Annika"]
+            .map(|name| HashMap::from([("name".to_string(), name.to_string())]));
+        dict_map.add_entries("test_dict", entries.into()).unwrap();
+        assert_eq!(dict_map.search("test_dict", "Ann", None).unwrap().len(), 2);
+        assert_eq!(
+            dict_map.search("test_dict", "Ann", Some(1)).unwrap().len(),
+            1
+        );
+        // capped by the ceiling of the map
+        assert_eq!(
+            dict_map.search("test_dict", "Ann", Some(10)).unwrap().len(),
+            3
+        );
+        assert_eq!(parse_search_type("Exact"), Some(AttributeSearch::Exact));
+        assert_eq!(parse_search_type("fuzzy"), None);
+    }
+
+    #[test]
+    fn test_search_during_ingestion() {
+        let dict_map = DictionaryMap::new();
+        let attributes = vec![("name".to_string(), AttributeSearch::Multiple)];
+        dict_map
+            .create_dictionary("test_dict", attributes, SearchConfig::default())
+            .unwrap();
+        std::thread::scope(|s| {
+            s.spawn(|| {
+                let entries = (0..500)
+                    .map(|i| HashMap::from([("name".to_string(), format!("John Doe{}", i))]))
+                    .collect();
+                dict_map.add_entries("test_dict", entries).unwrap();
+            });
+            s.spawn(|| {
+                for i in 0..100 {
+                    let dictionary_id = format!("other{}", i);
+                    dict_map
+                        .create_dictionary(&dictionary_id, vec![], SearchConfig::default())
+                        .unwrap();
+                    dict_map.drop_dictionary(&dictionary_id).unwrap();
+                }
+            });
+            for _ in 0..4 {
+                s.spawn(|| {
+                    for _ in 0..100 {
+                        let results = dict_map.search("test_dict", "John", None).unwrap();
+                        assert!(results.iter().all(|r| r.original_entry.starts_with("John")));
+                    }
+                });
+            }
+        });
+        assert_eq!(
+            dict_map.get_entry("test_dict", 499).unwrap()["name"],
+            "John Doe499"
+        );
+        assert_eq!(dict_map.list_dictionaries(), vec!["test_dict"]);
+    }
+
+    #[test]
+    fn test_save_and_restore() {
+        let dir = std::env::temp_dir().join(format!("netexample-access-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        assert!(DictionaryMap::new().save().is_err());
+
+        let dict_map = DictionaryMap::new().with_data_dir(dir.clone());
+        assert_eq!(dict_map.restore().unwrap(), 0);
+        let attributes = vec![("name".to_string(), AttributeSearch::Multiple)];
+        for dictionary_id in ["names", "dropped"] {
+            dict_map
+                .create_dictionary(dictionary_id, attributes.clone(), SearchConfig::default())
+                .unwrap();
+        }
+        let index = dict_map
+            .add_entry(
+                "names",
+                HashMap::from([("name".to_string(), "John Doe".to_string())]),
+            )
+            .unwrap();
+        assert_eq!(dict_map.save().unwrap(), vec!["dropped", "names"]);
+        // nothing changed since
+        assert!(dict_map.save().unwrap().is_empty());
+        dict_map.drop_dictionary("dropped").unwrap();
+        assert_eq!(dict_map.save().unwrap(), vec!["dropped"]);
+
+        let restored = DictionaryMap::new().with_data_dir(dir.clone());
+        assert_eq!(restored.restore().unwrap(), 1);
+        assert_eq!(restored.list_dictionaries(), vec!["names"]);
+        assert_eq!(
+            restored.search("names", "John", None).unwrap()[0].dictionary_index,
+            index
+        );
+        // a lower server limit than the saved one lowers the limit of the dictionary
+        let restored = DictionaryMap::with_max_search_results(5).with_data_dir(dir.clone());
+        assert_eq!(restored.restore().unwrap(), 1);
+        let names = restored.dictionary("names").unwrap();
+        assert_eq!(names.config().max_search_results, 5);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
     #[test]
     fn test_dictionary_not_found() {
         let dict_map = DictionaryMap::new();
-        let result = dict_map.search("non_existent", "test");
+        let result = dict_map.search("non_existent", "test", None);
         assert!(result.is_err());
         let error_msg = result.unwrap_err();
         assert!(error_msg.contains("not found"));
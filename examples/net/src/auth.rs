@@ -0,0 +1,201 @@
+use crate::command::{Command, Response};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+// The auth file maps every token to its grants, the permission per dictionary id, "*" for all the dictionaries:
+// {"tokens": {"s3cr3t": {"*": "read", "names": "write"}, "0p3r4t0r": {"*": "admin"}}}
+// Permissions include the lower ones: admin can write, write can read.
+
+pub const ALL_DICTIONARIES: &str = "*";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Permission {
+    Read,  // search, get entries and describe
+    Write, // add and delete entries
+    Admin, // create and drop dictionaries, save
+}
+
+impl Permission {
+    fn name(&self) -> &'static str {
+        match self {
+            Permission::Read => "read",
+            Permission::Write => "write",
+            Permission::Admin => "admin",
+        }
+    }
+}
+
+#[derive(Debug, Default, Clone, Deserialize)]
+pub struct Grants(HashMap<String, Permission>);
+
+impl Grants {
+    pub fn permission(&self, dictionary_id: &str) -> Option<Permission> {
+        let all = self.0.get(ALL_DICTIONARIES);
+        let own = self.0.get(dictionary_id);
+        all.max(own).copied()
+    }
+
+    pub fn allows(&self, dictionary_id: &str, required: Permission) -> bool {
+        self.permission(dictionary_id)
+            .is_some_and(|permission| permission >= required)
+    }
+}
+
+#[derive(Debug, Default, Deserialize)]
+pub struct AuthConfig {
+    tokens: HashMap<String, Grants>,
+}
+
+impl AuthConfig {
+    pub fn load(path: &Path) -> io::Result<Self> {
+        serde_json::from_slice(&fs::read(path)?).map_err(|e| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("{}: {}", path.display(), e),
+            )
+        })
+    }
+
+    pub fn authenticate(&self, token: &str) -> Option<&Grants> {
+        self.tokens.get(token)
+    }
+}
+
+// the dictionaries a command touches, with the permission it needs on each of them
+fn required_permissions(command: &Command) -> Vec<(&str, Permission)> {
+    match command {
+        Command::CreateDictionary(c) => vec![(&c.dictionary_id, Permission::Admin)],
+        Command::DropDictionary(c) => vec![(&c.dictionary_id, Permission::Admin)],
+        Command::AddDictionaryEntry(c) => vec![(&c.dictionary_id, Permission::Write)],
+        Command::AddDictionaryEntries(c) => vec![(&c.dictionary_id, Permission::Write)],
+        Command::DeleteEntry(c) => vec![(&c.dictionary_id, Permission::Write)],
+        Command::Search(c) => vec![(&c.dictionary_id, Permission::Read)],
+        Command::MultiSearch(c) => c
+            .searches
+            .iter()
+            .map(|s| (s.dictionary_id.as_str(), Permission::Read))
+            .collect(),
+        Command::GetEntry(c) => vec![(&c.dictionary_id, Permission::Read)],
+        Command::DescribeDictionary(c) => vec![(&c.dictionary_id, Permission::Read)],
+        Command::Save => vec![(ALL_DICTIONARIES, Permission::Admin)],
+        // the metrics cover all the dictionaries
+        Command::Stats => vec![(ALL_DICTIONARIES, Permission::Read)],
+        // the list is filtered by the grants instead
+        Command::ListDictionaries | Command::Auth(_) => vec![],
+    }
+}
+
+/// Checks the command against the grants of the connection, None if it didn't authenticate yet
+pub fn authorize(grants: Option<&Grants>, command: &Command) -> Result<(), String> {
+    let Some(grants) = grants else {
+        return Err("Permission denied: authenticate with the Auth command first".to_string());
+    };
+    for (dictionary_id, required) in required_permissions(command) {
+        if !grants.allows(dictionary_id, required) {
+            let target = if dictionary_id == ALL_DICTIONARIES {
+                "all the dictionaries".to_string()
+            } else {
+                format!("dictionary '{}'", dictionary_id)
+            };
+            return Err(format!(
+                "Permission denied: {} access to {} required",
+                required.name(),
+                target
+            ));
+        }
+    }
+    Ok(())
+}
+
+/// Leaves out of the response what the grants don't allow to see
+pub fn filter_response(grants: &Grants, response: &mut Response) {
+    if let Response::ListDictionaries(r) = response {
+        r.dictionary_ids
+            .retain(|dictionary_id| grants.allows(dictionary_id, Permission::Read));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::command::{
+        CreateDictionaryCommand, DeleteEntryCommand, ListDictionariesResponse, MultiSearchCommand,
+        SearchCommand,
+    };
+
+    fn auth_config() -> AuthConfig {
+        serde_json::from_str(
+            r#"{"tokens": {
+                "reader": {"*": "read", "names": "write"},
+                "operator": {"*": "admin"}
+            }}"#,
+        )
+        .unwrap()
+    }
+
+    fn search(dictionary_id: &str) -> SearchCommand {
+        SearchCommand {
+            dictionary_id: dictionary_id.to_string(),
+            term: "John".to_string(),
+            limit: None,
+        }
+    }
+
+    #[test]
+    fn test_authorize() {
+        let config = auth_config();
+        assert!(config.authenticate("unknown").is_none());
+        let reader = config.authenticate("reader");
+        let operator = config.authenticate("operator");
+
+        let delete = |dictionary_id: &str| {
+            Command::DeleteEntry(DeleteEntryCommand {
+                dictionary_id: dictionary_id.to_string(),
+                dictionary_index: 0,
+            })
+        };
+        assert!(authorize(reader, &delete("names")).is_ok());
+        let e = authorize(reader, &delete("cities")).unwrap_err();
+        assert_eq!(
+            e,
+            "Permission denied: write access to dictionary 'cities' required"
+        );
+        assert!(authorize(operator, &delete("cities")).is_ok());
+
+        let create = Command::CreateDictionary(CreateDictionaryCommand {
+            dictionary_id: "names".to_string(),
+            ..Default::default()
+        });
+        assert!(authorize(reader, &create).is_err());
+        assert!(authorize(operator, &create).is_ok());
+        assert!(authorize(reader, &Command::Save).is_err());
+        assert!(authorize(reader, &Command::Stats).is_ok());
+        assert!(authorize(None, &Command::Search(search("names"))).is_err());
+    }
+
+    #[test]
+    fn test_scoped_grants() {
+        let grants: Grants = serde_json::from_str(r#"{"names": "read"}"#).unwrap();
+        let multi_search = |ids: &[&str]| {
+            Command::MultiSearch(MultiSearchCommand {
+                searches: ids.iter().map(|id| search(id)).collect(),
+            })
+        };
+        assert!(authorize(Some(&grants), &multi_search(&["names", "names"])).is_ok());
+        assert!(authorize(Some(&grants), &multi_search(&["names", "cities"])).is_err());
+        // the metrics include the dictionaries the grants don't cover
+        assert!(authorize(Some(&grants), &Command::Stats).is_err());
+
+        let mut response = Response::ListDictionaries(ListDictionariesResponse {
+            dictionary_ids: vec!["cities".to_string(), "names".to_string()],
+        });
+        filter_response(&grants, &mut response);
+        assert!(
+            matches!(response, Response::ListDictionaries(r) if r.dictionary_ids == vec!["names"])
+        );
+    }
+}
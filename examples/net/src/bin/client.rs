@@ -1,99 +1,240 @@
-use netexample::command::{
-    AddDictionaryEntryCommand, Command, CreateDictionaryCommand, Response, SearchCommand,
+use crossterm::cursor::MoveTo;
+use crossterm::event::{self, Event, KeyCode, KeyEventKind, KeyModifiers};
+use crossterm::terminal::{self, Clear, ClearType, EnterAlternateScreen, LeaveAlternateScreen};
+use crossterm::{execute, queue};
+use netexample::cli::{
+    format_response, load_entries, response_error, split_line, CliCommand, ClientConfig, Output,
+    USAGE,
 };
-use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
-use std::io::{BufReader, BufWriter, Write};
-use std::net::TcpStream;
+use netexample::client::Client;
+use netexample::command::{AuthCommand, Command, Response, SearchCommand};
+use std::io::{self, BufRead, Write};
+use std::path::Path;
+use std::time::Instant;
 
-fn create_dictionary_client(
-    stream: &TcpStream,
-    dictionary_id: &str,
-    attributes: Vec<(String, String)>,
-) {
-    let reader = BufReader::new(stream);
-    let writer = BufWriter::new(stream);
-    let mut serializer = serde_json::Serializer::new(writer);
-    let mut deserializer = serde_json::Deserializer::from_reader(reader);
-    let c = Command::CreateDictionary(CreateDictionaryCommand {
-        dictionary_id: dictionary_id.to_string(),
-        attributes,
-    });
-    c.serialize(&mut serializer).unwrap();
-    serializer.into_inner().flush().unwrap();
-    let response = Response::deserialize(&mut deserializer).unwrap();
-    println!("Create dictionary response: {:?}", response);
+struct Session {
+    client: Client,
+    output: Output,
 }
 
-fn add_dictionary_entry_client(
-    stream: &TcpStream,
-    dictionary_id: &str,
-    entry_data: HashMap<String, String>,
-) {
-    let reader = BufReader::new(stream);
-    let writer = BufWriter::new(stream);
-    let mut serializer = serde_json::Serializer::new(writer);
-    let mut deserializer = serde_json::Deserializer::from_reader(reader);
-    let c = Command::AddDictionaryEntry(AddDictionaryEntryCommand {
-        dictionary_id: dictionary_id.to_string(),
-        entry_data,
-    });
-    c.serialize(&mut serializer).unwrap();
-    serializer.into_inner().flush().unwrap();
-    let response = Response::deserialize(&mut deserializer).unwrap();
-    println!("Add entry response: {:?}", response);
-}
+// Reports whether the command succeeded, the REPL keeps going either way
+type CommandResult = io::Result<bool>;
 
-fn search_term_client(stream: &TcpStream, dictionary_id: &str, term: &str) {
-    let reader = BufReader::new(stream);
-    let writer = BufWriter::new(stream);
-    let mut serializer = serde_json::Serializer::new(writer);
-    let mut deserializer = serde_json::Deserializer::from_reader(reader);
-    let c = Command::Search(SearchCommand {
-        dictionary_id: dictionary_id.to_string(),
-        term: term.to_string(),
-    });
-    c.serialize(&mut serializer).unwrap();
-    serializer.into_inner().flush().unwrap();
-    let r = Response::deserialize(&mut deserializer).unwrap();
-    println!("{:?}", r);
-}
+impl Session {
+    fn print(&self, response: &Response) -> bool {
+        println!("{}", format_response(response, self.output));
+        response_error(response).is_none()
+    }
 
-fn main() {
-    let stream = TcpStream::connect("127.0.0.1:4444").unwrap();
+    fn run(&mut self, command: CliCommand) -> CommandResult {
+        match command {
+            CliCommand::Send(command) => {
+                let response = self.client.send(command)?;
+                Ok(self.print(&response))
+            }
+            CliCommand::Load {
+                dictionary_id,
+                path,
+                batch_size,
+            } => self.load(&dictionary_id, &path, batch_size),
+            CliCommand::Complete {
+                dictionary_id,
+                limit,
+            } => self.complete(&dictionary_id, limit).map(|_| true),
+            CliCommand::Help => {
+                println!("{}", USAGE);
+                Ok(true)
+            }
+            CliCommand::Quit => Ok(true),
+        }
+    }
+
+    // The batches are pipelined, with a bounded number of them waiting for their responses
+    fn load(&mut self, dictionary_id: &str, path: &Path, batch_size: usize) -> CommandResult {
+        let entries = match load_entries(path) {
+            Ok(entries) => entries,
+            Err(e) => {
+                eprintln!("Error: {}", e);
+                return Ok(false);
+            }
+        };
+        let start = Instant::now();
+        let (added, failed) = match self
+            .client
+            .add_entries(dictionary_id, entries, batch_size)?
+        {
+            Ok(counts) => counts,
+            Err(response) => {
+                self.print(&response);
+                return Ok(false);
+            }
+        };
+        match self.output {
+            Output::Pretty => println!(
+                "Loaded {} entries into '{}', {} failed, in {:.1?}",
+                added,
+                dictionary_id,
+                failed,
+                start.elapsed()
+            ),
+            Output::Json => println!(
+                "{}",
+                serde_json::json!({"added": added, "failed": failed, "elapsed_ms": start.elapsed().as_millis()})
+            ),
+        }
+        Ok(failed == 0)
+    }
 
-    // First create a dictionary with attributes
-    create_dictionary_client(
-        &stream,
-        "names",
-        vec![
-            ("name".to_string(), "multiple".to_string()),
-            ("surname".to_string(), "exact".to_string()),
-            ("id".to_string(), "none".to_string()),
-        ],
-    );
+    // Searches the dictionary on every keystroke, in the alternate screen, until Enter or Esc
+    fn complete(&mut self, dictionary_id: &str, limit: usize) -> io::Result<()> {
+        let mut stdout = io::stdout();
+        terminal::enable_raw_mode()?;
+        execute!(stdout, EnterAlternateScreen)?;
+        let res = self.complete_loop(&mut stdout, dictionary_id, limit);
+        execute!(stdout, LeaveAlternateScreen)?;
+        terminal::disable_raw_mode()?;
+        res
+    }
 
-    // Add some dictionary entries
-    let mut entry1 = HashMap::new();
-    entry1.insert("name".to_string(), "Dusan Milutinovic".to_string());
-    entry1.insert("surname".to_string(), "Milutinovic".to_string());
-    entry1.insert("id".to_string(), "1".to_string());
-    add_dictionary_entry_client(&stream, "names", entry1);
+    fn complete_loop(
+        &mut self,
+        stdout: &mut io::Stdout,
+        dictionary_id: &str,
+        limit: usize,
+    ) -> io::Result<()> {
+        let mut term = String::new();
+        let mut lines = vec!["Type to search, Enter or Esc to leave".to_string()];
+        loop {
+            // in raw mode, the lines have to return the cursor themselves
+            queue!(stdout, MoveTo(0, 0), Clear(ClearType::All))?;
+            write!(stdout, "{}> {}\r\n", dictionary_id, term)?;
+            for line in &lines {
+                write!(stdout, "{}\r\n", line)?;
+            }
+            // the column counts characters, not bytes
+            let column = dictionary_id.chars().count() + 2 + term.chars().count();
+            queue!(stdout, MoveTo(column as u16, 0))?;
+            stdout.flush()?;
 
-    let mut entry2 = HashMap::new();
-    entry2.insert("name".to_string(), "Dragan Miloradovic".to_string());
-    entry2.insert("surname".to_string(), "Miloradovic".to_string());
-    entry2.insert("id".to_string(), "2".to_string());
-    add_dictionary_entry_client(&stream, "names", entry2);
+            let Event::Key(key) = event::read()? else {
+                continue;
+            };
+            if key.kind != KeyEventKind::Press {
+                continue;
+            }
+            match key.code {
+                KeyCode::Enter | KeyCode::Esc => return Ok(()),
+                KeyCode::Char('c') if key.modifiers.contains(KeyModifiers::CONTROL) => {
+                    return Ok(())
+                }
+                KeyCode::Char(c) => term.push(c),
+                KeyCode::Backspace => {
+                    term.pop();
+                }
+                _ => continue,
+            }
+            if term.is_empty() {
+                lines.clear();
+                continue;
+            }
+            let start = Instant::now();
+            let response = self.client.send(Command::Search(SearchCommand {
+                dictionary_id: dictionary_id.to_string(),
+                term: term.clone(),
+                limit: Some(limit),
+            }))?;
+            let elapsed = start.elapsed();
+            lines = format_response(&response, Output::Pretty)
+                .lines()
+                .map(str::to_string)
+                .collect();
+            lines.push(format!("in {:.2?}", elapsed));
+        }
+    }
+
+    fn repl(&mut self) -> io::Result<()> {
+        let stdin = io::stdin();
+        loop {
+            print!("> ");
+            io::stdout().flush()?;
+            let mut line = String::new();
+            if stdin.lock().read_line(&mut line)? == 0 {
+                return Ok(());
+            }
+            if line.trim().is_empty() {
+                continue;
+            }
+            match split_line(&line).and_then(|words| CliCommand::parse(&words)) {
+                Ok(CliCommand::Quit) => return Ok(()),
+                Ok(command) => {
+                    self.run(command)?;
+                }
+                Err(e) => eprintln!("{}", e),
+            }
+        }
+    }
+}
+
+fn main() {
+    let (config, words) = match ClientConfig::from_args(std::env::args().skip(1)) {
+        Ok(parsed) => parsed,
+        Err(e) => {
+            eprintln!("{}\n\n{}", e, USAGE);
+            std::process::exit(2);
+        }
+    };
+    // parse before connecting, so a typo doesn't need a server to be reported
+    let command = if words.is_empty() {
+        None
+    } else {
+        match CliCommand::parse(&words) {
+            Ok(command) => Some(command),
+            Err(e) => {
+                eprintln!("{}\n\n{}", e, USAGE);
+                std::process::exit(2);
+            }
+        }
+    };
 
-    let mut entry3 = HashMap::new();
-    entry3.insert("name".to_string(), "Petar Petrovic".to_string());
-    entry3.insert("surname".to_string(), "Petrovic".to_string());
-    entry3.insert("id".to_string(), "3".to_string());
-    add_dictionary_entry_client(&stream, "names", entry3);
+    let client = match Client::connect_with_codec(config.address.as_str(), config.codec) {
+        Ok(client) => client,
+        Err(e) => {
+            eprintln!("Could not connect to {}: {}", config.address, e);
+            std::process::exit(1);
+        }
+    };
+    let mut session = Session {
+        client,
+        output: config.output,
+    };
+    if let Some(token) = config.token {
+        let authenticated = session
+            .client
+            .send(Command::Auth(AuthCommand { token }))
+            .map(|response| response_error(&response).map(str::to_string));
+        match authenticated {
+            Ok(None) => {}
+            Ok(Some(e)) => {
+                eprintln!("{}", e);
+                std::process::exit(1);
+            }
+            Err(e) => {
+                eprintln!("Connection error: {}", e);
+                std::process::exit(1);
+            }
+        }
+    }
 
-    // Search for terms
-    search_term_client(&stream, "names", "Dusan");
-    search_term_client(&stream, "names", "Mil");
-    search_term_client(&stream, "names", "Petrovic");
+    let res = match command {
+        Some(command) => session.run(command),
+        None => session.repl().map(|_| true),
+    };
+    match res {
+        Ok(true) => {}
+        Ok(false) => std::process::exit(1),
+        Err(e) => {
+            eprintln!("Connection error: {}", e);
+            std::process::exit(1);
+        }
+    }
 }
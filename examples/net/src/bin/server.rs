@@ -1,5 +1,19 @@
-use netexample::listener;
+use netexample::listener::{self, ServerConfig};
 
-fn main() {
-    listener::listen().expect("Server failed to start");
+#[tokio::main]
+async fn main() {
+    let config = match ServerConfig::from_args(std::env::args().skip(1)) {
+        Ok(config) => config,
+        Err(e) => {
+            eprintln!("{}", e);
+            eprintln!(
+                "Usage: server [--address ADDRESS] [--port PORT] [--max-connections N] [--request-timeout-ms MS] \
+                 [--max-search-results N] [--data-dir DIR] [--snapshot-interval-secs SECS] [--auth-file FILE]"
+            );
+            std::process::exit(2);
+        }
+    };
+    listener::listen(config)
+        .await
+        .expect("Server failed to start");
 }
@@ -0,0 +1,606 @@
+use crate::command::{
+    AddDictionaryEntryCommand, Command, CreateDictionaryCommand, DeleteEntryCommand,
+    DescribeDictionaryCommand, DropDictionaryCommand, GetEntryCommand, Response, SearchCommand,
+    SearchResultItem,
+};
+use crate::protocol::Codec;
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader};
+use std::path::{Path, PathBuf};
+
+// The client takes its options first and then one command, `client --output json search names John`.
+// Without a command it starts a REPL, where every line is a command in the same syntax.
+
+pub const USAGE: &str = "\
+Usage: client [--address ADDRESS] [--token TOKEN] [--codec json|msgpack] [--output pretty|json] [COMMAND]
+
+Commands:
+  create DICTIONARY ATTRIBUTE:SEARCH_TYPE... [--max-search-results N] [--min-term-length N]
+         [--max-direct-entries N] [--default-multiple-search-length N]
+  add DICTIONARY ATTRIBUTE=VALUE...
+  load DICTIONARY FILE [--batch-size N]     entries from a .csv file with a header row, or a .jsonl file
+  search DICTIONARY TERM... [--limit N]
+  complete DICTIONARY [--limit N]           searches as you type
+  get DICTIONARY INDEX
+  delete DICTIONARY INDEX
+  describe DICTIONARY
+  drop DICTIONARY
+  list
+  save
+  stats                                     metrics of all the dictionaries, in the Prometheus text format
+  help
+  quit                                      leaves the REPL";
+
+pub const DEFAULT_ADDRESS: &str = "127.0.0.1:4444";
+pub const DEFAULT_BATCH_SIZE: usize = 1000;
+pub const DEFAULT_COMPLETE_LIMIT: usize = 10;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Output {
+    Pretty,
+    Json, // the responses as they are, one JSON document per line
+}
+
+#[derive(Debug, PartialEq)]
+pub struct ClientConfig {
+    pub address: String,
+    pub token: Option<String>, // sent with the Auth command right after connecting
+    pub codec: Codec,
+    pub output: Output,
+}
+
+impl Default for ClientConfig {
+    fn default() -> Self {
+        Self {
+            address: DEFAULT_ADDRESS.to_string(),
+            token: None,
+            codec: Codec::Json,
+            output: Output::Pretty,
+        }
+    }
+}
+
+impl ClientConfig {
+    /// Parses the options up to the first word that isn't one, returns the remaining words as the command
+    pub fn from_args(
+        args: impl IntoIterator<Item = String>,
+    ) -> Result<(Self, Vec<String>), String> {
+        let mut config = ClientConfig::default();
+        let mut args = args.into_iter().peekable();
+        while let Some(arg) = args.next_if(|arg| arg.starts_with("--")) {
+            let value = args
+                .next()
+                .ok_or_else(|| format!("Missing value for option '{}'", arg))?;
+            let invalid = || format!("Invalid value '{}' for option '{}'", value, arg);
+            match arg.as_str() {
+                "--address" => config.address = value.clone(),
+                "--token" => config.token = Some(value.clone()),
+                "--codec" => {
+                    config.codec = match value.as_str() {
+                        "json" => Codec::Json,
+                        "msgpack" => Codec::MessagePack,
+                        _ => return Err(invalid()),
+                    }
+                }
+                "--output" => {
+                    config.output = match value.as_str() {
+                        "pretty" => Output::Pretty,
+                        "json" => Output::Json,
+                        _ => return Err(invalid()),
+                    }
+                }
+                _ => return Err(format!("Unknown option '{}'", arg)),
+            }
+        }
+        Ok((config, args.collect()))
+    }
+}
+
+#[derive(Debug)]
+pub enum CliCommand {
+    // sent to the server as it is
+    Send(Command),
+    Load {
+        dictionary_id: String,
+        path: PathBuf,
+        batch_size: usize,
+    },
+    Complete {
+        dictionary_id: String,
+        limit: usize,
+    },
+    Help,
+    Quit,
+}
+
+// Splits the words of a command into the positional ones and the `--option value` pairs,
+// only the options listed are accepted
+fn split_options<'a>(
+    words: &'a [String],
+    allowed: &[&str],
+) -> Result<(Vec<&'a str>, HashMap<&'a str, &'a str>), String> {
+    let mut positional = Vec::new();
+    let mut options = HashMap::new();
+    let mut words = words.iter();
+    while let Some(word) = words.next() {
+        if let Some(name) = word.strip_prefix("--") {
+            if !allowed.contains(&name) {
+                return Err(format!("Unknown option '{}'", word));
+            }
+            let value = words
+                .next()
+                .ok_or_else(|| format!("Missing value for option '{}'", word))?;
+            options.insert(name, value.as_str());
+        } else {
+            positional.push(word.as_str());
+        }
+    }
+    Ok((positional, options))
+}
+
+fn parse_option(options: &HashMap<&str, &str>, name: &str) -> Result<Option<usize>, String> {
+    options
+        .get(name)
+        .map(|value| {
+            value
+                .parse()
+                .map_err(|_| format!("Invalid value '{}' for option '--{}'", value, name))
+        })
+        .transpose()
+}
+
+fn parse_index(value: &str) -> Result<usize, String> {
+    value
+        .parse()
+        .map_err(|_| format!("Invalid dictionary index '{}'", value))
+}
+
+// `attribute:search_type` for create, `attribute=value` for add
+fn parse_pairs(words: &[&str], separator: char) -> Result<Vec<(String, String)>, String> {
+    words
+        .iter()
+        .map(|word| match word.split_once(separator) {
+            Some((name, value)) if !name.is_empty() => Ok((name.to_string(), value.to_string())),
+            _ => Err(format!(
+                "Invalid argument '{}', expected NAME{}VALUE",
+                word, separator
+            )),
+        })
+        .collect()
+}
+
+impl CliCommand {
+    pub fn parse(words: &[String]) -> Result<Self, String> {
+        let Some((name, rest)) = words.split_first() else {
+            return Ok(CliCommand::Help);
+        };
+        let allowed: &[&str] = match name.as_str() {
+            "create" => &[
+                "max-search-results",
+                "min-term-length",
+                "max-direct-entries",
+                "default-multiple-search-length",
+            ],
+            "load" => &["batch-size"],
+            "search" | "complete" => &["limit"],
+            _ => &[],
+        };
+        let (args, options) = split_options(rest, allowed)?;
+        let dictionary_id = || {
+            args.first()
+                .map(|id| id.to_string())
+                .ok_or_else(|| format!("Missing dictionary id for '{}'", name))
+        };
+        let expect_args = |count: usize| {
+            if args.len() == count {
+                Ok(())
+            } else {
+                Err(format!(
+                    "'{}' takes {} arguments, got {}",
+                    name,
+                    count,
+                    args.len()
+                ))
+            }
+        };
+        let command = match name.as_str() {
+            "create" => Command::CreateDictionary(CreateDictionaryCommand {
+                dictionary_id: dictionary_id()?,
+                attributes: parse_pairs(&args[1..], ':')?,
+                max_search_results: parse_option(&options, "max-search-results")?,
+                min_term_length: parse_option(&options, "min-term-length")?,
+                max_direct_entries: parse_option(&options, "max-direct-entries")?,
+                default_multiple_search_length: parse_option(
+                    &options,
+                    "default-multiple-search-length",
+                )?,
+            }),
+            "add" => Command::AddDictionaryEntry(AddDictionaryEntryCommand {
+                dictionary_id: dictionary_id()?,
+                entry_data: parse_pairs(&args[1..], '=')?.into_iter().collect(),
+            }),
+            "load" => {
+                expect_args(2)?;
+                return Ok(CliCommand::Load {
+                    dictionary_id: dictionary_id()?,
+                    path: PathBuf::from(args[1]),
+                    batch_size: parse_option(&options, "batch-size")?
+                        .filter(|size| *size > 0)
+                        .unwrap_or(DEFAULT_BATCH_SIZE),
+                });
+            }
+            "search" => {
+                if args.len() < 2 {
+                    return Err("Missing search term for 'search'".to_string());
+                }
+                Command::Search(SearchCommand {
+                    dictionary_id: dictionary_id()?,
+                    term: args[1..].join(" "),
+                    limit: parse_option(&options, "limit")?,
+                })
+            }
+            "complete" => {
+                expect_args(1)?;
+                return Ok(CliCommand::Complete {
+                    dictionary_id: dictionary_id()?,
+                    limit: parse_option(&options, "limit")?.unwrap_or(DEFAULT_COMPLETE_LIMIT),
+                });
+            }
+            "get" | "delete" => {
+                expect_args(2)?;
+                let dictionary_id = dictionary_id()?;
+                let dictionary_index = parse_index(args[1])?;
+                if name == "get" {
+                    Command::GetEntry(GetEntryCommand {
+                        dictionary_id,
+                        dictionary_index,
+                    })
+                } else {
+                    Command::DeleteEntry(DeleteEntryCommand {
+                        dictionary_id,
+                        dictionary_index,
+                    })
+                }
+            }
+            "describe" => {
+                expect_args(1)?;
+                Command::DescribeDictionary(DescribeDictionaryCommand {
+                    dictionary_id: dictionary_id()?,
+                })
+            }
+            "drop" => {
+                expect_args(1)?;
+                Command::DropDictionary(DropDictionaryCommand {
+                    dictionary_id: dictionary_id()?,
+                })
+            }
+            "list" => {
+                expect_args(0)?;
+                Command::ListDictionaries
+            }
+            "save" => {
+                expect_args(0)?;
+                Command::Save
+            }
+            "stats" => {
+                expect_args(0)?;
+                Command::Stats
+            }
+            "help" => return Ok(CliCommand::Help),
+            "quit" | "exit" => return Ok(CliCommand::Quit),
+            _ => return Err(format!("Unknown command '{}', try 'help'", name)),
+        };
+        Ok(CliCommand::Send(command))
+    }
+}
+
+/// Splits a REPL line into words, on whitespace outside of single or double quotes
+pub fn split_line(line: &str) -> Result<Vec<String>, String> {
+    let mut words = Vec::new();
+    let mut word: Option<String> = None;
+    let mut quote = None;
+    for c in line.chars() {
+        match (quote, c) {
+            (Some(q), c) if c == q => quote = None,
+            (Some(_), c) => word.get_or_insert_with(String::new).push(c),
+            (None, '"' | '\'') => {
+                quote = Some(c);
+                word.get_or_insert_with(String::new);
+            }
+            (None, c) if c.is_whitespace() => words.extend(word.take()),
+            (None, c) => word.get_or_insert_with(String::new).push(c),
+        }
+    }
+    if quote.is_some() {
+        return Err("Unterminated quote".to_string());
+    }
+    words.extend(word);
+    Ok(words)
+}
+
+fn invalid_data(e: impl ToString) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, e.to_string())
+}
+
+// JSON values other than strings are added as their JSON text
+fn json_entry(line: &str) -> Result<HashMap<String, String>, String> {
+    let object: HashMap<String, serde_json::Value> =
+        serde_json::from_str(line).map_err(|e| e.to_string())?;
+    Ok(object
+        .into_iter()
+        .map(|(name, value)| match value {
+            serde_json::Value::String(s) => (name, s),
+            value => (name, value.to_string()),
+        })
+        .collect())
+}
+
+/// Reads the entries of a `.csv` file, with the attribute names in the header row,
+/// or of a JSON Lines file, one object per line
+pub fn load_entries(path: &Path) -> io::Result<Vec<HashMap<String, String>>> {
+    let with_path = |e: String| invalid_data(format!("{}: {}", path.display(), e));
+    if path
+        .extension()
+        .is_some_and(|e| e.eq_ignore_ascii_case("csv"))
+    {
+        let mut reader = csv::Reader::from_path(path).map_err(|e| with_path(e.to_string()))?;
+        let headers = reader
+            .headers()
+            .map_err(|e| with_path(e.to_string()))?
+            .clone();
+        reader
+            .records()
+            .map(|record| {
+                let record = record.map_err(|e| with_path(e.to_string()))?;
+                Ok(headers
+                    .iter()
+                    .zip(record.iter())
+                    .map(|(name, value)| (name.to_string(), value.to_string()))
+                    .collect())
+            })
+            .collect()
+    } else {
+        let mut entries = Vec::new();
+        for (number, line) in BufReader::new(File::open(path)?).lines().enumerate() {
+            let line = line?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            let entry =
+                json_entry(&line).map_err(|e| with_path(format!("line {}: {}", number + 1, e)))?;
+            entries.push(entry);
+        }
+        Ok(entries)
+    }
+}
+
+/// The error the response carries, if any
+pub fn response_error(response: &Response) -> Option<&str> {
+    match response {
+        Response::CreateDictionary(r) => r.error.as_deref(),
+        Response::AddDictionaryEntry(r) => r.error.as_deref(),
+        Response::AddDictionaryEntries(r) => r.error.as_deref(),
+        Response::Search(r) => r.error.as_deref(),
+        Response::MultiSearch(r) => r.results.iter().find_map(|r| r.error.as_deref()),
+        Response::DeleteEntry(r) => r.error.as_deref(),
+        Response::GetEntry(r) => r.error.as_deref(),
+        Response::ListDictionaries(_) => None,
+        Response::DropDictionary(r) => r.error.as_deref(),
+        Response::DescribeDictionary(r) => r.error.as_deref(),
+        Response::Save(r) => r.error.as_deref(),
+        Response::Stats(_) => None,
+        Response::Auth(r) => r.error.as_deref(),
+        Response::PermissionDenied(r) | Response::Error(r) => Some(&r.error),
+    }
+}
+
+fn format_search_results(out: &mut Vec<String>, results: &[SearchResultItem]) {
+    for item in results {
+        out.push(format!(
+            "{:>8}  {}: {}",
+            item.dictionary_index, item.attribute, item.original_entry
+        ));
+    }
+    out.push(format!(
+        "{} result{}",
+        results.len(),
+        if results.len() == 1 { "" } else { "s" }
+    ));
+}
+
+pub fn format_response(response: &Response, output: Output) -> String {
+    if output == Output::Json {
+        return serde_json::to_string(response).unwrap_or_else(|e| e.to_string());
+    }
+    if let Some(error) = response_error(response) {
+        return format!("Error: {}", error);
+    }
+    let mut out = Vec::new();
+    match response {
+        Response::CreateDictionary(_) => out.push("Dictionary created".to_string()),
+        Response::AddDictionaryEntry(r) => out.push(format!(
+            "Added entry {}",
+            r.dictionary_index.unwrap_or_default()
+        )),
+        Response::AddDictionaryEntries(r) => {
+            let failed: Vec<_> = r
+                .results
+                .iter()
+                .enumerate()
+                .filter_map(|(i, r)| r.error.as_ref().map(|e| (i, e)))
+                .collect();
+            out.push(format!(
+                "Added {} entries, {} failed",
+                r.results.len() - failed.len(),
+                failed.len()
+            ));
+            for (i, error) in failed {
+                out.push(format!("  entry {}: {}", i, error));
+            }
+        }
+        Response::Search(r) => format_search_results(&mut out, &r.results),
+        Response::MultiSearch(r) => {
+            for (i, r) in r.results.iter().enumerate() {
+                out.push(format!("Search {}:", i));
+                format_search_results(&mut out, &r.results);
+            }
+        }
+        Response::DeleteEntry(_) => out.push("Entry deleted".to_string()),
+        Response::GetEntry(r) => {
+            let mut attributes: Vec<_> = r.entry.iter().flatten().collect();
+            attributes.sort();
+            for (name, value) in attributes {
+                out.push(format!("{}: {}", name, value));
+            }
+        }
+        Response::ListDictionaries(r) => out.extend(r.dictionary_ids.iter().cloned()),
+        Response::DropDictionary(_) => out.push("Dictionary dropped".to_string()),
+        Response::DescribeDictionary(r) => {
+            for (name, search_type) in &r.attributes {
+                out.push(format!("{}: {}", name, search_type));
+            }
+        }
+        Response::Save(r) if r.saved.is_empty() => out.push("Nothing to save".to_string()),
+        Response::Save(r) => out.push(format!("Saved {}", r.saved.join(", "))),
+        Response::Stats(r) => out.push(r.metrics.trim_end().to_string()),
+        Response::Auth(_) => out.push("Authenticated".to_string()),
+        Response::PermissionDenied(_) | Response::Error(_) => {}
+    }
+    out.join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::command::SearchResponse;
+
+    fn words(line: &str) -> Vec<String> {
+        split_line(line).unwrap()
+    }
+
+    #[test]
+    fn test_client_config_from_args() {
+        let args = words("--output json --codec msgpack search names John Doe");
+        let (config, command) = ClientConfig::from_args(args).unwrap();
+        assert_eq!(config.address, DEFAULT_ADDRESS);
+        assert_eq!(config.codec, Codec::MessagePack);
+        assert_eq!(config.output, Output::Json);
+        assert_eq!(command, words("search names John Doe"));
+
+        let (config, command) = ClientConfig::from_args(words("--token s3cr3t")).unwrap();
+        assert_eq!(config.token.as_deref(), Some("s3cr3t"));
+        assert!(command.is_empty());
+        assert!(ClientConfig::from_args(words("--codec xml")).is_err());
+        assert!(ClientConfig::from_args(words("--address")).is_err());
+    }
+
+    #[test]
+    fn test_split_line() {
+        assert_eq!(
+            words(r#"add names name="John Doe" id=1 note='it''s' """#),
+            vec!["add", "names", "name=John Doe", "id=1", "note=its", ""]
+        );
+        assert!(split_line("search names \"John").is_err());
+    }
+
+    #[test]
+    fn test_parse_command() {
+        let parse = |line: &str| CliCommand::parse(&words(line));
+        assert!(matches!(
+            parse("search names John Doe --limit 5").unwrap(),
+            CliCommand::Send(Command::Search(s))
+                if s.dictionary_id == "names" && s.term == "John Doe" && s.limit == Some(5)
+        ));
+        let CliCommand::Send(Command::CreateDictionary(create)) =
+            parse("create names name:multiple id:none --max-search-results 20").unwrap()
+        else {
+            panic!("expected create");
+        };
+        assert_eq!(
+            create.attributes,
+            vec![
+                ("name".to_string(), "multiple".to_string()),
+                ("id".to_string(), "none".to_string())
+            ]
+        );
+        assert_eq!(create.max_search_results, Some(20));
+        assert!(matches!(
+            parse("load names people.csv").unwrap(),
+            CliCommand::Load { dictionary_id, path, batch_size }
+                if dictionary_id == "names" && path == Path::new("people.csv") && batch_size == DEFAULT_BATCH_SIZE
+        ));
+        assert!(matches!(parse("").unwrap(), CliCommand::Help));
+        assert!(matches!(parse("exit").unwrap(), CliCommand::Quit));
+        assert!(matches!(
+            parse("stats").unwrap(),
+            CliCommand::Send(Command::Stats)
+        ));
+
+        assert_eq!(
+            parse("get names first").unwrap_err(),
+            "Invalid dictionary index 'first'"
+        );
+        assert!(parse("search names").is_err());
+        assert!(parse("search names John --limit").is_err());
+        assert!(parse("delete names 1 --limit 5").is_err());
+        assert!(parse("add names name").is_err());
+        assert!(parse("frobnicate").is_err());
+    }
+
+    #[test]
+    fn test_load_entries() {
+        let dir = std::env::temp_dir().join(format!("netexample-cli-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let csv_path = dir.join("people.csv");
+        std::fs::write(&csv_path, "name,id\n\"Doe, John\",1\nJane Doe,2\n").unwrap();
+        let entries = load_entries(&csv_path).unwrap();
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[0]["name"], "Doe, John");
+        assert_eq!(entries[1]["id"], "2");
+
+        let jsonl_path = dir.join("people.jsonl");
+        std::fs::write(
+            &jsonl_path,
+            "{\"name\": \"John Doe\", \"id\": 1}\n\n{\"name\": \"Jane Doe\", \"id\": \"2\"}\n",
+        )
+        .unwrap();
+        let entries = load_entries(&jsonl_path).unwrap();
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[0]["id"], "1");
+        assert_eq!(entries[1]["name"], "Jane Doe");
+
+        std::fs::write(&jsonl_path, "{\"name\": \"John Doe\"}\n[1, 2]\n").unwrap();
+        let e = load_entries(&jsonl_path).unwrap_err();
+        assert!(e.to_string().contains("line 2"), "{}", e);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_format_response() {
+        let response = Response::Search(SearchResponse {
+            results: vec![SearchResultItem {
+                term: "john".to_string(),
+                attribute: "name".to_string(),
+                original_entry: "John Doe".to_string(),
+                dictionary_id: "names".to_string(),
+                dictionary_index: 3,
+            }],
+            error: None,
+        });
+        assert_eq!(
+            format_response(&response, Output::Pretty),
+            "       3  name: John Doe\n1 result"
+        );
+        assert!(format_response(&response, Output::Json).starts_with("{\"Search\":"));
+
+        let response = Response::Search(SearchResponse {
+            results: vec![],
+            error: Some("Dictionary 'x' not found".to_string()),
+        });
+        assert_eq!(
+            format_response(&response, Output::Pretty),
+            "Error: Dictionary 'x' not found"
+        );
+    }
+}
@@ -0,0 +1,123 @@
+use crate::command::{AddDictionaryEntriesCommand, Command, Response};
+use crate::listener::MAX_IN_FLIGHT;
+use crate::protocol::{
+    read_frame, write_frame, Codec, Hello, HelloResponse, Request, ResponseFrame, PROTOCOL_VERSION,
+};
+use std::collections::{HashMap, VecDeque};
+use std::io::{self, BufReader, BufWriter};
+use std::net::{TcpStream, ToSocketAddrs};
+
+/// Blocking client for the framed protocol. Commands can be pipelined with `submit`,
+/// and their responses collected with `wait` in any order.
+pub struct Client {
+    reader: BufReader<TcpStream>,
+    writer: BufWriter<TcpStream>,
+    codec: Codec,
+    next_id: u64,
+    pending: HashMap<u64, Response>, // responses that arrived before they were waited for
+}
+
+fn invalid_data(e: impl ToString) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, e.to_string())
+}
+
+impl Client {
+    pub fn connect(address: impl ToSocketAddrs) -> io::Result<Self> {
+        Self::connect_with_codec(address, Codec::Json)
+    }
+
+    pub fn connect_with_codec(address: impl ToSocketAddrs, codec: Codec) -> io::Result<Self> {
+        let stream = TcpStream::connect(address)?;
+        let mut client = Client {
+            reader: BufReader::new(stream.try_clone()?),
+            writer: BufWriter::new(stream),
+            codec: Codec::Json, // the handshake is always in JSON
+            next_id: 0,
+            pending: HashMap::new(),
+        };
+        let hello = Hello {
+            versions: vec![PROTOCOL_VERSION],
+            codecs: vec![codec],
+        };
+        write_frame(&mut client.writer, &client.codec.encode(&hello)?)?;
+        let response: HelloResponse = client.read()?;
+        if response.version.is_none() {
+            return Err(invalid_data(response.error.unwrap_or_default()));
+        }
+        client.codec = response.codec;
+        Ok(client)
+    }
+
+    fn read<T: serde::de::DeserializeOwned>(&mut self) -> io::Result<T> {
+        let payload = read_frame(&mut self.reader)?
+            .ok_or_else(|| io::Error::from(io::ErrorKind::UnexpectedEof))?;
+        self.codec.decode(&payload)
+    }
+
+    /// Sends the command without waiting for the response, returns the request id
+    pub fn submit(&mut self, command: Command) -> io::Result<u64> {
+        let id = self.next_id;
+        self.next_id += 1;
+        let request = Request { id, command };
+        write_frame(&mut self.writer, &self.codec.encode(&request)?)?;
+        Ok(id)
+    }
+
+    pub fn wait(&mut self, id: u64) -> io::Result<Response> {
+        loop {
+            if let Some(response) = self.pending.remove(&id) {
+                return Ok(response);
+            }
+            let frame: ResponseFrame = self.read()?;
+            match frame.id {
+                Some(frame_id) => {
+                    self.pending.insert(frame_id, frame.response);
+                }
+                None => return Err(invalid_data(format!("{:?}", frame.response))),
+            }
+        }
+    }
+
+    pub fn send(&mut self, command: Command) -> io::Result<Response> {
+        let id = self.submit(command)?;
+        self.wait(id)
+    }
+
+    /// Adds the entries in pipelined batches. At most MAX_IN_FLIGHT batches wait for their responses,
+    /// the server stops reading a connection with more requests running, and a client that doesn't read
+    /// the responses meanwhile would block the server's writes, and itself.
+    /// Returns the numbers of added and failed entries, or the response of a batch that failed as a whole.
+    pub fn add_entries(
+        &mut self,
+        dictionary_id: &str,
+        entries: Vec<HashMap<String, String>>,
+        batch_size: usize,
+    ) -> io::Result<Result<(usize, usize), Response>> {
+        let mut entries = entries.into_iter().peekable();
+        let mut waiting = VecDeque::new();
+        let (mut added, mut failed) = (0, 0);
+        loop {
+            if waiting.len() < MAX_IN_FLIGHT && entries.peek().is_some() {
+                let batch = entries.by_ref().take(batch_size.max(1)).collect();
+                waiting.push_back(self.submit(Command::AddDictionaryEntries(
+                    AddDictionaryEntriesCommand {
+                        dictionary_id: dictionary_id.to_string(),
+                        entries: batch,
+                    },
+                ))?);
+                continue;
+            }
+            let Some(id) = waiting.pop_front() else {
+                return Ok(Ok((added, failed)));
+            };
+            match self.wait(id)? {
+                Response::AddDictionaryEntries(r) if r.error.is_none() => {
+                    let errors = r.results.iter().filter(|r| r.error.is_some()).count();
+                    added += r.results.len() - errors;
+                    failed += errors;
+                }
+                response => return Ok(Err(response)),
+            }
+        }
+    }
+}
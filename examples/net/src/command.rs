@@ -7,24 +7,80 @@ pub struct AddDictionaryEntryCommand {
     pub entry_data: HashMap<String, String>,
 }
 
+#[derive(Debug, Serialize, Deserialize)]
+pub struct AddDictionaryEntriesCommand {
+    pub dictionary_id: String,
+    pub entries: Vec<HashMap<String, String>>,
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct SearchCommand {
     pub dictionary_id: String,
     pub term: String,
+    #[serde(default)]
+    pub limit: Option<usize>, // overrides max_search_results of the dictionary, up to the server limit
 }
 
 #[derive(Debug, Serialize, Deserialize)]
+pub struct MultiSearchCommand {
+    pub searches: Vec<SearchCommand>, // each search can target a different dictionary
+}
+
+// The search settings not given keep their default values
+#[derive(Debug, Default, Serialize, Deserialize)]
 pub struct CreateDictionaryCommand {
     pub dictionary_id: String,
     pub attributes: Vec<(String, String)>, // (attribute_name, search_type)
+    #[serde(default)]
+    pub max_search_results: Option<usize>,
+    #[serde(default)]
+    pub min_term_length: Option<usize>,
+    #[serde(default)]
+    pub max_direct_entries: Option<usize>,
+    #[serde(default)]
+    pub default_multiple_search_length: Option<usize>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct DeleteEntryCommand {
+    pub dictionary_id: String,
+    pub dictionary_index: usize,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct GetEntryCommand {
+    pub dictionary_id: String,
+    pub dictionary_index: usize,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct DropDictionaryCommand {
+    pub dictionary_id: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct DescribeDictionaryCommand {
+    pub dictionary_id: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct AuthCommand {
+    pub token: String,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct AddDictionaryEntryResponse {
     pub success: bool,
+    pub dictionary_index: Option<usize>,
     pub error: Option<String>,
 }
 
+#[derive(Debug, Serialize, Deserialize)]
+pub struct AddDictionaryEntriesResponse {
+    pub results: Vec<AddDictionaryEntryResponse>, // one per entry, in the order of the command
+    pub error: Option<String>,                    // set when the whole command failed
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct SearchResponse {
     pub results: Vec<SearchResultItem>,
@@ -37,6 +93,12 @@ pub struct SearchResultItem {
     pub attribute: String,
     pub original_entry: String,
     pub dictionary_id: String,
+    pub dictionary_index: usize,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct MultiSearchResponse {
+    pub results: Vec<SearchResponse>, // one per search, in the order of the command
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -45,18 +107,107 @@ pub struct CreateDictionaryResponse {
     pub error: Option<String>,
 }
 
+#[derive(Debug, Serialize, Deserialize)]
+pub struct DeleteEntryResponse {
+    pub success: bool,
+    pub error: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct GetEntryResponse {
+    pub entry: Option<HashMap<String, String>>,
+    pub error: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ListDictionariesResponse {
+    pub dictionary_ids: Vec<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct DropDictionaryResponse {
+    pub success: bool,
+    pub error: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct DescribeDictionaryResponse {
+    pub attributes: Vec<(String, String)>, // (attribute_name, search_type)
+    pub error: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SaveResponse {
+    pub success: bool,
+    pub saved: Vec<String>, // ids of the dictionaries written or removed, the unchanged ones are skipped
+    pub error: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct StatsResponse {
+    pub metrics: String, // Prometheus text exposition, with the dictionary id as the `dictionary` label
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct AuthResponse {
+    pub success: bool,
+    pub error: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ErrorResponse {
+    pub error: String,
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 pub enum Command {
     CreateDictionary(CreateDictionaryCommand),
     AddDictionaryEntry(AddDictionaryEntryCommand),
+    AddDictionaryEntries(AddDictionaryEntriesCommand),
     Search(SearchCommand),
+    MultiSearch(MultiSearchCommand),
+    DeleteEntry(DeleteEntryCommand),
+    GetEntry(GetEntryCommand),
+    ListDictionaries,
+    DropDictionary(DropDictionaryCommand),
+    DescribeDictionary(DescribeDictionaryCommand),
+    Save,
+    Stats,
+    Auth(AuthCommand),
+}
+
+impl Command {
+    /// Whether the command changes the dictionaries or their snapshots
+    pub fn mutates(&self) -> bool {
+        matches!(
+            self,
+            Command::CreateDictionary(_)
+                | Command::AddDictionaryEntry(_)
+                | Command::AddDictionaryEntries(_)
+                | Command::DeleteEntry(_)
+                | Command::DropDictionary(_)
+                | Command::Save
+        )
+    }
 }
 
 #[derive(Debug, Serialize, Deserialize)]
 pub enum Response {
     CreateDictionary(CreateDictionaryResponse),
     AddDictionaryEntry(AddDictionaryEntryResponse),
+    AddDictionaryEntries(AddDictionaryEntriesResponse),
     Search(SearchResponse),
+    MultiSearch(MultiSearchResponse),
+    DeleteEntry(DeleteEntryResponse),
+    GetEntry(GetEntryResponse),
+    ListDictionaries(ListDictionariesResponse),
+    DropDictionary(DropDictionaryResponse),
+    DescribeDictionary(DescribeDictionaryResponse),
+    Save(SaveResponse),
+    Stats(StatsResponse),
+    Auth(AuthResponse),
+    PermissionDenied(ErrorResponse),
+    Error(ErrorResponse),
 }
 
 pub trait CommandHandler {
@@ -1,3 +1,8 @@
 pub mod access;
+pub mod auth;
+pub mod cli;
+pub mod client;
 pub mod command;
 pub mod listener;
+pub mod persistence;
+pub mod protocol;
@@ -1,105 +1,1317 @@
-use crate::access::DictionaryMap;
-use crate::command::Command::{AddDictionaryEntry, CreateDictionary, Search};
+use crate::access::{
+    parse_search_type, search_type_name, DictionaryMap, DEFAULT_MAX_SEARCH_RESULTS,
+};
+use crate::auth::{authorize, filter_response, AuthConfig, Grants};
+use crate::command::Command::{
+    AddDictionaryEntries, AddDictionaryEntry, Auth, CreateDictionary, DeleteEntry,
+    DescribeDictionary, DropDictionary, GetEntry, ListDictionaries, MultiSearch, Save, Search,
+    Stats,
+};
+use crate::command::ErrorResponse;
 use crate::command::{
-    AddDictionaryEntryResponse, Command, CreateDictionaryResponse, Response, SearchResponse,
-    SearchResultItem,
+    AddDictionaryEntriesResponse, AddDictionaryEntryResponse, AuthResponse, Command,
+    CreateDictionaryResponse, DeleteEntryResponse, DescribeDictionaryResponse,
+    DropDictionaryResponse, GetEntryResponse, ListDictionariesResponse, MultiSearchResponse,
+    Response, SaveResponse, SearchCommand, SearchResponse, SearchResultItem, StatsResponse,
+};
+use crate::protocol::{
+    negotiate_codec, negotiate_version, read_frame_async, write_frame_async, Hello, HelloResponse,
+    Request, ResponseFrame, PROTOCOL_VERSION,
 };
+use dictionary_bitmap_trie::constants::SearchConfig;
 use dictionary_bitmap_trie::dictionary::AttributeSearch;
-use serde::{Deserialize, Serialize};
-use std::io::{BufReader, Write};
-use std::net::{TcpListener, TcpStream};
-use std::sync::{Arc, RwLock};
-use std::thread;
+use std::future::Future;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::io::{AsyncWriteExt, BufReader, BufWriter};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::{mpsc, watch, Semaphore};
+use tokio::task::JoinSet;
+
+// requests of one connection running at once, reading from the connection waits when reached
+pub const MAX_IN_FLIGHT: usize = 64;
+
+pub struct ServerConfig {
+    pub address: String,
+    pub port: u16,
+    pub max_connections: usize,
+    pub request_timeout: Duration,
+    pub max_search_results: usize, // ceiling for the limits of dictionaries and search requests
+    pub data_dir: Option<PathBuf>, // where the dictionaries are saved, None to keep them only in memory
+    pub snapshot_interval: Duration,
+    pub auth_file: Option<PathBuf>, // tokens and their permissions, None to allow everything to everyone
+}
+
+impl Default for ServerConfig {
+    fn default() -> Self {
+        Self {
+            address: "127.0.0.1".to_string(),
+            port: 4444,
+            max_connections: 1024,
+            request_timeout: Duration::from_secs(5),
+            max_search_results: DEFAULT_MAX_SEARCH_RESULTS,
+            data_dir: None,
+            snapshot_interval: Duration::from_secs(60),
+            auth_file: None,
+        }
+    }
+}
+
+impl ServerConfig {
+    /// Parses `--address`, `--port`, `--max-connections`, `--request-timeout-ms`, `--max-search-results`,
+    /// `--data-dir`, `--snapshot-interval-secs` and `--auth-file` options,
+    /// everything not given keeps the default value
+    pub fn from_args(args: impl IntoIterator<Item = String>) -> Result<Self, String> {
+        let mut config = ServerConfig::default();
+        let mut args = args.into_iter();
+        while let Some(arg) = args.next() {
+            let value = args
+                .next()
+                .ok_or_else(|| format!("Missing value for option '{}'", arg))?;
+            let invalid = |_| format!("Invalid value '{}' for option '{}'", value, arg);
+            match arg.as_str() {
+                "--address" => config.address = value.clone(),
+                "--port" => config.port = value.parse().map_err(invalid)?,
+                "--max-connections" => config.max_connections = value.parse().map_err(invalid)?,
+                "--request-timeout-ms" => {
+                    config.request_timeout = Duration::from_millis(value.parse().map_err(invalid)?)
+                }
+                "--max-search-results" => {
+                    config.max_search_results = value.parse().map_err(invalid)?
+                }
+                "--data-dir" => config.data_dir = Some(PathBuf::from(&value)),
+                "--auth-file" => config.auth_file = Some(PathBuf::from(&value)),
+                "--snapshot-interval-secs" => {
+                    config.snapshot_interval = Duration::from_secs(value.parse().map_err(invalid)?)
+                }
+                _ => return Err(format!("Unknown option '{}'", arg)),
+            }
+        }
+        Ok(config)
+    }
+}
+
+pub async fn listen(config: ServerConfig) -> std::io::Result<()> {
+    let listener = TcpListener::bind((config.address.as_str(), config.port)).await?;
+    println!("Listening on {}", listener.local_addr()?);
+    serve(listener, &config, shutdown_signal()).await
+}
 
-pub fn listen() -> std::io::Result<()> {
-    let listener = TcpListener::bind("127.0.0.1:4444")?;
+/// Accepts connections until the shutdown future completes, then stops reading new commands
+/// and waits for the commands in flight to be answered.
+/// With a data directory, the dictionaries are restored before accepting connections,
+/// and saved every snapshot interval and once more on shutdown.
+pub async fn serve(
+    listener: TcpListener,
+    config: &ServerConfig,
+    shutdown: impl Future<Output = ()>,
+) -> std::io::Result<()> {
+    let mut dict_map = DictionaryMap::with_max_search_results(config.max_search_results);
+    if let Some(data_dir) = &config.data_dir {
+        dict_map = dict_map.with_data_dir(data_dir.clone());
+    }
+    let arc_dict_map = Arc::new(dict_map);
+    if let Some(data_dir) = &config.data_dir {
+        let dict_map = Arc::clone(&arc_dict_map);
+        let restored = tokio::task::spawn_blocking(move || dict_map.restore())
+            .await?
+            .map_err(std::io::Error::other)?;
+        println!(
+            "Restored {} dictionaries from {}",
+            restored,
+            data_dir.display()
+        );
+    }
+    let auth = match &config.auth_file {
+        Some(auth_file) => Some(Arc::new(AuthConfig::load(auth_file)?)),
+        None => None,
+    };
+    // a connection holds a permit until it is closed, accepting waits when all are taken
+    let connections = Arc::new(Semaphore::new(config.max_connections));
+    let (shutdown_tx, shutdown_rx) = watch::channel(false);
+    let mut tasks = JoinSet::new();
+    tokio::pin!(shutdown);
 
-    let dict_map = crate::access::DictionaryMap::new();
-    let arc_dict_map = Arc::new(RwLock::new(dict_map));
+    let snapshots = (config.data_dir.is_some() && !config.snapshot_interval.is_zero()).then(|| {
+        let dict_map = Arc::clone(&arc_dict_map);
+        let mut shutdown_rx = shutdown_rx.clone();
+        let period = config.snapshot_interval;
+        tokio::spawn(async move {
+            let mut interval =
+                tokio::time::interval_at(tokio::time::Instant::now() + period, period);
+            loop {
+                tokio::select! {
+                    _ = interval.tick() => save_snapshot(&dict_map).await,
+                    _ = shutdown_rx.changed() => break,
+                }
+            }
+        })
+    });
 
-    // accept connections and process them serially
-    for res_stream in listener.incoming() {
+    loop {
+        let permit = tokio::select! {
+            permit = Arc::clone(&connections).acquire_owned() => permit.unwrap(),
+            _ = &mut shutdown => break,
+        };
+        let stream = tokio::select! {
+            res = listener.accept() => match res {
+                Ok((stream, _)) => stream,
+                Err(e) => {
+                    eprintln!("Failed to accept connection: {}", e);
+                    // e.g. out of file descriptors, give the open connections a chance to close
+                    tokio::time::sleep(Duration::from_millis(100)).await;
+                    continue;
+                }
+            },
+            _ = &mut shutdown => break,
+        };
         let arc_clone = Arc::clone(&arc_dict_map);
-        thread::spawn(move || match res_stream {
-            Ok(stream) => handle_client(stream, &arc_clone),
-            Err(e) => panic!("{:?}", e),
+        let auth = auth.clone();
+        let request_timeout = config.request_timeout;
+        let shutdown_rx = shutdown_rx.clone();
+        tasks.spawn(async move {
+            if let Err(e) =
+                handle_client(stream, arc_clone, auth, request_timeout, shutdown_rx).await
+            {
+                eprintln!("Connection error: {}", e);
+            }
+            drop(permit);
         });
+        while tasks.try_join_next().is_some() {}
+    }
+
+    shutdown_tx.send_replace(true);
+    while tasks.join_next().await.is_some() {}
+    if let Some(snapshots) = snapshots {
+        snapshots.await?;
+    }
+    if config.data_dir.is_some() {
+        save_snapshot(&arc_dict_map).await;
     }
     Ok(())
 }
 
-fn handle_client(stream: TcpStream, cloned_arc: &Arc<RwLock<DictionaryMap>>) {
-    let reader = BufReader::new(&stream);
-    let mut deserializer = serde_json::Deserializer::from_reader(reader);
-    // This allows handling multiple commands sent over the same connection
-    while let Ok(command) = Command::deserialize(&mut deserializer) {
-        let response = match command {
-            CreateDictionary(create_dict) => {
-                let mut dict_map = cloned_arc.write().unwrap();
-                let attributes: Vec<(String, AttributeSearch)> = create_dict
-                    .attributes
-                    .into_iter()
-                    .map(|(name, search_type)| {
-                        let attr_search = match search_type.to_lowercase().as_str() {
-                            "exact" => AttributeSearch::Exact,
-                            "multiple" => AttributeSearch::Multiple,
-                            _ => AttributeSearch::None,
-                        };
-                        (name, attr_search)
-                    })
-                    .collect();
-
-                match dict_map.create_dictionary(&create_dict.dictionary_id, attributes) {
-                    Ok(_) => Response::CreateDictionary(CreateDictionaryResponse {
-                        success: true,
-                        error: None,
-                    }),
-                    Err(e) => Response::CreateDictionary(CreateDictionaryResponse {
-                        success: false,
-                        error: Some(e),
-                    }),
-                }
+async fn save_snapshot(dict_map: &Arc<DictionaryMap>) {
+    let dict_map = Arc::clone(dict_map);
+    match tokio::task::spawn_blocking(move || dict_map.save()).await {
+        Ok(Ok(_)) => (),
+        Ok(Err(e)) => eprintln!("{}", e),
+        Err(e) => eprintln!("Snapshot failed: {}", e),
+    }
+}
+
+async fn shutdown_signal() {
+    let ctrl_c = async {
+        tokio::signal::ctrl_c()
+            .await
+            .expect("Failed to listen for ctrl-c");
+    };
+    #[cfg(unix)]
+    let terminate = async {
+        tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate())
+            .expect("Failed to listen for SIGTERM")
+            .recv()
+            .await;
+    };
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
+    tokio::select! {
+        _ = ctrl_c => {},
+        _ = terminate => {},
+    }
+}
+
+async fn handle_client(
+    stream: TcpStream,
+    cloned_arc: Arc<DictionaryMap>,
+    auth: Option<Arc<AuthConfig>>,
+    request_timeout: Duration,
+    mut shutdown: watch::Receiver<bool>,
+) -> std::io::Result<()> {
+    let (reader, writer) = stream.into_split();
+    let mut reader = BufReader::new(reader);
+    let mut writer = BufWriter::new(writer);
+
+    let hello = tokio::select! {
+        frame = read_frame_async(&mut reader) => frame?,
+        _ = shutdown.changed() => return Ok(()),
+    };
+    let Some(hello) = hello else {
+        return Ok(());
+    };
+    let hello = serde_json::from_slice::<Hello>(&hello).ok();
+    let version = hello.as_ref().and_then(negotiate_version);
+    let codec = hello.as_ref().map(negotiate_codec).unwrap_or_default();
+    let hello_response = HelloResponse {
+        version,
+        codec,
+        error: version.is_none().then(|| {
+            format!(
+                "Unsupported protocol version, the server supports version {}",
+                PROTOCOL_VERSION
+            )
+        }),
+    };
+    write_frame_async(&mut writer, &serde_json::to_vec(&hello_response)?).await?;
+    writer.flush().await?;
+    if version.is_none() {
+        return Ok(());
+    }
+
+    // Reads run concurrently, and are answered as soon as they complete, possibly out of order.
+    // The changes run one at a time in the order of the requests, so the requests after a change see it
+    let (tx, mut rx) = mpsc::channel::<ResponseFrame>(MAX_IN_FLIGHT);
+    let mut writer_task = tokio::spawn(async move {
+        while let Some(frame) = rx.recv().await {
+            write_frame_async(&mut writer, &codec.encode(&frame)?).await?;
+            if rx.is_empty() {
+                writer.flush().await?;
             }
-            AddDictionaryEntry(add_entry) => {
-                let mut dict_map = cloned_arc.write().unwrap();
-                match dict_map.add_entry(&add_entry.dictionary_id, add_entry.entry_data) {
-                    Ok(_) => Response::AddDictionaryEntry(AddDictionaryEntryResponse {
-                        success: true,
-                        error: None,
-                    }),
-                    Err(e) => Response::AddDictionaryEntry(AddDictionaryEntryResponse {
-                        success: false,
-                        error: Some(e),
-                    }),
+        }
+        writer.flush().await
+    });
+    let mut in_flight = JoinSet::new();
+    // with an auth file, nothing is allowed until the client authenticates
+    let mut grants: Option<Grants> = None;
+    loop {
+        if in_flight.len() >= MAX_IN_FLIGHT {
+            tokio::select! {
+                _ = in_flight.join_next() => {}
+                _ = shutdown.changed() => break,
+            }
+        }
+        let frame = tokio::select! {
+            frame = read_frame_async(&mut reader) => frame,
+            _ = shutdown.changed() => break,
+        };
+        let payload = match frame {
+            Ok(Some(payload)) => payload,
+            Ok(None) => break,
+            Err(e) if e.kind() == std::io::ErrorKind::InvalidData => {
+                // the payload of the frame was skipped, the next frame can be read
+                let response = error_frame(None, format!("Malformed frame: {}", e));
+                if !send(&tx, response, &mut shutdown).await {
+                    break;
                 }
+                continue;
             }
-            Search(search_command) => {
-                let dict_map = cloned_arc.read().unwrap();
-                match dict_map.search(&search_command.dictionary_id, &search_command.term) {
-                    Ok(results) => {
-                        let search_results: Vec<SearchResultItem> = results
-                            .into_iter()
-                            .map(|sr| SearchResultItem {
-                                term: sr.term,
-                                attribute: sr.attribute,
-                                original_entry: sr.original_entry,
-                                dictionary_id: search_command.dictionary_id.clone(),
+            Err(_) => break,
+        };
+        match codec.decode::<Request>(&payload) {
+            Ok(Request { id, command }) => {
+                // Auth is answered in order, so the grants apply to all the requests after it
+                let immediate = match (&auth, &command) {
+                    (Some(auth), Command::Auth(auth_command)) => {
+                        grants = auth.authenticate(&auth_command.token).cloned();
+                        Some(Response::Auth(AuthResponse {
+                            success: grants.is_some(),
+                            error: grants.is_none().then(|| "Invalid token".to_string()),
+                        }))
+                    }
+                    (Some(_), _) => authorize(grants.as_ref(), &command)
+                        .err()
+                        .map(|error| Response::PermissionDenied(ErrorResponse { error })),
+                    (None, _) => None,
+                };
+                if let Some(response) = immediate {
+                    let frame = ResponseFrame {
+                        id: Some(id),
+                        response,
+                    };
+                    if !send(&tx, frame, &mut shutdown).await {
+                        break;
+                    }
+                } else {
+                    let mutates = command.mutates();
+                    if mutates && !drain(&mut in_flight, &mut shutdown).await {
+                        break;
+                    }
+                    let arc_clone = Arc::clone(&cloned_arc);
+                    let tx = tx.clone();
+                    let grants = grants.clone();
+                    in_flight.spawn(async move {
+                        let mut response = execute(&arc_clone, command, request_timeout).await;
+                        if let Some(grants) = &grants {
+                            filter_response(grants, &mut response);
+                        }
+                        let _ = tx
+                            .send(ResponseFrame {
+                                id: Some(id),
+                                response,
                             })
-                            .collect();
-                        Response::Search(SearchResponse {
-                            results: search_results,
-                            error: None,
-                        })
+                            .await;
+                    });
+                    if mutates && !drain(&mut in_flight, &mut shutdown).await {
+                        break;
                     }
-                    Err(e) => Response::Search(SearchResponse {
-                        results: vec![],
-                        error: Some(e),
-                    }),
                 }
             }
+            Err(e) => {
+                let response = error_frame(
+                    codec.request_id(&payload),
+                    format!("Malformed request: {}", e),
+                );
+                if !send(&tx, response, &mut shutdown).await {
+                    break;
+                }
+            }
+        }
+        while in_flight.try_join_next().is_some() {}
+    }
+
+    // The requests in flight are answered before the connection is closed. A client that doesn't
+    // read its responses would hold them forever, so on shutdown they get the request timeout
+    let finished = {
+        let finish = async {
+            while in_flight.join_next().await.is_some() {}
+            drop(tx);
+            (&mut writer_task).await
         };
-        let mut serializer = serde_json::Serializer::new(&stream);
-        response.serialize(&mut serializer).unwrap();
-        serializer.into_inner().flush().unwrap();
+        tokio::pin!(finish);
+        if !*shutdown.borrow() {
+            tokio::select! {
+                res = &mut finish => return res?,
+                _ = shutdown.changed() => {}
+            }
+        }
+        tokio::time::timeout(request_timeout, finish).await
+    };
+    match finished {
+        Ok(res) => res?,
+        Err(_) => {
+            writer_task.abort();
+            Ok(())
+        }
+    }
+}
+
+// Queues the response for the writer, false when the server shuts down first
+async fn send(
+    tx: &mpsc::Sender<ResponseFrame>,
+    frame: ResponseFrame,
+    shutdown: &mut watch::Receiver<bool>,
+) -> bool {
+    tokio::select! {
+        _ = tx.send(frame) => true,
+        _ = shutdown.changed() => false,
+    }
+}
+
+// Waits for the requests in flight, false when the server shuts down first
+async fn drain(in_flight: &mut JoinSet<()>, shutdown: &mut watch::Receiver<bool>) -> bool {
+    tokio::select! {
+        _ = async { while in_flight.join_next().await.is_some() {} } => true,
+        _ = shutdown.changed() => false,
+    }
+}
+
+fn error_frame(id: Option<u64>, error: String) -> ResponseFrame {
+    ResponseFrame {
+        id,
+        response: Response::Error(ErrorResponse { error }),
+    }
+}
+
+// The dictionaries are guarded by std locks and searching is CPU bound, so the commands
+// run on the blocking pool instead of the async workers.
+// A blocking task can't be cancelled, a timed out command would still run to the end. So the
+// mutating commands are always waited for, the client gets their outcome and doesn't retry a
+// change that was applied
+async fn execute(
+    cloned_arc: &Arc<DictionaryMap>,
+    command: Command,
+    request_timeout: Duration,
+) -> Response {
+    let mutates = command.mutates();
+    let arc_clone = Arc::clone(cloned_arc);
+    let task = tokio::task::spawn_blocking(move || handle_command(&arc_clone, command));
+    let joined = if mutates {
+        Ok(task.await)
+    } else {
+        tokio::time::timeout(request_timeout, task).await
+    };
+    match joined {
+        Ok(Ok(response)) => response,
+        Ok(Err(e)) => Response::Error(ErrorResponse {
+            error: format!("Command failed: {}", e),
+        }),
+        Err(_) => Response::Error(ErrorResponse {
+            error: format!("Command timed out after {} ms", request_timeout.as_millis()),
+        }),
+    }
+}
+
+pub fn handle_command(dict_map: &DictionaryMap, command: Command) -> Response {
+    match command {
+        CreateDictionary(create_dict) => {
+            let attributes: Result<Vec<(String, AttributeSearch)>, String> = create_dict
+                .attributes
+                .into_iter()
+                .map(|(name, search_type)| match parse_search_type(&search_type) {
+                    Some(attr_search) => Ok((name, attr_search)),
+                    None => Err(format!(
+                        "Unknown search type '{}' for attribute '{}', expected exact, multiple or none",
+                        search_type, name
+                    )),
+                })
+                .collect();
+            let defaults = SearchConfig::default();
+            let config = SearchConfig {
+                max_search_results: create_dict
+                    .max_search_results
+                    .unwrap_or(defaults.max_search_results),
+                min_term_length: create_dict
+                    .min_term_length
+                    .unwrap_or(defaults.min_term_length),
+                max_direct_entries: create_dict
+                    .max_direct_entries
+                    .unwrap_or(defaults.max_direct_entries),
+                default_multiple_search_length: create_dict
+                    .default_multiple_search_length
+                    .unwrap_or(defaults.default_multiple_search_length),
+                ..defaults
+            };
+
+            match attributes.and_then(|attributes| {
+                dict_map.create_dictionary(&create_dict.dictionary_id, attributes, config)
+            }) {
+                Ok(_) => Response::CreateDictionary(CreateDictionaryResponse {
+                    success: true,
+                    error: None,
+                }),
+                Err(e) => Response::CreateDictionary(CreateDictionaryResponse {
+                    success: false,
+                    error: Some(e),
+                }),
+            }
+        }
+        AddDictionaryEntry(add_entry) => Response::AddDictionaryEntry(add_entry_response(
+            dict_map.add_entry(&add_entry.dictionary_id, add_entry.entry_data),
+        )),
+        AddDictionaryEntries(add_entries) => {
+            match dict_map.add_entries(&add_entries.dictionary_id, add_entries.entries) {
+                Ok(results) => Response::AddDictionaryEntries(AddDictionaryEntriesResponse {
+                    results: results.into_iter().map(add_entry_response).collect(),
+                    error: None,
+                }),
+                Err(e) => Response::AddDictionaryEntries(AddDictionaryEntriesResponse {
+                    results: vec![],
+                    error: Some(e),
+                }),
+            }
+        }
+        Search(search_command) => Response::Search(search_response(dict_map, search_command)),
+        MultiSearch(multi_search) => Response::MultiSearch(MultiSearchResponse {
+            results: multi_search
+                .searches
+                .into_iter()
+                .map(|search_command| search_response(dict_map, search_command))
+                .collect(),
+        }),
+        DeleteEntry(delete_entry) => {
+            match dict_map.delete_entry(&delete_entry.dictionary_id, delete_entry.dictionary_index)
+            {
+                Ok(_) => Response::DeleteEntry(DeleteEntryResponse {
+                    success: true,
+                    error: None,
+                }),
+                Err(e) => Response::DeleteEntry(DeleteEntryResponse {
+                    success: false,
+                    error: Some(e),
+                }),
+            }
+        }
+        GetEntry(get_entry) => {
+            match dict_map.get_entry(&get_entry.dictionary_id, get_entry.dictionary_index) {
+                Ok(entry) => Response::GetEntry(GetEntryResponse {
+                    entry: Some(entry),
+                    error: None,
+                }),
+                Err(e) => Response::GetEntry(GetEntryResponse {
+                    entry: None,
+                    error: Some(e),
+                }),
+            }
+        }
+        ListDictionaries => Response::ListDictionaries(ListDictionariesResponse {
+            dictionary_ids: dict_map.list_dictionaries(),
+        }),
+        DropDictionary(drop_dict) => match dict_map.drop_dictionary(&drop_dict.dictionary_id) {
+            Ok(_) => Response::DropDictionary(DropDictionaryResponse {
+                success: true,
+                error: None,
+            }),
+            Err(e) => Response::DropDictionary(DropDictionaryResponse {
+                success: false,
+                error: Some(e),
+            }),
+        },
+        // reaches here only when the server has no auth file, every connection is allowed everything
+        Auth(_) => Response::Auth(AuthResponse {
+            success: true,
+            error: None,
+        }),
+        Save => match dict_map.save() {
+            Ok(saved) => Response::Save(SaveResponse {
+                success: true,
+                saved,
+                error: None,
+            }),
+            Err(e) => Response::Save(SaveResponse {
+                success: false,
+                saved: vec![],
+                error: Some(e),
+            }),
+        },
+        Stats => Response::Stats(StatsResponse {
+            metrics: dict_map.metrics(),
+        }),
+        DescribeDictionary(describe_dict) => {
+            match dict_map.describe_dictionary(&describe_dict.dictionary_id) {
+                Ok(attributes) => Response::DescribeDictionary(DescribeDictionaryResponse {
+                    attributes: attributes
+                        .iter()
+                        .map(|(name, search)| (name.clone(), search_type_name(search).to_string()))
+                        .collect(),
+                    error: None,
+                }),
+                Err(e) => Response::DescribeDictionary(DescribeDictionaryResponse {
+                    attributes: vec![],
+                    error: Some(e),
+                }),
+            }
+        }
+    }
+}
+
+fn add_entry_response(result: Result<usize, String>) -> AddDictionaryEntryResponse {
+    match result {
+        Ok(dictionary_index) => AddDictionaryEntryResponse {
+            success: true,
+            dictionary_index: Some(dictionary_index),
+            error: None,
+        },
+        Err(e) => AddDictionaryEntryResponse {
+            success: false,
+            dictionary_index: None,
+            error: Some(e),
+        },
+    }
+}
+
+fn search_response(dict_map: &DictionaryMap, search_command: SearchCommand) -> SearchResponse {
+    match dict_map.search(
+        &search_command.dictionary_id,
+        &search_command.term,
+        search_command.limit,
+    ) {
+        Ok(results) => SearchResponse {
+            results: results
+                .into_iter()
+                .map(|sr| SearchResultItem {
+                    term: sr.term,
+                    attribute: sr.attribute,
+                    original_entry: sr.original_entry,
+                    dictionary_id: search_command.dictionary_id.clone(),
+                    dictionary_index: sr.dictionary_index,
+                })
+                .collect(),
+            error: None,
+        },
+        Err(e) => SearchResponse {
+            results: vec![],
+            error: Some(e),
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::client::Client;
+    use crate::command::{
+        AddDictionaryEntriesCommand, AddDictionaryEntryCommand, AuthCommand,
+        CreateDictionaryCommand, DescribeDictionaryCommand, GetEntryCommand, MultiSearchCommand,
+    };
+    use crate::protocol::Codec;
+    use serde::de::DeserializeOwned;
+    use serde::Serialize;
+    use std::collections::HashMap;
+    use tokio::sync::oneshot;
+
+    async fn start_server() -> (
+        std::net::SocketAddr,
+        oneshot::Sender<()>,
+        tokio::task::JoinHandle<std::io::Result<()>>,
+    ) {
+        start_server_with(ServerConfig::default()).await
+    }
+
+    async fn start_server_with(
+        config: ServerConfig,
+    ) -> (
+        std::net::SocketAddr,
+        oneshot::Sender<()>,
+        tokio::task::JoinHandle<std::io::Result<()>>,
+    ) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        let (shutdown_tx, shutdown_rx) = oneshot::channel::<()>();
+        let server = tokio::spawn(async move {
+            let shutdown = async {
+                shutdown_rx.await.ok();
+            };
+            serve(listener, &config, shutdown).await
+        });
+        (address, shutdown_tx, server)
+    }
+
+    async fn read<T: DeserializeOwned>(stream: &mut TcpStream) -> T {
+        let payload = read_frame_async(stream).await.unwrap().unwrap();
+        serde_json::from_slice(&payload).unwrap()
+    }
+
+    async fn write<T: Serialize>(stream: &mut TcpStream, message: &T) {
+        write_frame_async(stream, &serde_json::to_vec(message).unwrap())
+            .await
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_serve_and_shutdown() {
+        let (address, shutdown_tx, server) = start_server().await;
+        let mut stream = TcpStream::connect(address).await.unwrap();
+        write(
+            &mut stream,
+            &Hello {
+                versions: vec![PROTOCOL_VERSION],
+                codecs: vec![],
+            },
+        )
+        .await;
+        let hello: HelloResponse = read(&mut stream).await;
+        assert_eq!(hello.version, Some(PROTOCOL_VERSION));
+        assert_eq!(hello.codec, Codec::Json);
+
+        // pipelined, the malformed request in the middle doesn't close the connection, and the
+        // search runs after the changes before it
+        write(
+            &mut stream,
+            &Request {
+                id: 1,
+                command: Command::CreateDictionary(CreateDictionaryCommand {
+                    dictionary_id: "names".to_string(),
+                    attributes: vec![("name".to_string(), "multiple".to_string())],
+                    ..Default::default()
+                }),
+            },
+        )
+        .await;
+        write_frame_async(&mut stream, br#"{"id": 2, "command": "Bogus"}"#)
+            .await
+            .unwrap();
+        write(
+            &mut stream,
+            &Request {
+                id: 4,
+                command: Command::AddDictionaryEntry(AddDictionaryEntryCommand {
+                    dictionary_id: "names".to_string(),
+                    entry_data: HashMap::from([("name".to_string(), "John Doe".to_string())]),
+                }),
+            },
+        )
+        .await;
+        write(
+            &mut stream,
+            &Request {
+                id: 3,
+                command: Command::Search(SearchCommand {
+                    dictionary_id: "names".to_string(),
+                    term: "John".to_string(),
+                    limit: None,
+                }),
+            },
+        )
+        .await;
+        let mut responses = HashMap::new();
+        for _ in 0..4 {
+            let frame: ResponseFrame = read(&mut stream).await;
+            responses.insert(frame.id.unwrap(), frame.response);
+        }
+        assert!(matches!(&responses[&1], Response::CreateDictionary(r) if r.success));
+        assert!(matches!(&responses[&2], Response::Error(_)));
+        assert!(matches!(&responses[&4], Response::AddDictionaryEntry(r) if r.success));
+        assert!(
+            matches!(&responses[&3], Response::Search(r) if r.error.is_none() && r.results.len() == 1)
+        );
+
+        shutdown_tx.send(()).unwrap();
+        server.await.unwrap().unwrap();
+        // the connection is closed by the server on shutdown
+        assert!(read_frame_async(&mut stream).await.unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn test_frame_too_long() {
+        use crate::protocol::MAX_FRAME_LENGTH;
+        let (address, shutdown_tx, server) = start_server().await;
+        let mut stream = TcpStream::connect(address).await.unwrap();
+        write(
+            &mut stream,
+            &Hello {
+                versions: vec![PROTOCOL_VERSION],
+                codecs: vec![],
+            },
+        )
+        .await;
+        let _: HelloResponse = read(&mut stream).await;
+        let len = MAX_FRAME_LENGTH + 1;
+        let mut frame = (len as u32).to_be_bytes().to_vec();
+        frame.resize(4 + len, b' ');
+        stream.write_all(&frame).await.unwrap();
+        write(
+            &mut stream,
+            &Request {
+                id: 1,
+                command: Command::ListDictionaries,
+            },
+        )
+        .await;
+        let frame: ResponseFrame = read(&mut stream).await;
+        assert_eq!(frame.id, None);
+        assert!(matches!(frame.response, Response::Error(_)));
+        // the connection is still open, in sync with the frames after the skipped one
+        let frame: ResponseFrame = read(&mut stream).await;
+        assert_eq!(frame.id, Some(1));
+        assert!(matches!(frame.response, Response::ListDictionaries(_)));
+
+        shutdown_tx.send(()).unwrap();
+        server.await.unwrap().unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_unsupported_version() {
+        let (address, shutdown_tx, server) = start_server().await;
+        let mut stream = TcpStream::connect(address).await.unwrap();
+        write(
+            &mut stream,
+            &Hello {
+                versions: vec![PROTOCOL_VERSION + 1],
+                codecs: vec![],
+            },
+        )
+        .await;
+        let hello: HelloResponse = read(&mut stream).await;
+        assert_eq!(hello.version, None);
+        assert!(hello.error.is_some());
+        assert!(read_frame_async(&mut stream).await.unwrap().is_none());
+        shutdown_tx.send(()).unwrap();
+        server.await.unwrap().unwrap();
+    }
+
+    fn run_commands(address: std::net::SocketAddr, codec: Codec) -> Vec<serde_json::Value> {
+        let mut client = Client::connect_with_codec(address, codec).unwrap();
+        let dictionary_id = format!("{:?}", codec);
+        let commands = vec![
+            Command::CreateDictionary(CreateDictionaryCommand {
+                dictionary_id: dictionary_id.clone(),
+                attributes: vec![
+                    ("name".to_string(), "multiple".to_string()),
+                    ("id".to_string(), "none".to_string()),
+                ],
+                ..Default::default()
+            }),
+            Command::AddDictionaryEntry(AddDictionaryEntryCommand {
+                dictionary_id: dictionary_id.clone(),
+                entry_data: HashMap::from([
+                    ("name".to_string(), "Dusan Milutinovic".to_string()),
+                    ("id".to_string(), "1".to_string()),
+                ]),
+            }),
+            Command::Search(SearchCommand {
+                dictionary_id: dictionary_id.clone(),
+                term: "Mil".to_string(),
+                limit: None,
+            }),
+            Command::GetEntry(GetEntryCommand {
+                dictionary_id: dictionary_id.clone(),
+                dictionary_index: 0,
+            }),
+            Command::DescribeDictionary(DescribeDictionaryCommand { dictionary_id }),
+        ];
+        commands
+            .into_iter()
+            .map(|c| {
+                let response = client.send(c).unwrap();
+                // the dictionary id differs between the codecs
+                let json = serde_json::to_string(&response).unwrap();
+                serde_json::from_str(&json.replace(&format!("{:?}", codec), "names")).unwrap()
+            })
+            .collect()
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_codecs_equivalent() {
+        let (address, shutdown_tx, server) = start_server().await;
+        let json = tokio::task::spawn_blocking(move || run_commands(address, Codec::Json));
+        let msgpack =
+            tokio::task::spawn_blocking(move || run_commands(address, Codec::MessagePack));
+        let json = json.await.unwrap();
+        assert_eq!(json[2]["Search"]["results"][0]["term"], "Milutinovic");
+        assert_eq!(json, msgpack.await.unwrap());
+        shutdown_tx.send(()).unwrap();
+        server.await.unwrap().unwrap();
+    }
+
+    #[test]
+    fn test_batch_commands() {
+        let dict_map = DictionaryMap::new();
+        for dictionary_id in ["names", "cities"] {
+            handle_command(
+                &dict_map,
+                Command::CreateDictionary(CreateDictionaryCommand {
+                    dictionary_id: dictionary_id.to_string(),
+                    attributes: vec![("name".to_string(), "multiple".to_string())],
+                    ..Default::default()
+                }),
+            );
+        }
+        let entries = vec![
+            HashMap::from([("name".to_string(), "Dusan Milutinovic".to_string())]),
+            HashMap::from([("unknown".to_string(), "Belgrade".to_string())]),
+            HashMap::from([("name".to_string(), "Dragan Miloradovic".to_string())]),
+        ];
+        let response = handle_command(
+            &dict_map,
+            Command::AddDictionaryEntries(AddDictionaryEntriesCommand {
+                dictionary_id: "names".to_string(),
+                entries: entries.clone(),
+            }),
+        );
+        let Response::AddDictionaryEntries(r) = response else {
+            panic!("unexpected response {:?}", response);
+        };
+        assert!(r.error.is_none());
+        assert_eq!(
+            r.results.iter().map(|r| r.success).collect::<Vec<_>>(),
+            vec![true, false, true]
+        );
+        assert!(r.results[1].error.is_some());
+        let response = handle_command(
+            &dict_map,
+            Command::AddDictionaryEntries(AddDictionaryEntriesCommand {
+                dictionary_id: "missing".to_string(),
+                entries,
+            }),
+        );
+        assert!(
+            matches!(response, Response::AddDictionaryEntries(r) if r.results.is_empty() && r.error.is_some())
+        );
+
+        let search = |dictionary_id: &str, term: &str| SearchCommand {
+            dictionary_id: dictionary_id.to_string(),
+            term: term.to_string(),
+            limit: None,
+        };
+        let response = handle_command(
+            &dict_map,
+            Command::MultiSearch(MultiSearchCommand {
+                searches: vec![
+                    search("names", "Dragan"),
+                    search("cities", "Bel"),
+                    search("missing", "Mil"),
+                ],
+            }),
+        );
+        let Response::MultiSearch(r) = response else {
+            panic!("unexpected response {:?}", response);
+        };
+        assert_eq!(r.results.len(), 3);
+        assert_eq!(r.results[0].results[0].original_entry, "Dragan Miloradovic");
+        assert!(r.results[1].results.is_empty() && r.results[1].error.is_none());
+        assert!(r.results[2].error.is_some());
+    }
+
+    #[tokio::test]
+    async fn test_mutating_commands_not_timed_out() {
+        let dict_map = Arc::new(DictionaryMap::new());
+        let response = execute(
+            &dict_map,
+            Command::CreateDictionary(CreateDictionaryCommand {
+                dictionary_id: "names".to_string(),
+                attributes: vec![("name".to_string(), "multiple".to_string())],
+                ..Default::default()
+            }),
+            Duration::ZERO,
+        )
+        .await;
+        assert!(matches!(response, Response::CreateDictionary(r) if r.success));
+        let entries = (0..1000)
+            .map(|i| HashMap::from([("name".to_string(), format!("Dusan {}", i))]))
+            .collect();
+        let response = execute(
+            &dict_map,
+            Command::AddDictionaryEntries(AddDictionaryEntriesCommand {
+                dictionary_id: "names".to_string(),
+                entries,
+            }),
+            Duration::ZERO,
+        )
+        .await;
+        let Response::AddDictionaryEntries(r) = response else {
+            panic!("unexpected response {:?}", response);
+        };
+        assert_eq!(r.results.iter().filter(|r| r.success).count(), 1000);
+    }
+
+    #[test]
+    fn test_create_dictionary_config() {
+        let dict_map = DictionaryMap::with_max_search_results(50);
+        let create = |attributes: Vec<(&str, &str)>, max_search_results| {
+            handle_command(
+                &dict_map,
+                Command::CreateDictionary(CreateDictionaryCommand {
+                    dictionary_id: "names".to_string(),
+                    attributes: attributes
+                        .into_iter()
+                        .map(|(name, search_type)| (name.to_string(), search_type.to_string()))
+                        .collect(),
+                    max_search_results,
+                    min_term_length: Some(2),
+                    ..Default::default()
+                }),
+            )
+        };
+        let response = create(vec![("name", "multiple"), ("id", "fuzzy")], None);
+        assert!(
+            matches!(&response, Response::CreateDictionary(r) if r.error.as_ref().unwrap().contains("fuzzy"))
+        );
+        let response = create(vec![("name", "exact")], Some(100));
+        assert!(matches!(response, Response::CreateDictionary(r) if !r.success));
+        let response = create(vec![("name", "exact")], Some(2));
+        assert!(matches!(response, Response::CreateDictionary(r) if r.success));
+
+        let entries = ["Ana", "Anastasija", "Andjela", "Andrea"]
+            .map(|name| HashMap::from([("name".to_string(), name.to_string())]));
+        handle_command(
+            &dict_map,
+            Command::AddDictionaryEntries(AddDictionaryEntriesCommand {
+                dictionary_id: "names".to_string(),
+                entries: entries.into(),
+            }),
+        );
+        let search = |limit| {
+            let response = handle_command(
+                &dict_map,
+                Command::Search(SearchCommand {
+                    dictionary_id: "names".to_string(),
+                    term: "An".to_string(), // shorter than the default min_term_length
+                    limit,
+                }),
+            );
+            match response {
+                Response::Search(r) => r.results.len(),
+                _ => panic!("unexpected response {:?}", response),
+            }
+        };
+        assert_eq!(search(None), 2);
+        assert_eq!(search(Some(3)), 3);
+        assert_eq!(search(Some(1)), 1);
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_shutdown_with_unread_responses() {
+        let (address, shutdown_tx, server) = start_server_with(ServerConfig {
+            request_timeout: Duration::from_millis(200),
+            ..ServerConfig::default()
+        })
+        .await;
+        let (loaded_tx, loaded_rx) = oneshot::channel();
+        // the client is blocked on writing until the server closes the connection
+        std::thread::spawn(move || {
+            let mut client = Client::connect(address).unwrap();
+            client
+                .send(Command::CreateDictionary(CreateDictionaryCommand {
+                    dictionary_id: "names".to_string(),
+                    attributes: vec![("name".to_string(), "exact".to_string())],
+                    max_search_results: Some(1000),
+                    ..Default::default()
+                }))
+                .unwrap();
+            let entries = (0..1000)
+                .map(|i| HashMap::from([("name".to_string(), format!("Name {}", i))]))
+                .collect();
+            client.add_entries("names", entries, 1000).unwrap().unwrap();
+            loaded_tx.send(()).unwrap();
+            // large responses that are never read, they fill the socket and the response queue
+            loop {
+                let search = Command::Search(SearchCommand {
+                    dictionary_id: "names".to_string(),
+                    term: "Name".to_string(),
+                    limit: Some(1000),
+                });
+                if client.submit(search).is_err() {
+                    break;
+                }
+            }
+        });
+        loaded_rx.await.unwrap();
+        tokio::time::sleep(Duration::from_millis(500)).await;
+        shutdown_tx.send(()).unwrap();
+        let stopped = tokio::time::timeout(Duration::from_secs(10), server).await;
+        assert!(stopped.is_ok(), "the server didn't shut down");
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_load_more_batches_than_in_flight() {
+        let (address, shutdown_tx, server) = start_server().await;
+        // many more batches than the server runs at once
+        let batch_size = 10;
+        let count = MAX_IN_FLIGHT * batch_size * 10;
+        let load = tokio::task::spawn_blocking(move || {
+            let mut client = Client::connect(address).unwrap();
+            client
+                .send(Command::CreateDictionary(CreateDictionaryCommand {
+                    dictionary_id: "names".to_string(),
+                    attributes: vec![("name".to_string(), "exact".to_string())],
+                    ..Default::default()
+                }))
+                .unwrap();
+            let entries = (0..count)
+                .map(|i| HashMap::from([("name".to_string(), format!("Name {}", i))]))
+                .collect();
+            client.add_entries("names", entries, batch_size).unwrap()
+        });
+        let loaded = tokio::time::timeout(Duration::from_secs(60), load)
+            .await
+            .expect("the load hung")
+            .unwrap();
+        assert_eq!(loaded.unwrap(), (count, 0));
+        shutdown_tx.send(()).unwrap();
+        server.await.unwrap().unwrap();
+    }
+
+    // A server that only answers when the client stops sending. A client that sends all its batches
+    // before reading would have every batch outstanding, and block a real server once the buffers are full
+    #[test]
+    fn test_load_window() {
+        use crate::protocol::{read_frame, write_frame};
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+        let server = std::thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            read_frame(&mut stream).unwrap();
+            let hello = HelloResponse {
+                version: Some(PROTOCOL_VERSION),
+                codec: Codec::Json,
+                error: None,
+            };
+            write_frame(&mut stream, &serde_json::to_vec(&hello).unwrap()).unwrap();
+            stream
+                .set_read_timeout(Some(Duration::from_millis(200)))
+                .unwrap();
+            let (mut outstanding, mut most_outstanding) = (Vec::new(), 0);
+            loop {
+                match read_frame(&mut stream) {
+                    Ok(Some(payload)) => {
+                        let request: Request = serde_json::from_slice(&payload).unwrap();
+                        outstanding.push(request.id);
+                        most_outstanding = most_outstanding.max(outstanding.len());
+                    }
+                    Ok(None) => return most_outstanding,
+                    Err(_) => {
+                        for id in outstanding.drain(..) {
+                            let frame = ResponseFrame {
+                                id: Some(id),
+                                response: Response::AddDictionaryEntries(
+                                    AddDictionaryEntriesResponse {
+                                        results: vec![],
+                                        error: None,
+                                    },
+                                ),
+                            };
+                            write_frame(&mut stream, &serde_json::to_vec(&frame).unwrap()).unwrap();
+                        }
+                    }
+                }
+            }
+        });
+        let mut client = Client::connect(address).unwrap();
+        let entries = vec![HashMap::new(); MAX_IN_FLIGHT * 3];
+        assert_eq!(
+            client.add_entries("names", entries, 1).unwrap().unwrap(),
+            (0, 0)
+        );
+        drop(client);
+        assert_eq!(server.join().unwrap(), MAX_IN_FLIGHT);
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_restore_after_restart() {
+        let data_dir =
+            std::env::temp_dir().join(format!("netexample-listener-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&data_dir);
+        let config = || ServerConfig {
+            data_dir: Some(data_dir.clone()),
+            ..ServerConfig::default()
+        };
+
+        let (address, shutdown_tx, server) = start_server_with(config()).await;
+        let responses = tokio::task::spawn_blocking(move || {
+            let mut client = Client::connect(address).unwrap();
+            let commands = vec![
+                Command::CreateDictionary(CreateDictionaryCommand {
+                    dictionary_id: "names".to_string(),
+                    attributes: vec![("name".to_string(), "multiple".to_string())],
+                    ..Default::default()
+                }),
+                Command::AddDictionaryEntry(AddDictionaryEntryCommand {
+                    dictionary_id: "names".to_string(),
+                    entry_data: HashMap::from([(
+                        "name".to_string(),
+                        "Dusan Milutinovic".to_string(),
+                    )]),
+                }),
+                Command::Save,
+                Command::Save,
+                Command::CreateDictionary(CreateDictionaryCommand {
+                    dictionary_id: "unsaved".to_string(),
+                    ..Default::default()
+                }),
+            ];
+            commands
+                .into_iter()
+                .map(|c| client.send(c).unwrap())
+                .collect::<Vec<_>>()
+        })
+        .await
+        .unwrap();
+        assert!(matches!(&responses[2], Response::Save(r) if r.saved == vec!["names"]));
+        assert!(matches!(&responses[3], Response::Save(r) if r.success && r.saved.is_empty()));
+        shutdown_tx.send(()).unwrap();
+        server.await.unwrap().unwrap();
+
+        // saved on shutdown too
+        let (address, shutdown_tx, server) = start_server_with(config()).await;
+        let responses = tokio::task::spawn_blocking(move || {
+            let mut client = Client::connect(address).unwrap();
+            vec![
+                client.send(Command::ListDictionaries).unwrap(),
+                client
+                    .send(Command::Search(SearchCommand {
+                        dictionary_id: "names".to_string(),
+                        term: "Milu".to_string(),
+                        limit: None,
+                    }))
+                    .unwrap(),
+            ]
+        })
+        .await
+        .unwrap();
+        assert!(
+            matches!(&responses[0], Response::ListDictionaries(r) if r.dictionary_ids == vec!["names", "unsaved"])
+        );
+        assert!(
+            matches!(&responses[1], Response::Search(r) if r.results[0].original_entry == "Dusan Milutinovic")
+        );
+        shutdown_tx.send(()).unwrap();
+        server.await.unwrap().unwrap();
+        std::fs::remove_dir_all(&data_dir).unwrap();
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_auth() {
+        let auth_file =
+            std::env::temp_dir().join(format!("netexample-auth-{}.json", std::process::id()));
+        std::fs::write(
+            &auth_file,
+            r#"{"tokens": {"admin": {"*": "admin"}, "reader": {"names": "read"}}}"#,
+        )
+        .unwrap();
+        let (address, shutdown_tx, server) = start_server_with(ServerConfig {
+            auth_file: Some(auth_file.clone()),
+            ..ServerConfig::default()
+        })
+        .await;
+        let auth = |token: &str| {
+            Command::Auth(AuthCommand {
+                token: token.to_string(),
+            })
+        };
+        let create = |dictionary_id: &str| {
+            Command::CreateDictionary(CreateDictionaryCommand {
+                dictionary_id: dictionary_id.to_string(),
+                attributes: vec![("name".to_string(), "multiple".to_string())],
+                ..Default::default()
+            })
+        };
+        let search = || {
+            Command::Search(SearchCommand {
+                dictionary_id: "names".to_string(),
+                term: "John".to_string(),
+                limit: None,
+            })
+        };
+        let responses = tokio::task::spawn_blocking(move || {
+            let mut admin = Client::connect(address).unwrap();
+            let mut reader = Client::connect(address).unwrap();
+            vec![
+                admin.send(create("names")).unwrap(),
+                admin.send(auth("admin")).unwrap(),
+                admin.send(create("names")).unwrap(),
+                admin.send(create("cities")).unwrap(),
+                reader.send(auth("wrong")).unwrap(),
+                reader.send(auth("reader")).unwrap(),
+                reader.send(search()).unwrap(),
+                reader.send(create("other")).unwrap(),
+                reader.send(Command::ListDictionaries).unwrap(),
+            ]
+        })
+        .await
+        .unwrap();
+        assert!(matches!(&responses[0], Response::PermissionDenied(_)));
+        assert!(matches!(&responses[1], Response::Auth(r) if r.success));
+        assert!(matches!(&responses[2], Response::CreateDictionary(r) if r.success));
+        assert!(matches!(&responses[3], Response::CreateDictionary(r) if r.success));
+        assert!(matches!(&responses[4], Response::Auth(r) if !r.success));
+        assert!(matches!(&responses[5], Response::Auth(r) if r.success));
+        assert!(matches!(&responses[6], Response::Search(r) if r.error.is_none()));
+        assert!(
+            matches!(&responses[7], Response::PermissionDenied(r) if r.error.contains("admin access to dictionary 'other'"))
+        );
+        assert!(
+            matches!(&responses[8], Response::ListDictionaries(r) if r.dictionary_ids == vec!["names"])
+        );
+        shutdown_tx.send(()).unwrap();
+        server.await.unwrap().unwrap();
+        std::fs::remove_file(&auth_file).unwrap();
+    }
+
+    #[test]
+    fn test_server_config_from_args() {
+        let args = [
+            "--port",
+            "5555",
+            "--request-timeout-ms",
+            "250",
+            "--max-search-results",
+            "20",
+        ]
+        .map(String::from);
+        let config = ServerConfig::from_args(args).unwrap();
+        assert_eq!(config.max_search_results, 20);
+        assert_eq!(config.port, 5555);
+        assert_eq!(config.address, "127.0.0.1");
+        assert_eq!(config.request_timeout, Duration::from_millis(250));
+        assert_eq!(config.data_dir, None);
+        let args = ["--data-dir", "/var/lib/dictionaries"].map(String::from);
+        let config = ServerConfig::from_args(args).unwrap();
+        assert_eq!(
+            config.data_dir,
+            Some(PathBuf::from("/var/lib/dictionaries"))
+        );
+        assert!(ServerConfig::from_args(["--port".to_string()]).is_err());
+        assert!(ServerConfig::from_args(["--port", "x"].map(String::from)).is_err());
     }
 }
@@ -0,0 +1,287 @@
+use crate::access::{parse_search_type, search_type_name};
+use dictionary_bitmap_trie::constants::SearchConfig;
+use dictionary_bitmap_trie::dictionary::{AttributeSearch, Dictionary};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+// Every dictionary is stored in its own file in the data directory, with its schema and all its entries.
+// A file is written next to the old one, synced, and renamed over it, so a crash never leaves a half written
+// dictionary. The directory is synced after the rename, so the rename itself survives a crash.
+
+const EXTENSION: &str = "json";
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct DictionaryFile {
+    pub dictionary_id: String,
+    pub attributes: Vec<(String, String)>, // (attribute_name, search_type)
+    pub max_search_results: usize,
+    pub min_term_length: usize,
+    pub max_direct_entries: usize,
+    pub default_multiple_search_length: usize,
+    pub entries: Vec<Option<HashMap<String, StoredValue>>>, // by dictionary index, None for the deleted entries
+}
+
+// A single value is stored as a plain string, as in the files written before the multi-valued attributes
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum StoredValue {
+    One(String),
+    Many(Vec<String>),
+}
+
+impl From<Vec<String>> for StoredValue {
+    fn from(mut values: Vec<String>) -> Self {
+        if values.len() == 1 {
+            StoredValue::One(values.remove(0))
+        } else {
+            StoredValue::Many(values)
+        }
+    }
+}
+
+impl From<StoredValue> for Vec<String> {
+    fn from(value: StoredValue) -> Self {
+        match value {
+            StoredValue::One(value) => vec![value],
+            StoredValue::Many(values) => values,
+        }
+    }
+}
+
+impl DictionaryFile {
+    pub fn from_dictionary(dictionary_id: &str, dictionary: &Dictionary) -> Self {
+        let config = dictionary.config();
+        Self {
+            dictionary_id: dictionary_id.to_string(),
+            attributes: dictionary
+                .attributes()
+                .iter()
+                .map(|(name, search)| (name.clone(), search_type_name(search).to_string()))
+                .collect(),
+            max_search_results: config.max_search_results,
+            min_term_length: config.min_term_length,
+            max_direct_entries: config.max_direct_entries,
+            default_multiple_search_length: config.default_multiple_search_length,
+            entries: dictionary
+                .snapshot()
+                .into_iter()
+                .map(|entry| entry.map(|m| m.into_iter().map(|(k, v)| (k, v.into())).collect()))
+                .collect(),
+        }
+    }
+
+    // the config is given to the check with the dictionary id before the dictionary is built,
+    // the check can adjust it or refuse it
+    pub fn into_dictionary(
+        self,
+        check: impl Fn(&str, &mut SearchConfig) -> Result<(), String>,
+    ) -> io::Result<(String, Dictionary)> {
+        let attributes = self
+            .attributes
+            .into_iter()
+            .map(
+                |(name, search_type)| match parse_search_type(&search_type) {
+                    Some(search) => Ok((name, search)),
+                    None => Err(invalid_data(format!(
+                        "Unknown search type '{}' for attribute '{}'",
+                        search_type, name
+                    ))),
+                },
+            )
+            .collect::<io::Result<Vec<(String, AttributeSearch)>>>()?;
+        let mut config = SearchConfig {
+            max_search_results: self.max_search_results,
+            min_term_length: self.min_term_length,
+            max_direct_entries: self.max_direct_entries,
+            default_multiple_search_length: self.default_multiple_search_length,
+            ..SearchConfig::default()
+        };
+        check(&self.dictionary_id, &mut config)
+            .map_err(|e| invalid_data(format!("{}: {}", self.dictionary_id, e)))?;
+        let entries = self
+            .entries
+            .into_iter()
+            .map(|entry| entry.map(|m| m.into_iter().map(|(k, v)| (k, v.into())).collect()))
+            .collect();
+        let dictionary = Dictionary::from_snapshot(attributes, config, entries);
+        Ok((self.dictionary_id, dictionary))
+    }
+}
+
+fn invalid_data(e: impl ToString) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, e.to_string())
+}
+
+// Dictionary ids can be any string, everything but ASCII letters, digits, '-' and '_' is escaped as %XX
+pub fn file_name(dictionary_id: &str) -> String {
+    let mut name = String::with_capacity(dictionary_id.len());
+    for b in dictionary_id.bytes() {
+        if b.is_ascii_alphanumeric() || b == b'-' || b == b'_' {
+            name.push(b as char);
+        } else {
+            name.push_str(&format!("%{:02X}", b));
+        }
+    }
+    format!("{}.{}", name, EXTENSION)
+}
+
+pub fn save_dictionary(dir: &Path, dictionary_id: &str, dictionary: &Dictionary) -> io::Result<()> {
+    let file = DictionaryFile::from_dictionary(dictionary_id, dictionary);
+    let path = dir.join(file_name(dictionary_id));
+    let tmp = path.with_extension(format!("{}.tmp", EXTENSION));
+    let mut f = fs::File::create(&tmp)?;
+    f.write_all(&serde_json::to_vec(&file).map_err(invalid_data)?)?;
+    f.sync_all()?;
+    drop(f);
+    fs::rename(&tmp, &path)?;
+    sync_dir(dir)
+}
+
+// the directories can't be opened as files on windows, where the rename is durable without it
+#[cfg(unix)]
+fn sync_dir(dir: &Path) -> io::Result<()> {
+    fs::File::open(dir)?.sync_all()
+}
+
+#[cfg(not(unix))]
+fn sync_dir(_dir: &Path) -> io::Result<()> {
+    Ok(())
+}
+
+pub fn remove_dictionary(dir: &Path, dictionary_id: &str) -> io::Result<()> {
+    match fs::remove_file(dir.join(file_name(dictionary_id))) {
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(()),
+        res => res,
+    }
+}
+
+// the files of the dictionaries in the directory, leftover temporary files are skipped
+fn dictionary_files(dir: &Path) -> io::Result<Vec<PathBuf>> {
+    let mut paths = Vec::new();
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.extension().is_some_and(|e| e == EXTENSION) {
+            paths.push(path);
+        }
+    }
+    paths.sort();
+    Ok(paths)
+}
+
+/// Loads all the dictionaries of the data directory, creating the directory if it doesn't exist.
+/// A dictionary whose config doesn't pass the check fails the load
+pub fn load_dictionaries(
+    dir: &Path,
+    check: impl Fn(&str, &mut SearchConfig) -> Result<(), String>,
+) -> io::Result<Vec<(String, Dictionary)>> {
+    fs::create_dir_all(dir)?;
+    dictionary_files(dir)?
+        .into_iter()
+        .map(|path| {
+            let file: DictionaryFile = serde_json::from_slice(&fs::read(&path)?)
+                .map_err(|e| invalid_data(format!("{}: {}", path.display(), e)))?;
+            file.into_dictionary(&check)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_file_name() {
+        assert_eq!(file_name("names_2024-01"), "names_2024-01.json");
+        assert_eq!(file_name("../etc/passwd"), "%2E%2E%2Fetc%2Fpasswd.json");
+        assert_eq!(file_name("čaj"), "%C4%8Daj.json");
+    }
+
+    fn valid(_: &str, _: &mut SearchConfig) -> Result<(), String> {
+        Ok(())
+    }
+
+    #[test]
+    fn test_save_and_load() {
+        let dir =
+            std::env::temp_dir().join(format!("netexample-persistence-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        assert!(load_dictionaries(&dir, valid).unwrap().is_empty());
+
+        let config = SearchConfig {
+            max_search_results: 7,
+            ..SearchConfig::default()
+        };
+        let dictionary = Dictionary::new(
+            vec![
+                ("name".to_string(), AttributeSearch::Multiple),
+                ("id".to_string(), AttributeSearch::None),
+            ],
+            config,
+        );
+        for (name, id) in [("John Doe", "1"), ("Jane Doe", "2"), ("Joe Doe", "3")] {
+            dictionary.add_dictionary_entry(HashMap::from([
+                ("name".to_string(), name.to_string()),
+                ("id".to_string(), id.to_string()),
+            ]));
+        }
+        dictionary.add_multi_valued_entry(HashMap::from([(
+            "name".to_string(),
+            vec!["Joan Doe".to_string(), "Joan Smith".to_string()],
+        )]));
+        dictionary.delete(1).unwrap();
+        save_dictionary(&dir, "people/all", &dictionary).unwrap();
+        save_dictionary(
+            &dir,
+            "other",
+            &Dictionary::new(vec![], SearchConfig::default()),
+        )
+        .unwrap();
+        remove_dictionary(&dir, "other").unwrap();
+        remove_dictionary(&dir, "other").unwrap();
+        fs::write(dir.join("leftover.json.tmp"), b"{").unwrap();
+
+        let loaded = load_dictionaries(&dir, valid).unwrap();
+        assert_eq!(loaded.len(), 1);
+        let (dictionary_id, restored) = &loaded[0];
+        assert_eq!(dictionary_id, "people/all");
+        assert_eq!(restored.attributes(), dictionary.attributes());
+        assert_eq!(restored.config().max_search_results, 7);
+        assert!(!restored.contains(1));
+        assert_eq!(restored.get(2), dictionary.get(2));
+        assert_eq!(restored.search("Joe")[0].dictionary_index, 2);
+        assert_eq!(restored.get_values(3), dictionary.get_values(3));
+        assert_eq!(restored.search("Smith")[0].value_index, 1);
+
+        // files written before the multi-valued attributes have a string per attribute
+        let file: DictionaryFile = serde_json::from_str(
+            r#"{"dictionary_id": "old", "attributes": [["name", "multiple"]], "max_search_results": 10,
+                "min_term_length": 3, "max_direct_entries": 5, "default_multiple_search_length": 3,
+                "entries": [{"name": "John Doe"}, null]}"#,
+        )
+        .unwrap();
+        let (_, old) = file.into_dictionary(valid).unwrap();
+        assert_eq!(old.get(0)["name"], "John Doe");
+
+        let file: DictionaryFile = serde_json::from_str(
+            r#"{"dictionary_id": "thrashing", "attributes": [["name", "multiple"]], "max_search_results": 10,
+                "min_term_length": 3, "max_direct_entries": 2, "default_multiple_search_length": 3,
+                "entries": []}"#,
+        )
+        .unwrap();
+        let no_thrashing = |_: &str, config: &mut SearchConfig| {
+            (config.max_direct_entries > config.min_bitmap_entries)
+                .then_some(())
+                .ok_or_else(|| "max_direct_entries is too small".to_string())
+        };
+        let e = file.into_dictionary(no_thrashing).err().unwrap();
+        assert_eq!(e.kind(), io::ErrorKind::InvalidData);
+        assert!(e.to_string().starts_with("thrashing: "));
+
+        fs::write(dir.join("broken.json"), b"{").unwrap();
+        assert!(load_dictionaries(&dir, valid).is_err());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
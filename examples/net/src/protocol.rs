@@ -0,0 +1,311 @@
+use crate::command::{Command, Response};
+use serde::de::{DeserializeOwned, IgnoredAny};
+use serde::{Deserialize, Serialize};
+use std::io::{self, Read, Write};
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+
+// Every message on the wire is a frame: payload length as a big endian u32, followed by the payload.
+// The first frame on a connection is the client Hello, answered with HelloResponse, both always in JSON.
+// After that the client sends Requests and the server answers with ResponseFrames, in the negotiated codec.
+
+pub const PROTOCOL_VERSION: u32 = 1;
+pub const MAX_FRAME_LENGTH: usize = 16 * 1024 * 1024;
+
+/// Encoding of the request and response frames
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Codec {
+    #[default]
+    Json,
+    MessagePack, // compact binary encoding, structs are encoded as arrays without the field names
+}
+
+impl Codec {
+    pub fn encode<T: Serialize>(&self, value: &T) -> io::Result<Vec<u8>> {
+        match self {
+            Codec::Json => serde_json::to_vec(value).map_err(invalid_data),
+            Codec::MessagePack => rmp_serde::to_vec(value).map_err(invalid_data),
+        }
+    }
+
+    pub fn decode<T: DeserializeOwned>(&self, payload: &[u8]) -> io::Result<T> {
+        match self {
+            Codec::Json => serde_json::from_slice(payload).map_err(invalid_data),
+            Codec::MessagePack => rmp_serde::from_slice(payload).map_err(invalid_data),
+        }
+    }
+
+    /// Best effort id of a request that failed to deserialize, so the error can still be correlated
+    pub fn request_id(&self, payload: &[u8]) -> Option<u64> {
+        match self {
+            Codec::Json => serde_json::from_slice::<serde_json::Value>(payload)
+                .ok()?
+                .get("id")?
+                .as_u64(),
+            Codec::MessagePack => rmp_serde::from_slice::<(u64, IgnoredAny)>(payload)
+                .ok()
+                .map(|(id, _)| id),
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Hello {
+    pub versions: Vec<u32>, // protocol versions the client supports
+    #[serde(default)]
+    pub codecs: Vec<Codec>, // codecs the client supports, in the order of preference, JSON if empty
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct HelloResponse {
+    pub version: Option<u32>, // the version chosen by the server, None if there is no common version
+    #[serde(default)]
+    pub codec: Codec,
+    pub error: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Request {
+    pub id: u64,
+    pub command: Command,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ResponseFrame {
+    pub id: Option<u64>, // id of the request, None if the request frame was too malformed to read it
+    pub response: Response,
+}
+
+/// Picks the highest version supported by both sides
+pub fn negotiate_version(hello: &Hello) -> Option<u32> {
+    hello
+        .versions
+        .iter()
+        .copied()
+        .filter(|v| *v == PROTOCOL_VERSION)
+        .max()
+}
+
+/// The server supports all the codecs, so it takes the client's first choice
+pub fn negotiate_codec(hello: &Hello) -> Codec {
+    hello.codecs.first().copied().unwrap_or_default()
+}
+
+fn invalid_data(e: impl ToString) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, e.to_string())
+}
+
+fn frame_too_long(len: usize) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidData,
+        format!(
+            "Frame of {} bytes exceeds the limit of {} bytes",
+            len, MAX_FRAME_LENGTH
+        ),
+    )
+}
+
+pub fn write_frame<W: Write>(writer: &mut W, payload: &[u8]) -> io::Result<()> {
+    if payload.len() > MAX_FRAME_LENGTH {
+        return Err(frame_too_long(payload.len()));
+    }
+    writer.write_all(&(payload.len() as u32).to_be_bytes())?;
+    writer.write_all(payload)?;
+    writer.flush()
+}
+
+/// Returns None on a clean end of stream between frames
+pub fn read_frame<R: Read>(reader: &mut R) -> io::Result<Option<Vec<u8>>> {
+    let mut len = [0u8; 4];
+    match reader.read_exact(&mut len) {
+        Ok(()) => (),
+        Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => return Ok(None),
+        Err(e) => return Err(e),
+    }
+    let len = u32::from_be_bytes(len) as usize;
+    if len > MAX_FRAME_LENGTH {
+        return Err(frame_too_long(len));
+    }
+    let mut payload = vec![0u8; len];
+    reader.read_exact(&mut payload)?;
+    Ok(Some(payload))
+}
+
+pub async fn write_frame_async<W: AsyncWrite + Unpin>(
+    writer: &mut W,
+    payload: &[u8],
+) -> io::Result<()> {
+    if payload.len() > MAX_FRAME_LENGTH {
+        return Err(frame_too_long(payload.len()));
+    }
+    writer.write_u32(payload.len() as u32).await?;
+    writer.write_all(payload).await
+}
+
+/// Returns None on a clean end of stream between frames.
+/// The payload of a frame over MAX_FRAME_LENGTH is skipped, and an InvalidData error returned,
+/// the next frame can still be read then. The other errors leave the stream out of sync
+pub async fn read_frame_async<R: AsyncRead + Unpin>(reader: &mut R) -> io::Result<Option<Vec<u8>>> {
+    let len = match reader.read_u32().await {
+        Ok(len) => len as usize,
+        Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => return Ok(None),
+        Err(e) => return Err(e),
+    };
+    if len > MAX_FRAME_LENGTH {
+        let skipped =
+            tokio::io::copy(&mut (&mut *reader).take(len as u64), &mut tokio::io::sink()).await?;
+        if skipped < len as u64 {
+            return Err(io::ErrorKind::UnexpectedEof.into());
+        }
+        return Err(frame_too_long(len));
+    }
+    let mut payload = vec![0u8; len];
+    reader.read_exact(&mut payload).await?;
+    Ok(Some(payload))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::command::{
+        AddDictionaryEntryCommand, DescribeDictionaryResponse, ErrorResponse, SearchCommand,
+        SearchResponse, SearchResultItem,
+    };
+    use std::collections::HashMap;
+    use std::io::Cursor;
+
+    #[test]
+    fn test_frame_round_trip() {
+        let mut buffer = Vec::new();
+        write_frame(&mut buffer, b"first").unwrap();
+        write_frame(&mut buffer, b"").unwrap();
+        let mut reader = Cursor::new(buffer);
+        assert_eq!(read_frame(&mut reader).unwrap().unwrap(), b"first");
+        assert_eq!(read_frame(&mut reader).unwrap().unwrap(), b"");
+        assert!(read_frame(&mut reader).unwrap().is_none());
+    }
+
+    #[test]
+    fn test_frame_too_long() {
+        let mut reader = Cursor::new(u32::MAX.to_be_bytes().to_vec());
+        let e = read_frame(&mut reader).unwrap_err();
+        assert_eq!(e.kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[tokio::test]
+    async fn test_frame_too_long_skipped() {
+        let len = MAX_FRAME_LENGTH + 1;
+        let mut buffer = (len as u32).to_be_bytes().to_vec();
+        buffer.resize(4 + len, b'x');
+        write_frame(&mut buffer, b"next").unwrap();
+        let mut reader = Cursor::new(buffer.clone());
+        let e = read_frame_async(&mut reader).await.unwrap_err();
+        assert_eq!(e.kind(), io::ErrorKind::InvalidData);
+        assert_eq!(
+            read_frame_async(&mut reader).await.unwrap().unwrap(),
+            b"next"
+        );
+        // a stream that ends within the skipped payload
+        let mut reader = Cursor::new(buffer[..100].to_vec());
+        let e = read_frame_async(&mut reader).await.unwrap_err();
+        assert_eq!(e.kind(), io::ErrorKind::UnexpectedEof);
+    }
+
+    #[test]
+    fn test_negotiation() {
+        let hello: Hello = serde_json::from_str(r#"{"versions": [0, 1]}"#).unwrap();
+        assert_eq!(negotiate_version(&hello), Some(1));
+        assert_eq!(negotiate_codec(&hello), Codec::Json);
+        let hello = Hello {
+            versions: vec![2],
+            codecs: vec![Codec::MessagePack, Codec::Json],
+        };
+        assert_eq!(negotiate_version(&hello), None);
+        assert_eq!(negotiate_codec(&hello), Codec::MessagePack);
+    }
+
+    #[test]
+    fn test_request_id() {
+        let json = Codec::Json;
+        assert_eq!(
+            json.request_id(br#"{"id": 7, "command": "Bogus"}"#),
+            Some(7)
+        );
+        assert_eq!(json.request_id(b"not json"), None);
+        let msgpack = Codec::MessagePack;
+        let payload = rmp_serde::to_vec(&(7u64, "Bogus")).unwrap();
+        assert_eq!(msgpack.request_id(&payload), Some(7));
+        assert_eq!(msgpack.request_id(b"\xc1"), None);
+    }
+
+    fn sample_request() -> Request {
+        Request {
+            id: 42,
+            command: Command::AddDictionaryEntry(AddDictionaryEntryCommand {
+                dictionary_id: "names".to_string(),
+                entry_data: HashMap::from([
+                    ("name".to_string(), "Dusan Milutinovic".to_string()),
+                    ("id".to_string(), "1".to_string()),
+                ]),
+            }),
+        }
+    }
+
+    fn sample_responses() -> Vec<ResponseFrame> {
+        vec![
+            ResponseFrame {
+                id: Some(1),
+                response: Response::Search(SearchResponse {
+                    results: vec![SearchResultItem {
+                        term: "Milutinovic".to_string(),
+                        attribute: "name".to_string(),
+                        original_entry: "Dusan Milutinovic".to_string(),
+                        dictionary_id: "names".to_string(),
+                        dictionary_index: 0,
+                    }],
+                    error: None,
+                }),
+            },
+            ResponseFrame {
+                id: None,
+                response: Response::Error(ErrorResponse {
+                    error: "Malformed request".to_string(),
+                }),
+            },
+            ResponseFrame {
+                id: Some(2),
+                response: Response::DescribeDictionary(DescribeDictionaryResponse {
+                    attributes: vec![("name".to_string(), "multiple".to_string())],
+                    error: None,
+                }),
+            },
+        ]
+    }
+
+    // decodes what the codec encoded, and compares it with the original through its JSON value
+    fn round_trip<T: Serialize + DeserializeOwned>(codec: Codec, value: &T) -> serde_json::Value {
+        let decoded: T = codec.decode(&codec.encode(value).unwrap()).unwrap();
+        serde_json::to_value(&decoded).unwrap()
+    }
+
+    #[test]
+    fn test_codec_round_trip() {
+        let request = sample_request();
+        let expected = serde_json::to_value(&request).unwrap();
+        assert_eq!(round_trip(Codec::Json, &request), expected);
+        assert_eq!(round_trip(Codec::MessagePack, &request), expected);
+        for response in sample_responses() {
+            let expected = serde_json::to_value(&response).unwrap();
+            assert_eq!(round_trip(Codec::Json, &response), expected);
+            assert_eq!(round_trip(Codec::MessagePack, &response), expected);
+        }
+        let search = Command::Search(SearchCommand {
+            dictionary_id: "names".to_string(),
+            term: "Mil".to_string(),
+            limit: None,
+        });
+        assert!(
+            Codec::MessagePack.encode(&search).unwrap().len()
+                < Codec::Json.encode(&search).unwrap().len()
+        );
+    }
+}
@@ -1,12 +1,18 @@
 use crate::encoding::Encoding;
 
+/// Separator of the values of a multi-valued attribute, when they are returned as one string
+pub const MULTI_VALUE_SEPARATOR: &str = "; ";
+
 /// Configuration for search behavior. All fields have sensible defaults.
 #[derive(Debug, Clone)]
 pub struct SearchConfig {
     pub max_search_results: usize,
     pub max_direct_entries: usize,
+    pub min_bitmap_entries: usize,
     pub min_term_length: usize,
     pub default_multiple_search_length: usize,
+    // share of free trie rows or dictionary slots after which delete compacts automatically, None to compact only explicitly
+    pub compact_threshold: Option<f32>,
 }
 
 impl Default for SearchConfig {
@@ -14,8 +20,10 @@ impl Default for SearchConfig {
         Self {
             max_search_results: 10,
             max_direct_entries: 5, // how long before we promote to a TrieEntryG
+            min_bitmap_entries: 2, // how short before we demote back to a TrieEntryV, must be below max_direct_entries so the nodes don't thrash, Trie::new panics otherwise
             min_term_length: 3,
             default_multiple_search_length: 3,
+            compact_threshold: None,
         }
     }
 }
\ No newline at end of file
@@ -1,14 +1,23 @@
-use crate::constants::SearchConfig;
-use crate::encoding::{translate_decode, translate_encode};
+use crate::constants::{SearchConfig, MULTI_VALUE_SEPARATOR};
+use crate::encoding::{idx, translate_decode, translate_encode};
+use crate::facets::{FacetCount, FacetQuery, FacetedResults};
+use crate::metrics::{Metrics, MetricsSnapshot};
+use crate::stats::DictionaryStats;
+use crate::validation::{ValidationReport, Violation};
 use crate::trie::{Trie, TrieSearchResult};
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
+use std::mem::size_of;
 use std::sync::{Mutex, RwLock};
+use std::thread;
+use std::time::Instant;
 
 #[derive(Debug, Clone)]
-pub struct DictionaryEntry(HashMap<usize, String>);
-// each attribute of a dictionaryentry is one string in a vector, the order is defined in the dictionary
+pub struct DictionaryEntry(HashMap<usize, Vec<String>>);
+// each attribute of a dictionaryentry is a vector of values, usually just one, the order is defined in the dictionary
 // attribute is mapped to a usize, that is a position in the vector
+// every value is indexed on its own, so the words of two values never make one n-gram
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum AttributeSearch {
     None,     // don't include the attribute in search, but include it in result
     Exact,    // autocomplete has to be exact match from the beginning of attribute
@@ -18,6 +27,13 @@ pub enum AttributeSearch {
 // a b c d e -> (a,b,c), (b,c,d), (c,d,e), (d,e), e
 // in other words the search is for the 3 consecutive words(we can define the different default)
 
+/// Returned by `Dictionary::delete` when there is no live entry on the index
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct NotFound;
+
+// (word, dictionary index, attribute, value ordinal, position in the value), one vector per shard
+type ShardedWords = Vec<Vec<(String, u32, u8, u16, u16)>>;
+
 pub struct Dictionary {
     entries: RwLock<Vec<DictionaryEntry>>,
     attribute_map: HashMap<String, (usize, AttributeSearch)>,
@@ -25,18 +41,24 @@ pub struct Dictionary {
     trie: RwLock<Trie>,
     config: SearchConfig,
     free_list: Mutex<Vec<usize>>,
+    metrics: Metrics,
 }
 
 pub struct SearchResult {
     pub term: String,
     pub attribute: String,
-    pub original_entry: String,
+    pub original_entry: String, // the value of the attribute that matched
     pub attribute_index: usize,
+    pub value_index: usize, // ordinal of the value that matched, for the multi-valued attributes
     pub position: usize,
     pub dictionary_entry: DictionaryEntry,
     pub dictionary_index: usize, // once the search is done, we can use this to get the dictionary entry
 }
 
+fn single_values(data: HashMap<String, String>) -> HashMap<String, Vec<String>> {
+    data.into_iter().map(|(k, v)| (k, vec![v])).collect()
+}
+
 impl Dictionary {
     pub fn new(attrs: Vec<(String, AttributeSearch)>, search_config: SearchConfig) -> Dictionary {
         let mut attribute_map = HashMap::new();
@@ -53,6 +75,7 @@ impl Dictionary {
             trie: RwLock::new(Trie::new(search_config.clone())),
             config: search_config,
             free_list: Mutex::new(Vec::new()),
+            metrics: Metrics::default(),
         }
     }
     // Trie save up to DEFAULT_MULTIPLE_SEARCH_LENGTH words, after that we need to filter the results here
@@ -94,8 +117,36 @@ impl Dictionary {
         }
         ret
     }
-    pub fn add_dictionary_entry(&self, data: HashMap<String, String>) {
-        let mut m: HashMap<usize, String> = HashMap::new();
+
+    // words of the attribute value that go to the trie, with their position in the value
+    fn index_words(&self, attr_s: &AttributeSearch, value: &str) -> Vec<(String, u16)> {
+        match attr_s {
+            AttributeSearch::None => Vec::new(),
+            AttributeSearch::Exact => vec![(value.to_string(), 0)],
+            AttributeSearch::Multiple => self
+                .split_word(value)
+                .into_iter()
+                .map(|(s, pos, _)| (s, pos as u16))
+                .collect(),
+        }
+    }
+
+    // Returns the index of the new entry, None if the data has none of the dictionary attributes
+    pub fn add_dictionary_entry(&self, data: HashMap<String, String>) -> Option<usize> {
+        self.add_multi_valued_entry(single_values(data))
+    }
+
+    // Same as add_dictionary_entry, with a list of values per attribute, each value is indexed independently
+    pub fn add_multi_valued_entry(&self, data: HashMap<String, Vec<String>>) -> Option<usize> {
+        let start = Instant::now();
+        let added = self.add_entry(data);
+        self.metrics.add.observe(start.elapsed());
+        Metrics::count(&self.metrics.entries_added, added.iter().len());
+        added
+    }
+
+    fn add_entry(&self, data: HashMap<String, Vec<String>>) -> Option<usize> {
+        let mut m: HashMap<usize, Vec<String>> = HashMap::new();
         let mut entries = self.entries.write().unwrap();
         let mut dictionary_pos = entries.len();
         let mut fl = self.free_list.lock().unwrap();
@@ -104,27 +155,25 @@ impl Dictionary {
             dictionary_pos = fl.pop().unwrap();
             reused = true;
         }
-        data.keys().for_each(|k| {
-            if let Some((u, attr_s)) = self.attribute_map.get(k) {
-                m.insert(*u, data[k].clone());
-                match attr_s {
-                    AttributeSearch::None => (),
-                    AttributeSearch::Exact => {
+        data.into_iter().for_each(|(k, values)| {
+            if let Some((u, attr_s)) = self.attribute_map.get(&k) {
+                if values.is_empty() {
+                    return;
+                }
+                for (ordinal, value) in values.iter().enumerate() {
+                    for (s, pos) in self.index_words(attr_s, value) {
                         let mut l = self.trie.write().unwrap();
-                        l.add_word(&data[k], dictionary_pos as u32, *u as u8, 0);
-                    }
-                    AttributeSearch::Multiple => {
-                        let v = self.split_word(&data[k]);
-                        for (s, pos, _) in v {
-                            let mut l = self.trie.write().unwrap();
-                            l.add_word(&s, dictionary_pos as u32, *u as u8, pos as u16);
-                        }
+                        l.add_word(&s, dictionary_pos as u32, *u as u8, ordinal as u16, pos);
                     }
                 }
+                m.insert(*u, values);
             }
         });
         if m.len() == 0 {
-            return;
+            if reused {
+                fl.push(dictionary_pos);
+            }
+            return None;
         }
 
         if reused {
@@ -132,8 +181,360 @@ impl Dictionary {
         }else {
             entries.push(DictionaryEntry(m));
         }
+        Some(dictionary_pos)
+    }
+    // Replaces the entry on the index in place, it keeps its index. Returns false if there is no live entry
+    // on the index, or the data has none of the dictionary attributes, the entry is left as it was then
+    pub fn replace_dictionary_entry(&self, index: usize, data: HashMap<String, String>) -> bool {
+        self.replace_multi_valued_entry(index, single_values(data))
+    }
+
+    pub fn replace_multi_valued_entry(&self, index: usize, data: HashMap<String, Vec<String>>) -> bool {
+        let m: HashMap<usize, Vec<String>> = data
+            .into_iter()
+            .filter(|(_, v)| !v.is_empty())
+            .filter_map(|(k, v)| self.attribute_map.get(&k).map(|(u, _)| (*u, v)))
+            .collect();
+        if m.is_empty() {
+            return false;
+        }
+        // the old words out and the new ones in under the same locks, searches see either of the entries
+        let mut entries = self.entries.write().unwrap();
+        if index >= entries.len() || self.free_list.lock().unwrap().contains(&index) {
+            return false;
+        }
+        let mut trie = self.trie.write().unwrap();
+        self.unindex_entry(&mut trie, index, &entries[index]);
+        for (u, values) in &m {
+            let attr_s = &self.attribute_map[&self.reverse_attribute_map[&(*u as u8)]].1;
+            for (ordinal, value) in values.iter().enumerate() {
+                for (s, pos) in self.index_words(attr_s, value) {
+                    trie.add_word(&s, index as u32, *u as u8, ordinal as u16, pos);
+                }
+            }
+        }
+        entries[index] = DictionaryEntry(m);
+        true
+    }
+
+    // Bulk version of add_dictionary_entry, used for loading large data sets.
+    // Entries are tokenized on all available cores, the words are sharded by their first character,
+    // and each shard is built into its own sub-trie in parallel, before any lock is taken. The entries
+    // then get their indices and the sub-tries are merged into the dictionary trie, the searches only
+    // wait for the merge.
+    // Returns the index of every entry in the order of the data, None for the entries with no known attributes,
+    // which are skipped
+    pub fn add_dictionary_entries(&self, data: Vec<HashMap<String, String>>) -> Vec<Option<usize>> {
+        self.add_multi_valued_entries(data.into_iter().map(single_values).collect())
+    }
+
+    // Bulk version of add_multi_valued_entry
+    pub fn add_multi_valued_entries(&self, data: Vec<HashMap<String, Vec<String>>>) -> Vec<Option<usize>> {
+        let maps: Vec<HashMap<usize, Vec<String>>> = data
+            .into_iter()
+            .map(|d| {
+                d.into_iter()
+                    .filter(|(_, v)| !v.is_empty())
+                    .filter_map(|(k, v)| self.attribute_map.get(&k).map(|(u, _)| (*u, v)))
+                    .collect()
+            })
+            .collect();
+        let known: Vec<bool> = maps.iter().map(|m| !m.is_empty()).collect();
+        let batch: Vec<DictionaryEntry> = maps
+            .into_iter()
+            .filter(|m| !m.is_empty())
+            .map(DictionaryEntry)
+            .collect();
+        if batch.is_empty() {
+            return vec![None; known.len()];
+        }
+        // the words are indexed by the position in the batch, until the entries get their indices
+        let sub_tries = self.build_sub_tries(&batch);
+
+        let mut entries = self.entries.write().unwrap();
+        let mut fl = self.free_list.lock().unwrap();
+        let mut added: Vec<u32> = Vec::with_capacity(batch.len());
+        for entry in batch {
+            match fl.pop() {
+                Some(pos) => {
+                    entries[pos] = entry;
+                    added.push(pos as u32);
+                }
+                None => {
+                    entries.push(entry);
+                    added.push(entries.len() as u32 - 1);
+                }
+            }
+        }
+        drop(fl);
+        let mut trie = self.trie.write().unwrap();
+        for t in sub_tries {
+            trie.merge_remapped(t, &added);
+        }
+        drop(trie);
+        drop(entries);
+        Metrics::count(&self.metrics.entries_added, added.len());
+        let mut added = added.into_iter();
+        known
+            .into_iter()
+            .map(|known| known.then(|| added.next().unwrap() as usize))
+            .collect()
+    }
+
+    // Builds the sub-tries of the words of the entries, in parallel as described above.
+    // The words are added with the position of their entry in the slice as the dictionary index
+    fn build_sub_tries(&self, entries: &[DictionaryEntry]) -> Vec<Trie> {
+        let threads = thread::available_parallelism()
+            .map(|n| n.get())
+            .unwrap_or(1);
+        let chunk_size = entries.len().div_ceil(threads).max(1);
+        let tokenized: Vec<ShardedWords> = thread::scope(|s| {
+            let handles: Vec<_> = entries
+                .chunks(chunk_size)
+                .enumerate()
+                .map(|(c, chunk)| {
+                    s.spawn(move || {
+                        let mut shards = vec![Vec::new(); threads];
+                        for (i, entry) in chunk.iter().enumerate() {
+                            let dictionary_pos = c * chunk_size + i;
+                            for (u, values) in &entry.0 {
+                                let attr_s = self
+                                    .reverse_attribute_map
+                                    .get(&(*u as u8))
+                                    .and_then(|name| self.attribute_map.get(name))
+                                    .map(|(_, attr_s)| attr_s);
+                                let Some(attr_s) = attr_s else {
+                                    continue;
+                                };
+                                for (ordinal, value) in values.iter().enumerate() {
+                                    for (w, pos) in self.index_words(attr_s, value) {
+                                        let shard = w
+                                            .chars()
+                                            .next()
+                                            .map(|c| idx(c) as usize % threads)
+                                            .unwrap_or(0);
+                                        shards[shard].push((
+                                            w,
+                                            dictionary_pos as u32,
+                                            *u as u8,
+                                            ordinal as u16,
+                                            pos,
+                                        ));
+                                    }
+                                }
+                            }
+                        }
+                        shards
+                    })
+                })
+                .collect();
+            handles.into_iter().map(|h| h.join().unwrap()).collect()
+        });
+
+        thread::scope(|s| {
+            let handles: Vec<_> = (0..threads)
+                .map(|shard| {
+                    let tokenized = &tokenized;
+                    s.spawn(move || {
+                        let mut t = Trie::new(self.config.clone());
+                        for words in tokenized {
+                            for (w, dictionary_index, attribute, value, pos) in &words[shard] {
+                                t.add_word(w, *dictionary_index, *attribute, *value, *pos);
+                            }
+                        }
+                        t
+                    })
+                })
+                .collect();
+            handles.into_iter().map(|h| h.join().unwrap()).collect()
+        })
+    }
+
+    // All the entries by their index, None for the deleted ones, so a restored dictionary keeps the indices
+    pub fn snapshot(&self) -> Vec<Option<HashMap<String, Vec<String>>>> {
+        let entries = self.entries.read().unwrap();
+        let free: HashSet<usize> = self.free_list.lock().unwrap().iter().copied().collect();
+        entries
+            .iter()
+            .enumerate()
+            .map(|(i, entry)| {
+                (!free.contains(&i)).then(|| {
+                    entry
+                        .0
+                        .iter()
+                        .filter_map(|(u, v)| {
+                            self.reverse_attribute_map
+                                .get(&(*u as u8))
+                                .map(|name| (name.clone(), v.clone()))
+                        })
+                        .collect()
+                })
+            })
+            .collect()
+    }
+
+    // Rebuilds a dictionary from a snapshot, the entries get the same indices they had
+    pub fn from_snapshot(
+        attrs: Vec<(String, AttributeSearch)>,
+        search_config: SearchConfig,
+        snapshot: Vec<Option<HashMap<String, Vec<String>>>>,
+    ) -> Dictionary {
+        let d = Dictionary::new(attrs, search_config);
+        let mut entries = d.entries.write().unwrap();
+        let mut fl = d.free_list.lock().unwrap();
+        for (i, data) in snapshot.into_iter().enumerate() {
+            let m: HashMap<usize, Vec<String>> = data
+                .unwrap_or_default()
+                .into_iter()
+                .filter(|(_, v)| !v.is_empty())
+                .filter_map(|(k, v)| d.attribute_map.get(&k).map(|(u, _)| (*u, v)))
+                .collect();
+            if m.is_empty() {
+                fl.push(i);
+            }
+            entries.push(DictionaryEntry(m));
+        }
+        drop(fl);
+        // the positions are the indices here, the deleted entries have no words
+        let sub_tries = d.build_sub_tries(&entries);
+        let mut trie = d.trie.write().unwrap();
+        for t in sub_tries {
+            trie.merge(t);
+        }
+        drop(trie);
+        drop(entries);
+        d
     }
+
+    pub fn config(&self) -> &SearchConfig {
+        &self.config
+    }
+
     pub fn search(&self, term: &str) -> Vec<SearchResult> {
+        self.search_with_limit(term, self.config.max_search_results)
+    }
+
+    // same as search, returning at most max_search_results instead of the configured number
+    pub fn search_with_limit(&self, term: &str, max_search_results: usize) -> Vec<SearchResult> {
+        self.search_filtered(term, max_search_results, |_| true)
+    }
+
+    // same as search_with_limit, keeping only the results the filter accepts,
+    // they are filtered before the limit is applied, so a selective filter still fills the results
+    pub fn search_filtered(
+        &self,
+        term: &str,
+        max_search_results: usize,
+        filter: impl Fn(&SearchResult) -> bool,
+    ) -> Vec<SearchResult> {
+        let start = Instant::now();
+        let ret = self.search_entries(term, max_search_results, filter);
+        self.metrics.search.observe(start.elapsed());
+        Metrics::count(&self.metrics.search_results, ret.len());
+        ret
+    }
+
+    // Search with the counts of the values of the facet attributes over all the matched entries, usually
+    // attributes that aren't searchable, like a publisher or a price. The results are limited to the entries
+    // that pass the filters of the query. The counts of a facet ignore the filters on its own attribute, so
+    // the other values of the facet can still be picked. filter is applied to the matches, as in search_filtered.
+    // An entry is a single result, with its first match, as it is counted once in the total and the counts.
+    // All the matches are searched for the counts, not only the first max_search_results
+    pub fn search_faceted(
+        &self,
+        term: &str,
+        max_search_results: usize,
+        query: &FacetQuery,
+        filter: impl Fn(&SearchResult) -> bool,
+    ) -> FacetedResults {
+        let mut filters: HashMap<usize, HashSet<&str>> = HashMap::new();
+        let mut unknown_filter = false;
+        for (attr, value) in &query.filters {
+            match self.attribute_map.get(attr) {
+                Some((u, _)) => {
+                    filters.entry(*u).or_default().insert(value.as_str());
+                }
+                None => unknown_filter = true, // no entry has a value of an unknown attribute
+            }
+        }
+        let facets: Vec<(&String, Option<usize>)> = query
+            .facets
+            .iter()
+            .map(|attr| (attr, self.attribute_map.get(attr).map(|(u, _)| *u)))
+            .collect();
+        let matches = if unknown_filter {
+            Vec::new()
+        } else {
+            self.search_filtered(term, usize::MAX, filter)
+        };
+
+        // an entry can match several times, it is counted once
+        let mut seen = HashSet::new();
+        let mut passing = HashSet::new();
+        let mut counts: Vec<HashMap<&str, usize>> = vec![HashMap::new(); facets.len()];
+        for sr in &matches {
+            if !seen.insert(sr.dictionary_index) {
+                continue;
+            }
+            let entry = &sr.dictionary_entry.0;
+            let failed: Vec<usize> = filters
+                .iter()
+                .filter(|(u, values)| {
+                    !entry
+                        .get(u)
+                        .is_some_and(|v| v.iter().any(|v| values.contains(v.as_str())))
+                })
+                .map(|(u, _)| *u)
+                .collect();
+            if failed.is_empty() {
+                passing.insert(sr.dictionary_index);
+            }
+            for ((_, u), counts) in facets.iter().zip(counts.iter_mut()) {
+                let Some(u) = u else { continue };
+                if failed.iter().any(|f| f != u) {
+                    continue;
+                }
+                let values: HashSet<&str> = entry.get(u).into_iter().flatten().map(String::as_str).collect();
+                for value in values {
+                    *counts.entry(value).or_default() += 1;
+                }
+            }
+        }
+        let facets = facets
+            .into_iter()
+            .zip(counts)
+            .map(|((attr, _), counts)| {
+                let mut counts: Vec<FacetCount> = counts
+                    .into_iter()
+                    .map(|(value, count)| FacetCount {
+                        value: value.to_string(),
+                        count,
+                    })
+                    .collect();
+                counts.sort_by(|a, b| b.count.cmp(&a.count).then_with(|| a.value.cmp(&b.value)));
+                (attr.clone(), counts)
+            })
+            .collect();
+        // one result per entry, as in the counts, with its first match
+        let mut returned = HashSet::new();
+        let results = matches
+            .into_iter()
+            .filter(|sr| passing.contains(&sr.dictionary_index) && returned.insert(sr.dictionary_index))
+            .take(max_search_results)
+            .collect();
+        FacetedResults {
+            results,
+            total: passing.len(),
+            facets,
+        }
+    }
+
+    fn search_entries(
+        &self,
+        term: &str,
+        max_search_results: usize,
+        filter: impl Fn(&SearchResult) -> bool,
+    ) -> Vec<SearchResult> {
         // term is a search term , consists of words separated by whitespace
         // in the underlying trie, we save max of DEFAULT_MULTIPLE_SEARCH_LENGTH words
         // if the term has more words, we need to get all the results from the trie for the DEFAULT_MULTIPLE_SEARCH_LENGTH words
@@ -142,10 +543,14 @@ impl Dictionary {
             return Vec::new();
         }
         let (filter_dict, trie_term) = self.longest_term(term);
+        // entries before the trie, the same order as the writers, so a search can't deadlock with an add
+        let entries_guard = self.entries.read().unwrap();
         let trie = self.trie.read().unwrap();
-        let search_res = trie.search(&trie_term.to_uppercase(), filter_dict);
+        let search_res = trie.search_with_limit(
+            &trie_term.to_uppercase(),
+            (!filter_dict).then_some(max_search_results),
+        );
         let mut ret: Vec<SearchResult> = Vec::new();
-        let entries_guard = self.entries.read().unwrap();
         let mut not_empty = true;
         let mut j = 0;
         while not_empty {
@@ -153,25 +558,33 @@ impl Dictionary {
             for TrieSearchResult { word: _, entries } in &search_res {
                 if let Some(entry) = entries.entries.get(j) {
                     not_empty = true;
-                    let (dict_index, attribute, pos, len) = entry;
+                    let (dict_index, attribute, value, pos, len) = entry;
                     if let Some(entry) = entries_guard.get(*dict_index as usize) {
                         let attr = match self.reverse_attribute_map.get(&attribute) {
                             Some(attr) => attr.as_str(),
                             None => "", //default attribute
                         };
-                        if let Some(original_entry) = entry.0.get(&(*attribute as usize)) {
+                        let original_entry = entry
+                            .0
+                            .get(&(*attribute as usize))
+                            .and_then(|values| values.get(*value as usize));
+                        if let Some(original_entry) = original_entry {
                             let w = translate_decode(original_entry, *pos as usize, *len);
                             let sr = SearchResult {
                                 term: w.to_string(),
                                 attribute: attr.to_string(),
                                 original_entry: original_entry.to_string(),
                                 attribute_index: *attribute as usize,
+                                value_index: *value as usize,
                                 position: *pos as usize,
                                 dictionary_entry: entry.clone(),
                                 dictionary_index: *dict_index as usize,
                             };
+                            if !filter(&sr) {
+                                continue;
+                            }
                             ret.push(sr);
-                            if !filter_dict && ret.len() >= self.config.max_search_results {
+                            if !filter_dict && ret.len() >= max_search_results {
                                 return ret;
                             }
                         }
@@ -192,12 +605,13 @@ impl Dictionary {
                         attribute: sr.attribute,
                         original_entry: sr.original_entry,
                         attribute_index: sr.attribute_index,
+                        value_index: sr.value_index,
                         position: sr.position,
                         dictionary_entry: sr.dictionary_entry,
                         dictionary_index: sr.dictionary_index,
                     };
                     fitered_res.push(new_sr);
-                    if fitered_res.len() >= self.config.max_search_results {
+                    if fitered_res.len() >= max_search_results {
                         return fitered_res;
                     }
                 }
@@ -207,8 +621,39 @@ impl Dictionary {
         ret
     }
 
+    // attributes with their search type, in the order they were defined
+    pub fn attributes(&self) -> Vec<(String, AttributeSearch)> {
+        let mut attrs = self
+            .attribute_map
+            .iter()
+            .map(|(attr, (u, search))| (*u, attr.clone(), *search))
+            .collect::<Vec<_>>();
+        attrs.sort_by_key(|(u, _, _)| *u);
+        attrs
+            .into_iter()
+            .map(|(_, attr, search)| (attr, search))
+            .collect()
+    }
+
+    // true if there is a live (not deleted) entry on the index
+    pub fn contains(&self, index: usize) -> bool {
+        index < self.entries.read().unwrap().len()
+            && !self.free_list.lock().unwrap().contains(&index)
+    }
+
+    // the values of a multi-valued attribute are joined with MULTI_VALUE_SEPARATOR, get_values keeps them apart
     pub fn get(&self, index: usize) -> HashMap<String, String> {
+        self.get_values(index)
+            .into_iter()
+            .map(|(k, v)| (k, v.join(MULTI_VALUE_SEPARATOR)))
+            .collect()
+    }
+
+    pub fn get_values(&self, index: usize) -> HashMap<String, Vec<String>> {
         let mut ret = HashMap::new();
+        if !self.contains(index) {
+            return ret;
+        }
         if let Some(entry) = self.entries.read().unwrap().get(index) {
             let hm = &entry.0;
             for (k, v) in hm {
@@ -223,38 +668,176 @@ impl Dictionary {
         ret
     }
 
-    pub fn delete(&mut self, index: usize) {
-        let entries = self.entries.write().unwrap();
+    pub fn stats(&self) -> DictionaryStats {
+        let entries = self.entries.read().unwrap();
+        let trie = self.trie.read().unwrap();
+        let fl = self.free_list.lock().unwrap();
+        let free: HashSet<usize> = fl.iter().copied().collect();
+        let mut stats = DictionaryStats {
+            free_slots: free.len(),
+            entries_heap_bytes: entries.capacity() * size_of::<DictionaryEntry>()
+                + fl.capacity() * size_of::<usize>(),
+            entries_per_attribute: self
+                .attribute_map
+                .keys()
+                .map(|attr| (attr.clone(), 0))
+                .collect(),
+            trie: trie.stats(),
+            ..DictionaryStats::default()
+        };
+        for (i, entry) in entries.iter().enumerate() {
+            stats.entries_heap_bytes += entry.0.capacity() * (size_of::<usize>() + size_of::<Vec<String>>());
+            for values in entry.0.values() {
+                stats.entries_heap_bytes += values.capacity() * size_of::<String>();
+                stats.entries_heap_bytes += values.iter().map(|v| v.capacity()).sum::<usize>();
+            }
+            if free.contains(&i) {
+                continue;
+            }
+            stats.entries += 1;
+            for k in entry.0.keys() {
+                if let Some(attr) = self.reverse_attribute_map.get(&(*k as u8)) {
+                    *stats.entries_per_attribute.entry(attr.clone()).or_default() += 1;
+                }
+            }
+        }
+        stats
+    }
+
+    // Counters and latencies of the operations, with the current sizes. Cheaper than stats, for frequent scraping
+    pub fn metrics(&self) -> MetricsSnapshot {
+        let entries = self.entries.read().unwrap();
+        let trie = self.trie.read().unwrap();
+        let free_slots = self.free_list.lock().unwrap().len();
+        let m = &self.metrics;
+        let search_latency = m.search.snapshot();
+        MetricsSnapshot {
+            searches: search_latency.count,
+            search_results: Metrics::load(&m.search_results),
+            entries_added: Metrics::load(&m.entries_added),
+            entries_deleted: Metrics::load(&m.entries_deleted),
+            compactions: Metrics::load(&m.compactions),
+            search_latency,
+            add_latency: m.add.snapshot(),
+            delete_latency: m.delete.snapshot(),
+            entries: entries.len() - free_slots,
+            free_slots,
+            trie_nodes: trie.node_count(),
+            free_trie_rows: trie.free_rows(),
+        }
+    }
+
+    // Integrity check of the trie, and of the trie postings against the dictionary entries.
+    // Can be run in tests, or as a startup check after loading
+    pub fn validate(&self) -> ValidationReport {
+        let entries = self.entries.read().unwrap();
+        let trie = self.trie.read().unwrap();
+        let free: HashSet<usize> = self.free_list.lock().unwrap().iter().copied().collect();
+        let mut report = trie.validate();
+        for (row, dictionary_index, attribute) in trie.postings() {
+            let index = dictionary_index as usize;
+            match entries.get(index) {
+                Some(entry) if !free.contains(&index) => {
+                    if !entry.0.contains_key(&(attribute as usize)) {
+                        report.violations.push(Violation::MissingAttribute {
+                            row,
+                            index,
+                            attribute,
+                        });
+                    }
+                }
+                _ => report.violations.push(Violation::StalePosting { row, index }),
+            }
+        }
+        report
+    }
+
+    // Deleting an entry only frees its slot, compaction removes the free slots from the entries vector
+    // and the free rows from the trie. The entries are renumbered, the returned map is old index -> new index
+    // for all the live entries.
+    pub fn compact(&self) -> HashMap<usize, usize> {
+        let mut entries = self.entries.write().unwrap();
         let mut trie = self.trie.write().unwrap();
-        if let Some(entry) = entries.get(index) {
-            let hm = &entry.0;
-            for (k, v) in hm {
-                let uk = *k as u8;
-                if let Some(str) = self.reverse_attribute_map.get(&uk){
-                    if let Some(attrs) = self.attribute_map.get(str){
-                        match attrs.1 {
-                            AttributeSearch::None => (),
-                            AttributeSearch::Exact => trie.delete_word(&v, index as u32, uk),
-                            AttributeSearch::Multiple => {
-                                let v = self.split_word(&v);
-                                for (s, _, _) in v {
-                                    trie.delete_word(&s, index as u32, uk);
-                                }
-                            }
+        let mut fl = self.free_list.lock().unwrap();
+        let free: HashSet<usize> = fl.drain(..).collect();
+        let mut remap = HashMap::new();
+        let old_entries = std::mem::take(&mut *entries);
+        for (i, entry) in old_entries.into_iter().enumerate() {
+            if !free.contains(&i) {
+                remap.insert(i, entries.len());
+                entries.push(entry);
+            }
+        }
+        entries.shrink_to_fit();
+        fl.shrink_to_fit();
+        trie.remap_dictionary_indices(&remap);
+        trie.compact();
+        Metrics::count(&self.metrics.compactions, 1);
+        remap
+    }
+
+    // Returns the index remapping if the deletion crossed the compact_threshold and the entries were compacted,
+    // NotFound if there was no entry to delete. Two concurrent deletes of an index can't both succeed
+    pub fn delete(&self, index: usize) -> Result<Option<HashMap<usize, usize>>, NotFound> {
+        let start = Instant::now();
+        let remap = self.delete_entry(index);
+        self.metrics.delete.observe(start.elapsed());
+        remap
+    }
+
+    // removes the words of the entry from the trie
+    fn unindex_entry(&self, trie: &mut Trie, index: usize, entry: &DictionaryEntry) {
+        for (k, values) in &entry.0 {
+            let uk = *k as u8;
+            if let Some(str) = self.reverse_attribute_map.get(&uk){
+                if let Some(attrs) = self.attribute_map.get(str){
+                    for (ordinal, v) in values.iter().enumerate() {
+                        for (s, _) in self.index_words(&attrs.1, v) {
+                            trie.delete_word(&s, index as u32, uk, ordinal as u16);
                         }
                     }
                 }
             }
         }
+    }
+
+    fn delete_entry(&self, index: usize) -> Result<Option<HashMap<usize, usize>>, NotFound> {
+        let entries = self.entries.write().unwrap();
+        // checked under the entries lock, deleting twice would put the slot twice on the free list
+        if index >= entries.len() || self.free_list.lock().unwrap().contains(&index) {
+            return Err(NotFound);
+        }
+        let mut trie = self.trie.write().unwrap();
+        if let Some(entry) = entries.get(index) {
+            self.unindex_entry(&mut trie, index, entry);
+        }
         let mut fl = self.free_list.lock().unwrap();
         fl.push(index);
+        Metrics::count(&self.metrics.entries_deleted, 1);
+        let Some(threshold) = self.config.compact_threshold else {
+            return Ok(None);
+        };
+        let dictionary_fragmentation = fl.len() as f32 / entries.len() as f32;
+        drop(fl);
+        drop(entries);
+        if dictionary_fragmentation > threshold {
+            drop(trie);
+            return Ok(Some(self.compact()));
+        }
+        if trie.fragmentation() > threshold {
+            // trie rows are internal, no index changes visible to the caller
+            trie.compact();
+        }
+        Ok(None)
     }
 }
 
 #[cfg(test)]
 mod test {
-    use crate::constants::SearchConfig;
-    use crate::dictionary::{AttributeSearch, Dictionary};
+    use crate::constants::{SearchConfig, MULTI_VALUE_SEPARATOR};
+    use crate::dictionary::{AttributeSearch, Dictionary, NotFound};
+    use crate::facets::{FacetCount, FacetQuery};
+    use crate::validation::Violation;
     use std::collections::HashMap;
 
     fn prepare_dictionary() -> Dictionary {
@@ -340,6 +923,454 @@ mod test {
         assert_eq!(z.len(), 2);
     }
 
+    #[test]
+    fn test_search_with_limit() {
+        let d = prepare_dictionary();
+        assert_eq!(d.search_with_limit("TOY", 1).len(), 1);
+        let config = SearchConfig {
+            max_search_results: 1,
+            ..SearchConfig::default()
+        };
+        let d = Dictionary::new(vec![("car".to_string(), AttributeSearch::Exact)], config);
+        for car in ["Camry", "Camaro", "Caminho"] {
+            d.add_dictionary_entry(HashMap::from([("car".to_string(), car.to_string())]));
+        }
+        assert_eq!(d.search("CAM").len(), 1);
+        assert_eq!(d.search_with_limit("CAM", 5).len(), 3);
+    }
+
+    #[test]
+    fn test_search_filtered() {
+        let config = SearchConfig {
+            max_search_results: 1,
+            ..SearchConfig::default()
+        };
+        let d = Dictionary::new(
+            vec![
+                ("car".to_string(), AttributeSearch::Exact),
+                ("manufacturer".to_string(), AttributeSearch::Exact),
+            ],
+            config,
+        );
+        d.add_dictionary_entry(HashMap::from([("manufacturer".to_string(), "Camco".to_string())]));
+        d.add_dictionary_entry(HashMap::from([("car".to_string(), "Camry".to_string())]));
+        let z = d.search_filtered("CAM", 1, |sr| sr.attribute == "car");
+        assert_eq!(z.len(), 1);
+        assert_eq!(z[0].original_entry, "Camry");
+        assert!(d.search_filtered("CAM", 5, |_| false).is_empty());
+    }
+
+    #[test]
+    fn test_add_dictionary_entries() {
+        let m = vec![
+            ("car".to_string(), AttributeSearch::Multiple),
+            ("manufacturer".to_string(), AttributeSearch::Exact),
+            ("serial_number".to_string(), AttributeSearch::None),
+        ];
+        let d = Dictionary::new(m, SearchConfig::default());
+        let added = d.add_dictionary_entries(vec![
+            HashMap::from([
+                ("manufacturer".to_string(), "Toyota".to_string()),
+                ("car".to_string(), "Corolla".to_string()),
+                ("serial_number".to_string(), "123456".to_string()),
+            ]),
+            HashMap::from([
+                ("manufacturer".to_string(), "Subaru".to_string()),
+                ("car".to_string(), "Outback Wilderness".to_string()),
+            ]),
+            HashMap::from([("color".to_string(), "red".to_string())]),
+            HashMap::from([
+                ("manufacturer".to_string(), "Toyota".to_string()),
+                ("car".to_string(), "Camry".to_string()),
+            ]),
+        ]);
+        assert_eq!(added, vec![Some(0), Some(1), None, Some(2)]);
+        assert_eq!(d.search("TOY").len(), 2);
+        assert_eq!(d.search("wild")[0].term, "Wilderness");
+        assert_eq!(d.search("Camry")[0].dictionary_index, 2);
+        // the parallel path merges into an already populated trie
+        let d = prepare_dictionary();
+        d.add_dictionary_entries(vec![HashMap::from([
+            ("manufacturer".to_string(), "Toyota".to_string()),
+            ("car".to_string(), "Corona".to_string()),
+        ])]);
+        assert_eq!(d.search("TOY").len(), 3);
+        assert_eq!(d.search("COR").len(), 2);
+        // the freed slots are reused, and the words get the indices of the slots
+        d.delete(1).unwrap();
+        let added = d.add_dictionary_entries(vec![
+            HashMap::from([("car".to_string(), "Impreza".to_string())]),
+            HashMap::from([("car".to_string(), "Forester".to_string())]),
+        ]);
+        assert_eq!(added, vec![Some(1), Some(5)]);
+        let indices: Vec<usize> = ["Impreza", "Forester"]
+            .iter()
+            .map(|car| d.search(car)[0].dictionary_index)
+            .collect();
+        assert_eq!(indices, vec![1, 5]);
+        assert_eq!(d.get(d.search("Impreza")[0].dictionary_index)["car"], "Impreza");
+        assert!(d.validate().is_ok());
+    }
+
+    #[test]
+    fn test_compact() {
+        let d = prepare_dictionary();
+        d.delete(0).unwrap();
+        d.delete(2).unwrap();
+        let remap = d.compact();
+        assert_eq!(remap, HashMap::from([(1, 0), (3, 1)]));
+        assert_eq!(d.entries.read().unwrap().len(), 2);
+        assert!(d.search("COR").is_empty());
+        let z = d.search("TOY");
+        assert_eq!(z.len(), 1);
+        assert_eq!(z[0].dictionary_index, 1);
+        assert_eq!(d.get(1)["car"], "Camry");
+        assert_eq!(d.search("Outback")[0].dictionary_index, 0);
+    }
+
+    #[test]
+    fn test_auto_compact() {
+        let m = vec![("car".to_string(), AttributeSearch::Multiple)];
+        let config = SearchConfig {
+            compact_threshold: Some(0.4),
+            ..SearchConfig::default()
+        };
+        let d = Dictionary::new(m, config);
+        for car in ["Corolla", "Outback", "Accord"] {
+            d.add_dictionary_entry(HashMap::from([("car".to_string(), car.to_string())]));
+        }
+        assert_eq!(d.delete(0), Ok(None));
+        let remap = d.delete(1).unwrap().unwrap();
+        assert_eq!(remap, HashMap::from([(2, 0)]));
+        assert_eq!(d.search("Acc")[0].dictionary_index, 0);
+    }
+
+    #[test]
+    fn test_stats() {
+        let d = prepare_dictionary();
+        d.add_dictionary_entry(HashMap::from([("car".to_string(), "Golf".to_string())]));
+        d.delete(1).unwrap();
+        let stats = d.stats();
+        assert_eq!(stats.entries, 4);
+        assert_eq!(stats.free_slots, 1);
+        assert_eq!(stats.entries_per_attribute["car"], 4);
+        assert_eq!(stats.entries_per_attribute["manufacturer"], 3);
+        assert_eq!(stats.entries_per_attribute["serial_number"], 3);
+        // Corolla, Accord, Camry, Golf, Toyota (two entries), Honda
+        assert_eq!(stats.trie.postings, 7);
+        assert_eq!(stats.trie.depth_histogram[0], 1);
+        assert_eq!(
+            stats.trie.depth_histogram.iter().sum::<usize>(),
+            stats.trie.direct_nodes + stats.trie.bitmap_nodes
+        );
+        assert!(stats.heap_bytes() > stats.trie.heap_bytes());
+    }
+
+    #[test]
+    fn test_validate() {
+        let d = prepare_dictionary();
+        assert!(d.validate().is_ok());
+        d.delete(1).unwrap();
+        d.delete(3).unwrap();
+        assert!(d.validate().is_ok());
+        d.compact();
+        assert!(d.validate().is_ok());
+        // entry removed from the dictionary behind the trie's back
+        d.free_list.lock().unwrap().push(0);
+        let report = d.validate();
+        assert!(!report.is_ok());
+        assert!(report
+            .violations
+            .iter()
+            .all(|v| matches!(v, Violation::StalePosting { index: 0, .. })));
+    }
+
+    #[test]
+    fn test_get_and_delete() {
+        let d = prepare_dictionary();
+        assert_eq!(d.get(1)["car"], "Outback");
+        d.delete(1).unwrap();
+        assert!(!d.contains(1));
+        assert!(d.get(1).is_empty());
+        assert_eq!(d.delete(1), Err(NotFound));
+        assert_eq!(d.delete(10), Err(NotFound));
+        assert_eq!(*d.free_list.lock().unwrap(), vec![1]);
+        let index = d.add_dictionary_entry(HashMap::from([("car".to_string(), "Golf".to_string())]));
+        assert_eq!(index, Some(1));
+        let index = d.add_dictionary_entry(HashMap::from([("color".to_string(), "red".to_string())]));
+        assert_eq!(index, None);
+        assert_eq!(
+            d.attributes(),
+            vec![
+                ("car".to_string(), AttributeSearch::Multiple),
+                ("manufacturer".to_string(), AttributeSearch::Exact),
+                ("serial_number".to_string(), AttributeSearch::None),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_metrics() {
+        let d = prepare_dictionary();
+        let added = d.metrics();
+        assert_eq!(added.entries_added, 4);
+        assert_eq!(added.add_latency.count, 4);
+        assert_eq!(added.entries, 4);
+        assert!(added.trie_nodes > 0);
+
+        assert_eq!(d.search("Toy").len(), 2);
+        assert_eq!(d.search("Cam").len(), 1);
+        assert_eq!(d.delete(0), Ok(None));
+        assert_eq!(d.delete(0), Err(NotFound)); // already deleted, not counted
+        let m = d.metrics();
+        assert_eq!(m.searches, 2);
+        assert_eq!(m.search_results, 3);
+        assert_eq!(m.search_latency.buckets.last().unwrap().1, 2);
+        assert_eq!(m.entries_deleted, 1);
+        assert_eq!(m.delete_latency.count, 2);
+        assert_eq!((m.entries, m.free_slots), (3, 1));
+        assert!(m.free_trie_rows > 0);
+        assert_eq!(m.trie_nodes + m.free_trie_rows, added.trie_nodes);
+
+        d.compact();
+        let m = d.metrics();
+        assert_eq!((m.compactions, m.free_slots, m.free_trie_rows), (1, 0, 0));
+    }
+
+    #[test]
+    fn test_replace_entry() {
+        let d = prepare_dictionary();
+        assert!(d.replace_dictionary_entry(
+            1,
+            HashMap::from([
+                ("manufacturer".to_string(), "Mazda".to_string()),
+                ("car".to_string(), "Miata".to_string()),
+            ])
+        ));
+        assert!(d.search("Outback").is_empty());
+        assert!(d.search("Subaru").is_empty());
+        let res = d.search("Miata");
+        assert_eq!(res.len(), 1);
+        assert_eq!(res[0].dictionary_index, 1);
+        assert_eq!(d.get(1).get("manufacturer").map(String::as_str), Some("Mazda"));
+        // the serial number wasn't in the replacement
+        assert!(!d.get(1).contains_key("serial_number"));
+
+        d.delete(2).unwrap();
+        let mazda = HashMap::from([("manufacturer".to_string(), "Mazda".to_string())]);
+        assert!(!d.replace_dictionary_entry(2, mazda.clone()));
+        assert!(!d.replace_dictionary_entry(10, mazda));
+        assert!(!d.replace_dictionary_entry(
+            1,
+            HashMap::from([("unknown".to_string(), "x".to_string())])
+        ));
+        assert_eq!(d.search("Miata").len(), 1);
+        // the free slot is still the one reused by the next add
+        assert_eq!(
+            d.add_dictionary_entry(HashMap::from([("car".to_string(), "Civic".to_string())])),
+            Some(2)
+        );
+        assert!(d.validate().is_ok());
+    }
+
+    #[test]
+    fn test_search_faceted() {
+        let d = Dictionary::new(
+            vec![
+                ("title".to_string(), AttributeSearch::Multiple),
+                ("publisher".to_string(), AttributeSearch::None),
+                ("tag".to_string(), AttributeSearch::None),
+            ],
+            SearchConfig::default(),
+        );
+        for (title, publisher, tags) in [
+            ("Penguin Island", "Gallimard", vec!["novel", "satire"]),
+            ("The Penguin Lessons", "Penguin", vec!["memoir"]),
+            ("Penguins of the World", "Penguin", vec!["nature", "nature"]),
+            ("Emperor Penguin", "Penguin", vec!["nature"]),
+            ("Island", "Penguin", vec!["novel"]),
+        ] {
+            d.add_multi_valued_entry(HashMap::from([
+                ("title".to_string(), vec![title.to_string()]),
+                ("publisher".to_string(), vec![publisher.to_string()]),
+                ("tag".to_string(), tags.into_iter().map(String::from).collect()),
+            ]));
+        }
+        let count = |value: &str, count| FacetCount {
+            value: value.to_string(),
+            count,
+        };
+        let query = FacetQuery {
+            facets: vec!["publisher".to_string(), "tag".to_string()],
+            filters: vec![],
+        };
+        let res = d.search_faceted("Penguin", 2, &query, |_| true);
+        assert_eq!(res.results.len(), 2);
+        assert_eq!(res.total, 4);
+        assert_eq!(
+            res.facets[0],
+            ("publisher".to_string(), vec![count("Penguin", 3), count("Gallimard", 1)])
+        );
+        // an entry is counted once per value, "nature" is twice in one entry
+        assert_eq!(res.facets[1].1[0], count("nature", 2));
+
+        let query = FacetQuery {
+            facets: vec!["publisher".to_string(), "tag".to_string()],
+            filters: vec![
+                ("publisher".to_string(), "Penguin".to_string()),
+                ("tag".to_string(), "nature".to_string()),
+                ("tag".to_string(), "memoir".to_string()),
+            ],
+        };
+        let res = d.search_faceted("Penguin", 10, &query, |_| true);
+        assert_eq!(res.total, 3);
+        assert!(res.results.iter().all(|sr| sr.dictionary_index != 0));
+        // the counts of a facet ignore its own filters
+        assert_eq!(res.facets[0].1, vec![count("Penguin", 3)]);
+        assert_eq!(res.facets[1].1, vec![count("nature", 2), count("memoir", 1)]);
+
+        let query = FacetQuery {
+            facets: vec!["unknown".to_string()],
+            filters: vec![("publisher".to_string(), "Penguin".to_string())],
+        };
+        let res = d.search_faceted("Penguin", 10, &query, |sr| sr.dictionary_index != 1);
+        assert_eq!(res.total, 2);
+        assert_eq!(res.facets, vec![("unknown".to_string(), vec![])]);
+        let query = FacetQuery {
+            facets: vec![],
+            filters: vec![("unknown".to_string(), "x".to_string())],
+        };
+        assert_eq!(d.search_faceted("Penguin", 10, &query, |_| true).total, 0);
+
+        // an entry matching through two of its values is a single result
+        d.add_multi_valued_entry(HashMap::from([
+            ("title".to_string(), vec!["Penguin Modern".to_string(), "Penguin Poets".to_string()]),
+            ("publisher".to_string(), vec!["Penguin".to_string()]),
+        ]));
+        let query = FacetQuery {
+            facets: vec!["publisher".to_string()],
+            filters: vec![("publisher".to_string(), "Penguin".to_string())],
+        };
+        assert!(d.search_filtered("Penguin", 100, |sr| sr.dictionary_index == 5).len() > 1);
+        let res = d.search_faceted("Penguin", 10, &query, |_| true);
+        assert_eq!(res.total, 4);
+        assert_eq!(res.results.len(), 4);
+        assert_eq!(res.results.iter().filter(|sr| sr.dictionary_index == 5).count(), 1);
+        assert_eq!(res.facets[0].1[0], count("Penguin", 4));
+    }
+
+    #[test]
+    fn test_snapshot_restore() {
+        let d = prepare_dictionary();
+        d.delete(1).unwrap();
+        let snapshot = d.snapshot();
+        assert_eq!(snapshot.len(), 4);
+        assert!(snapshot[1].is_none());
+        let r = Dictionary::from_snapshot(d.attributes(), d.config().clone(), snapshot);
+        for i in [0, 2, 3] {
+            assert_eq!(r.get(i), d.get(i));
+        }
+        assert!(!r.contains(1));
+        let mut p = r
+            .search("TOY")
+            .iter()
+            .map(|sr| sr.dictionary_index)
+            .collect::<Vec<_>>();
+        p.sort();
+        assert_eq!(p, vec![0, 3]);
+        assert!(r.validate().is_ok());
+        let index = r.add_dictionary_entry(HashMap::from([("car".to_string(), "Golf".to_string())]));
+        assert_eq!(index, Some(1));
+        let empty = Dictionary::from_snapshot(d.attributes(), d.config().clone(), vec![]);
+        assert!(empty.search("TOY").is_empty());
+    }
+
+    #[test]
+    fn test_multi_valued_entry() {
+        let d = Dictionary::new(
+            vec![
+                ("title".to_string(), AttributeSearch::Multiple),
+                ("author".to_string(), AttributeSearch::Exact),
+            ],
+            SearchConfig::default(),
+        );
+        let authors = vec!["Terry Pratchett".to_string(), "Neil Gaiman".to_string()];
+        let index = d.add_multi_valued_entry(HashMap::from([
+            ("title".to_string(), vec!["Good Omens".to_string()]),
+            ("author".to_string(), authors.clone()),
+        ]));
+        assert_eq!(index, Some(0));
+        d.add_multi_valued_entries(vec![HashMap::from([
+            ("title".to_string(), vec!["Nation".to_string()]),
+            ("author".to_string(), vec!["Terry Pratchett".to_string()]),
+            ("year".to_string(), vec![]),
+        ])]);
+
+        // the exact search matches the start of every value, not only the first one
+        let z = d.search("NEIL");
+        assert_eq!(z.len(), 1);
+        assert_eq!(z[0].original_entry, "Neil Gaiman");
+        assert_eq!(z[0].value_index, 1);
+        assert_eq!(d.search("TERRY").len(), 2);
+        // the values are indexed one by one, no n-gram crosses from one to the next
+        assert!(d.search("PRATCHETT NEIL").is_empty());
+
+        assert_eq!(d.get_values(0)["author"], authors);
+        assert_eq!(d.get(0)["author"], authors.join(MULTI_VALUE_SEPARATOR));
+        assert_eq!(d.snapshot()[0].as_ref().unwrap()["author"], authors);
+        assert!(d.validate().is_ok());
+
+        d.delete(0).unwrap();
+        assert!(d.search("NEIL").is_empty());
+        assert!(d.validate().is_ok());
+
+        // the last characters of both values are on the same row, each value is found
+        let index = d
+            .add_multi_valued_entry(HashMap::from([(
+                "author".to_string(),
+                vec!["Ann".to_string(), "Anx".to_string()],
+            )]))
+            .unwrap();
+        let z = d.search("ANX");
+        assert!(z.iter().any(|sr| sr.dictionary_index == index
+            && sr.value_index == 1
+            && sr.original_entry == "Anx"));
+        d.delete(index).unwrap();
+        assert!(d.search("ANN").is_empty());
+        assert!(d.search("ANX").is_empty());
+        assert!(d.validate().is_ok());
+    }
+
+    #[test]
+    fn test_concurrent_add_and_search() {
+        let d = Dictionary::new(
+            vec![("name".to_string(), AttributeSearch::Multiple)],
+            SearchConfig::default(),
+        );
+        std::thread::scope(|s| {
+            s.spawn(|| {
+                for i in 0..200 {
+                    let name = format!("Person{} Surname{}", i, i % 7);
+                    let index = d.add_dictionary_entry(HashMap::from([("name".to_string(), name)]));
+                    if i % 3 == 0 {
+                        d.delete(index.unwrap()).unwrap();
+                    }
+                }
+            });
+            for _ in 0..4 {
+                s.spawn(|| {
+                    for _ in 0..200 {
+                        for sr in d.search("Surname") {
+                            assert!(sr.original_entry.contains(&sr.term));
+                        }
+                    }
+                });
+            }
+        });
+        assert!(d.validate().is_ok());
+        assert_eq!(d.stats().entries, 133);
+    }
+
     #[test]
     fn test_case_sensitivity() {
         let d = prepare_dictionary();
@@ -0,0 +1,23 @@
+use crate::dictionary::SearchResult;
+
+/// Facets and filters of `Dictionary::search_faceted`
+#[derive(Debug, Clone, Default)]
+pub struct FacetQuery {
+    pub facets: Vec<String>, // attributes to count the values of
+    // (attribute, value), an entry needs one of the values given for every filtered attribute
+    pub filters: Vec<(String, String)>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FacetCount {
+    pub value: String,
+    pub count: usize, // matched entries with the value
+}
+
+/// Results of `Dictionary::search_faceted`
+pub struct FacetedResults {
+    pub results: Vec<SearchResult>,
+    pub total: usize, // matched entries that pass the filters, regardless of the limit
+    // the counts of every facet of the query, in the same order, the most common values first
+    pub facets: Vec<(String, Vec<FacetCount>)>,
+}
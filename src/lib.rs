@@ -1,4 +1,8 @@
 pub mod dictionary;
 pub mod constants;
+pub mod facets;
+pub mod metrics;
+pub mod stats;
+pub mod validation;
 mod encoding;
 mod trie;
\ No newline at end of file
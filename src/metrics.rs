@@ -0,0 +1,206 @@
+use std::fmt::Write;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+
+/// Upper bounds of the latency histogram buckets, in seconds
+pub const LATENCY_BUCKETS: [f64; 16] = [
+    0.00001, 0.000025, 0.00005, 0.0001, 0.00025, 0.0005, 0.001, 0.0025, 0.005, 0.01, 0.025, 0.05,
+    0.1, 0.25, 0.5, 1.0,
+];
+
+// Latency histogram, lock free so the searches don't contend on it.
+// Each observation is counted in its own bucket only, the buckets are made cumulative by the snapshot
+#[derive(Default)]
+pub(crate) struct Histogram {
+    buckets: [AtomicU64; LATENCY_BUCKETS.len()],
+    count: AtomicU64,
+    sum_nanos: AtomicU64,
+}
+
+impl Histogram {
+    pub(crate) fn observe(&self, elapsed: Duration) {
+        let secs = elapsed.as_secs_f64();
+        // slower than the last bucket is only in the count, which is the +Inf bucket
+        if let Some(i) = LATENCY_BUCKETS.iter().position(|bound| secs <= *bound) {
+            self.buckets[i].fetch_add(1, Ordering::Relaxed);
+        }
+        self.count.fetch_add(1, Ordering::Relaxed);
+        self.sum_nanos
+            .fetch_add(elapsed.as_nanos() as u64, Ordering::Relaxed);
+    }
+
+    pub(crate) fn snapshot(&self) -> HistogramSnapshot {
+        let mut cumulative = 0;
+        let buckets = LATENCY_BUCKETS
+            .iter()
+            .zip(&self.buckets)
+            .map(|(bound, count)| {
+                cumulative += count.load(Ordering::Relaxed);
+                (*bound, cumulative)
+            })
+            .collect();
+        HistogramSnapshot {
+            buckets,
+            count: self.count.load(Ordering::Relaxed),
+            sum_secs: self.sum_nanos.load(Ordering::Relaxed) as f64 / 1e9,
+        }
+    }
+}
+
+// Counters of the dictionary operations, updated by the operations themselves
+#[derive(Default)]
+pub(crate) struct Metrics {
+    pub(crate) search: Histogram,
+    pub(crate) add: Histogram,
+    pub(crate) delete: Histogram,
+    pub(crate) search_results: AtomicU64,
+    pub(crate) entries_added: AtomicU64,
+    pub(crate) entries_deleted: AtomicU64,
+    pub(crate) compactions: AtomicU64,
+}
+
+impl Metrics {
+    pub(crate) fn count(counter: &AtomicU64, n: usize) {
+        counter.fetch_add(n as u64, Ordering::Relaxed);
+    }
+
+    pub(crate) fn load(counter: &AtomicU64) -> u64 {
+        counter.load(Ordering::Relaxed)
+    }
+}
+
+/// Cumulative buckets of a latency histogram, (upper bound in seconds, observations up to the bound)
+#[derive(Debug, Clone, Default)]
+pub struct HistogramSnapshot {
+    pub buckets: Vec<(f64, u64)>,
+    pub count: u64, // all the observations, the +Inf bucket
+    pub sum_secs: f64,
+}
+
+/// Counters, latencies and sizes of a dictionary, returned by `Dictionary::metrics`.
+/// The counters start with the dictionary, a dictionary restored from a snapshot starts from zero.
+#[derive(Debug, Clone, Default)]
+pub struct MetricsSnapshot {
+    pub searches: u64,
+    pub search_results: u64,
+    pub entries_added: u64, // by the single and the bulk adds
+    pub entries_deleted: u64,
+    pub compactions: u64,
+    pub search_latency: HistogramSnapshot,
+    pub add_latency: HistogramSnapshot, // single entry adds, the bulk adds are only counted in entries_added
+    pub delete_latency: HistogramSnapshot,
+    pub entries: usize, // live entries
+    pub free_slots: usize,
+    pub trie_nodes: usize, // live trie rows
+    pub free_trie_rows: usize,
+}
+
+type Counter = (&'static str, &'static str, fn(&MetricsSnapshot) -> u64);
+type Latency = (&'static str, &'static str, fn(&MetricsSnapshot) -> &HistogramSnapshot);
+
+const COUNTERS: [Counter; 5] = [
+    ("bitmap_trie_searches_total", "Searches run", |m| m.searches),
+    ("bitmap_trie_search_results_total", "Results returned by the searches", |m| m.search_results),
+    ("bitmap_trie_entries_added_total", "Entries added", |m| m.entries_added),
+    ("bitmap_trie_entries_deleted_total", "Entries deleted", |m| m.entries_deleted),
+    ("bitmap_trie_compactions_total", "Compactions of the dictionary", |m| m.compactions),
+];
+
+const GAUGES: [Counter; 4] = [
+    ("bitmap_trie_entries", "Live entries", |m| m.entries as u64),
+    ("bitmap_trie_free_slots", "Entry slots on the free list", |m| m.free_slots as u64),
+    ("bitmap_trie_nodes", "Live trie nodes", |m| m.trie_nodes as u64),
+    ("bitmap_trie_free_rows", "Trie rows on the free list", |m| m.free_trie_rows as u64),
+];
+
+const LATENCIES: [Latency; 3] = [
+    ("bitmap_trie_search_duration_seconds", "Latency of the searches", |m| &m.search_latency),
+    ("bitmap_trie_add_duration_seconds", "Latency of the single entry adds", |m| &m.add_latency),
+    ("bitmap_trie_delete_duration_seconds", "Latency of the deletes", |m| &m.delete_latency),
+];
+
+fn escape_label(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+/// Prometheus text exposition of the metrics of the named dictionaries, the name is the `dictionary` label
+pub fn prometheus_text(dictionaries: &[(&str, &MetricsSnapshot)]) -> String {
+    let mut out = String::new();
+    let labeled: Vec<_> = dictionaries
+        .iter()
+        .map(|(name, metrics)| (format!("dictionary=\"{}\"", escape_label(name)), *metrics))
+        .collect();
+    for (kind, families) in [("counter", &COUNTERS[..]), ("gauge", &GAUGES[..])] {
+        for (name, help, value) in families {
+            let _ = writeln!(out, "# HELP {} {}\n# TYPE {} {}", name, help, name, kind);
+            for (labels, metrics) in &labeled {
+                let _ = writeln!(out, "{}{{{}}} {}", name, labels, value(metrics));
+            }
+        }
+    }
+    for (name, help, histogram) in LATENCIES {
+        let _ = writeln!(out, "# HELP {} {}\n# TYPE {} histogram", name, help, name);
+        for (labels, metrics) in &labeled {
+            let histogram = histogram(metrics);
+            for (bound, count) in &histogram.buckets {
+                let _ = writeln!(out, "{}_bucket{{{},le=\"{}\"}} {}", name, labels, bound, count);
+            }
+            let _ = writeln!(out, "{}_bucket{{{},le=\"+Inf\"}} {}", name, labels, histogram.count);
+            let _ = writeln!(out, "{}_sum{{{}}} {}", name, labels, histogram.sum_secs);
+            let _ = writeln!(out, "{}_count{{{}}} {}", name, labels, histogram.count);
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_histogram_buckets() {
+        let histogram = Histogram::default();
+        histogram.observe(Duration::from_micros(5));
+        histogram.observe(Duration::from_micros(700));
+        histogram.observe(Duration::from_secs(2));
+        let snapshot = histogram.snapshot();
+        assert_eq!(snapshot.count, 3);
+        assert_eq!(snapshot.buckets[0], (0.00001, 1));
+        // cumulative, the 700µs observation is in the 1ms bucket and all the following ones
+        assert_eq!(snapshot.buckets[5], (0.0005, 1));
+        assert_eq!(snapshot.buckets[6], (0.001, 2));
+        assert_eq!(snapshot.buckets.last(), Some(&(1.0, 2)));
+        assert!((snapshot.sum_secs - 2.000705).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_prometheus_text() {
+        let metrics = MetricsSnapshot {
+            searches: 4,
+            entries: 10,
+            search_latency: HistogramSnapshot {
+                buckets: vec![(0.001, 3), (1.0, 4)],
+                count: 4,
+                sum_secs: 0.5,
+            },
+            ..MetricsSnapshot::default()
+        };
+        let text = prometheus_text(&[("books", &metrics), ("a\"b", &MetricsSnapshot::default())]);
+        assert!(text.contains("# TYPE bitmap_trie_searches_total counter\n"));
+        assert!(text.contains("bitmap_trie_searches_total{dictionary=\"books\"} 4\n"));
+        assert!(text.contains("bitmap_trie_searches_total{dictionary=\"a\\\"b\"} 0\n"));
+        assert!(text.contains("bitmap_trie_entries{dictionary=\"books\"} 10\n"));
+        assert!(text.contains(
+            "bitmap_trie_search_duration_seconds_bucket{dictionary=\"books\",le=\"0.001\"} 3\n"
+        ));
+        assert!(text.contains(
+            "bitmap_trie_search_duration_seconds_bucket{dictionary=\"books\",le=\"+Inf\"} 4\n"
+        ));
+        assert!(text.contains("bitmap_trie_search_duration_seconds_sum{dictionary=\"books\"} 0.5\n"));
+        // one HELP and TYPE per metric, whatever the number of dictionaries
+        assert_eq!(text.matches("# TYPE bitmap_trie_entries gauge").count(), 1);
+    }
+}
@@ -0,0 +1,39 @@
+use std::collections::HashMap;
+
+/// Shape and memory use of the trie, returned by `Dictionary::stats`.
+/// Heap sizes are estimates, based on the capacity of the underlying vectors and maps.
+#[derive(Debug, Clone, Default)]
+pub struct TrieStats {
+    pub direct_nodes: usize, // TrieEntryV nodes
+    pub bitmap_nodes: usize, // TrieEntryG nodes
+    pub free_rows: usize,
+    pub average_fan_out: f64,
+    pub depth_histogram: Vec<usize>, // number of nodes on each depth, the root is on depth 0
+    pub dictionary_map_entries: usize,
+    pub postings: usize, // dictionary entry references in the dictionary map
+    pub nodes_heap_bytes: usize,
+    pub dictionary_map_heap_bytes: usize,
+    pub free_list_heap_bytes: usize,
+}
+
+impl TrieStats {
+    pub fn heap_bytes(&self) -> usize {
+        self.nodes_heap_bytes + self.dictionary_map_heap_bytes + self.free_list_heap_bytes
+    }
+}
+
+/// Size of the dictionary and its trie, used for capacity planning and tuning of `SearchConfig`
+#[derive(Debug, Clone, Default)]
+pub struct DictionaryStats {
+    pub entries: usize, // live entries, without the free slots
+    pub free_slots: usize,
+    pub entries_per_attribute: HashMap<String, usize>,
+    pub entries_heap_bytes: usize,
+    pub trie: TrieStats,
+}
+
+impl DictionaryStats {
+    pub fn heap_bytes(&self) -> usize {
+        self.entries_heap_bytes + self.trie.heap_bytes()
+    }
+}
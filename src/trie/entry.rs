@@ -242,3 +242,15 @@ impl TrieEntryG {
         entry
     }
 }
+
+impl TrieEntryV {
+    pub fn demote(trie_entry: &TrieEntryG) -> Self {
+        let mut entry = Vec::with_capacity(trie_entry.positions.len());
+        for i in 0..64 {
+            if let Some(node) = trie_entry.get(i) {
+                entry.push((i, *node));
+            }
+        }
+        TrieEntryV(entry)
+    }
+}
@@ -6,8 +6,11 @@ pub use self::entry::{
     NodeIndex, TrieEntry, TrieEntryG, TrieEntryOp, TrieEntryV,
 };
 use crate::encoding::idx;
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet, VecDeque};
 use crate::constants::{SearchConfig};
+use crate::stats::TrieStats;
+use crate::validation::{ValidationReport, Violation};
+use std::mem::size_of;
 /*
 This is the Trie implementation for contextual search.
 The primary use case is autocomplete search in context.
@@ -26,10 +29,11 @@ In node index we store the address of dictionary entries, and from there we do t
 
 #[derive(Debug, Clone, Eq, PartialEq, Ord, PartialOrd)]
 pub struct DictionaryMapEntry {
-    pub entries: Vec<(u32, u8, u16, u16)>,
+    pub entries: Vec<(u32, u8, u16, u16, u16)>,
     // each terminated word in trie maps to one dictionary entry and one attribute (if no attribute, use default attribute 0)
     // attribute is expected as u8, the dictionary itself should keep the mapping of attributes(if there is one)
-    // the last entry is the word position in the original entry
+    // then the ordinal of the value, for the attributes with multiple values,
+    // and the word position and length in that value
 }
 
 #[derive(Debug)]
@@ -63,6 +67,13 @@ pub struct Trie {
 
 impl Trie {
     pub fn new(search_config: SearchConfig) -> Self {
+        // otherwise a node would be demoted right after it was promoted, and back on the next add
+        assert!(
+            search_config.min_bitmap_entries < search_config.max_direct_entries,
+            "min_bitmap_entries ({}) must be less than max_direct_entries ({})",
+            search_config.min_bitmap_entries,
+            search_config.max_direct_entries
+        );
         let mut t = Trie {
             trie_entries: Vec::new(),
             dictionary_map: HashMap::new(),
@@ -86,6 +97,7 @@ impl Trie {
         curr_row: usize,
         dictionary_index: u32,
         dictionary_attribute: u8,
+        value: u16,
         entry_pos: u16,
         len: u16,
     ) {
@@ -94,15 +106,16 @@ impl Trie {
             Some(e) => {
                 let v = &mut e.entries;
                 for vv in v.iter_mut() {
-                    if vv.0 == dictionary_index && vv.1 == dictionary_attribute {
+                    // the values of a multi-valued attribute can end on the same row, each keeps its own entry
+                    if vv.0 == dictionary_index && vv.1 == dictionary_attribute && vv.2 == value {
                         return;
                     }
                 }
-                v.push((dictionary_index, dictionary_attribute, entry_pos,len));
+                v.push((dictionary_index, dictionary_attribute, value, entry_pos, len));
             }
             None => {
                 let e = DictionaryMapEntry {
-                    entries: vec![(dictionary_index, dictionary_attribute, entry_pos,len)],
+                    entries: vec![(dictionary_index, dictionary_attribute, value, entry_pos, len)],
                 };
                 self.dictionary_map.insert(curr_row, e);
             }
@@ -114,11 +127,12 @@ impl Trie {
         curr_row: usize,
         dictionary_index: u32,
         dictionary_attribute: u8,
+        value: u16,
     ) -> bool {
         if let Some(e) = self.dictionary_map.get_mut(&curr_row) {
             let v = &mut e.entries;
             for (i, vv) in v.iter().enumerate() {
-                if vv.0 == dictionary_index && vv.1 == dictionary_attribute {
+                if vv.0 == dictionary_index && vv.1 == dictionary_attribute && vv.2 == value {
                     v.remove(i);
                     break;
                 }
@@ -139,6 +153,7 @@ impl Trie {
         word: &str,
         dictionary_index: u32,
         dictionary_attribute: u8,
+        value: u16,
         entry_pos: u16,
     ) {
         let len = word.len() as u16; // slice in bytes
@@ -189,15 +204,88 @@ impl Trie {
                     terminated,
                 };
                 entry.add(c, ni);
-                if let TrieEntry::TrieEntryV(v) = entry {
-                    if v.0.len() >= self.search_config.max_direct_entries {
-                        let promoted = TrieEntryG::promote(v);
-                        self.trie_entries[curr_row] = TrieEntry::TrieEntryG(promoted);
+                self.promote_if_full(curr_row);
+            }
+        }
+        self.update_dictionary_entry(prev_row, dictionary_index, dictionary_attribute, value, entry_pos, len);
+    }
+
+    fn promote_if_full(&mut self, row: usize) {
+        if let TrieEntry::TrieEntryV(v) = &self.trie_entries[row] {
+            if v.0.len() >= self.search_config.max_direct_entries {
+                let promoted = TrieEntryG::promote(v);
+                self.trie_entries[row] = TrieEntry::TrieEntryG(promoted);
+            }
+        }
+    }
+
+    fn demote_if_sparse(&mut self, row: usize) {
+        if let TrieEntry::TrieEntryG(g) = &self.trie_entries[row] {
+            if g.positions.len() <= self.search_config.min_bitmap_entries {
+                let demoted = TrieEntryV::demote(g);
+                self.trie_entries[row] = TrieEntry::TrieEntryV(demoted);
+            }
+        }
+    }
+
+    // Merges all the words of the other trie into this one. Parallel ingestion builds one sub-trie
+    // per thread, and grafts them here at the end. The dictionary map entries are moved with the rows.
+    pub fn merge(&mut self, other: Trie) {
+        self.merge_row(0, &other, 0, None);
+    }
+
+    // Same as merge, the dictionary indices of the other trie are positions in the indices
+    pub fn merge_remapped(&mut self, other: Trie, indices: &[u32]) {
+        self.merge_row(0, &other, 0, Some(indices));
+    }
+
+    fn merge_row(&mut self, row: usize, other: &Trie, other_row: usize, indices: Option<&[u32]>) {
+        if let Some(e) = other.dictionary_map.get(&other_row) {
+            for (dictionary_index, dictionary_attribute, value, entry_pos, len) in &e.entries {
+                let dictionary_index = indices.map_or(*dictionary_index, |indices| indices[*dictionary_index as usize]);
+                self.update_dictionary_entry(row, dictionary_index, *dictionary_attribute, *value, *entry_pos, *len);
+            }
+        }
+        for (c, ni) in other.trie_entries[other_row].get_all() {
+            match self.trie_entries[row].find(c) {
+                Some(existing) => {
+                    if ni.terminated {
+                        self.trie_entries[row].update_terminated(c, true);
+                    }
+                    if ni.index == 0 {
+                        continue;
                     }
+                    if existing.index != 0 {
+                        self.merge_row(existing.index as usize, other, ni.index as usize, indices);
+                    } else {
+                        let position = self.copy_row(other, ni.index as usize, indices);
+                        self.trie_entries[row].update_index(c, position);
+                    }
+                }
+                None => {
+                    let index = if ni.index != 0 {
+                        self.copy_row(other, ni.index as usize, indices)
+                    } else {
+                        0
+                    };
+                    self.trie_entries[row].add(
+                        c,
+                        NodeIndex {
+                            index,
+                            terminated: ni.terminated,
+                        },
+                    );
+                    self.promote_if_full(row);
                 }
             }
         }
-        self.update_dictionary_entry(prev_row, dictionary_index, dictionary_attribute, entry_pos,len);
+    }
+
+    // copies the whole subtree starting at the other_row into the free rows of this trie
+    fn copy_row(&mut self, other: &Trie, other_row: usize, indices: Option<&[u32]>) -> u32 {
+        let position = self.add_trie_entry(TrieEntryV(Vec::new()));
+        self.merge_row(position as usize, other, other_row, indices);
+        position
     }
 
     fn add_trie_entry(&mut self, tt: TrieEntryV) -> u32 {
@@ -212,7 +300,15 @@ impl Trie {
         position
     }
 
+    // the dictionary always passes its own limit, kept for the tests
+    #[cfg(test)]
     pub fn search(&self, term: &str, ignore_max_search_results : bool) -> Vec<TrieSearchResult> {
+        let max_search_results = (!ignore_max_search_results).then_some(self.search_config.max_search_results);
+        self.search_with_limit(term, max_search_results)
+    }
+
+    // same as search, with the limit given by the caller, None for all the results
+    pub fn search_with_limit(&self, term: &str, max_search_results: Option<usize>) -> Vec<TrieSearchResult> {
         let mut res = Vec::new();
         let mut curr_row = 0;
         let mut last_terminated = false;
@@ -246,7 +342,7 @@ impl Trie {
             let w = term.to_string() + &c.to_string(); 
             bfs_stack.push((w, ni));
         }
-        while bfs_stack.len() > 0 && (ignore_max_search_results || res.len() < self.search_config.max_search_results) {
+        while !bfs_stack.is_empty() && max_search_results.is_none_or(|m| res.len() < m) {
             let e = bfs_stack.pop();
             match e {
                 None => break,
@@ -274,7 +370,183 @@ impl Trie {
         res
     }
 
-    pub fn delete_word(&mut self, word: &str, dictionary_index: u32, dictionary_attribute: u8) {
+    pub fn stats(&self) -> TrieStats {
+        let mut stats = TrieStats {
+            free_rows: self.free_list.len(),
+            dictionary_map_entries: self.dictionary_map.len(),
+            free_list_heap_bytes: self.free_list.capacity() * size_of::<usize>(),
+            nodes_heap_bytes: self.trie_entries.capacity() * size_of::<TrieEntry>(),
+            dictionary_map_heap_bytes: self.dictionary_map.capacity()
+                * (size_of::<usize>() + size_of::<DictionaryMapEntry>()),
+            ..TrieStats::default()
+        };
+        for e in self.dictionary_map.values() {
+            stats.postings += e.entries.len();
+            stats.dictionary_map_heap_bytes += e.entries.capacity() * size_of::<(u32, u8, u16, u16, u16)>();
+        }
+        let mut edges = 0;
+        let mut bfs_queue = VecDeque::from([(0usize, 0usize)]);
+        while let Some((row, depth)) = bfs_queue.pop_front() {
+            if stats.depth_histogram.len() <= depth {
+                stats.depth_histogram.push(0);
+            }
+            stats.depth_histogram[depth] += 1;
+            match &self.trie_entries[row] {
+                TrieEntry::TrieEntryV(v) => {
+                    stats.direct_nodes += 1;
+                    stats.nodes_heap_bytes += v.0.capacity() * size_of::<(u8, NodeIndex)>();
+                }
+                TrieEntry::TrieEntryG(g) => {
+                    stats.bitmap_nodes += 1;
+                    stats.nodes_heap_bytes += g.positions.capacity() * size_of::<NodeIndex>();
+                }
+            }
+            for (_, ni) in self.trie_entries[row].get_all() {
+                edges += 1;
+                if ni.index != 0 {
+                    bfs_queue.push_back((ni.index as usize, depth + 1));
+                }
+            }
+        }
+        stats.average_fan_out = edges as f64 / (stats.direct_nodes + stats.bitmap_nodes) as f64;
+        stats
+    }
+
+    // Checks the structure of the trie: child indices point to live rows, bitmaps match the positions,
+    // and the terminated edges match the dictionary map
+    pub fn validate(&self) -> ValidationReport {
+        let mut report = ValidationReport::default();
+        let free: HashSet<usize> = self.free_list.iter().copied().collect();
+        let mut seen: HashSet<usize> = HashSet::new();
+        for row in 0..self.trie_entries.len() {
+            if free.contains(&row) {
+                continue;
+            }
+            let entry = &self.trie_entries[row];
+            if let TrieEntry::TrieEntryG(g) = entry {
+                if g.bitmap.count_ones() as usize != g.positions.len() {
+                    report.violations.push(Violation::BitmapMismatch {
+                        row,
+                        bitmap: g.bitmap.count_ones() as usize,
+                        positions: g.positions.len(),
+                    });
+                    // get_all would index out of the positions
+                    continue;
+                }
+            }
+            let mut terminated = false;
+            for (_, ni) in entry.get_all() {
+                terminated |= ni.terminated;
+                let index = ni.index as usize;
+                if index == 0 {
+                    continue;
+                }
+                if index >= self.trie_entries.len() {
+                    report.violations.push(Violation::DanglingIndex { row, index });
+                } else if free.contains(&index) {
+                    report.violations.push(Violation::FreeRowReferenced { row, index });
+                } else if !seen.insert(index) {
+                    report.violations.push(Violation::SharedRow { row, index });
+                }
+            }
+            if terminated && !self.dictionary_map.contains_key(&row) {
+                report.violations.push(Violation::MissingDictionaryMap { row });
+            }
+        }
+        for (row, e) in &self.dictionary_map {
+            let row = *row;
+            let terminated = row < self.trie_entries.len()
+                && !free.contains(&row)
+                && self.trie_entries[row]
+                    .get_all()
+                    .iter()
+                    .any(|(_, ni)| ni.terminated);
+            if !terminated {
+                report.violations.push(Violation::OrphanDictionaryMap { row });
+            }
+            if e.entries.is_empty() {
+                report.violations.push(Violation::EmptyDictionaryMap { row });
+            }
+        }
+        report
+    }
+
+    // all the dictionary references from the dictionary map, as (row, dictionary index, attribute)
+    pub fn postings(&self) -> impl Iterator<Item = (usize, u32, u8)> + '_ {
+        self.dictionary_map
+            .iter()
+            .flat_map(|(row, e)| e.entries.iter().map(move |entry| (*row, entry.0, entry.1)))
+    }
+
+    // live rows, without the ones on the free list
+    pub fn node_count(&self) -> usize {
+        self.trie_entries.len() - self.free_list.len()
+    }
+
+    pub fn free_rows(&self) -> usize {
+        self.free_list.len()
+    }
+
+    // share of the trie rows that are on the free list
+    pub fn fragmentation(&self) -> f32 {
+        self.free_list.len() as f32 / self.trie_entries.len() as f32
+    }
+
+    // Deleted rows are only put on the free list, so the trie vector never shrinks.
+    // Compaction renumbers the live rows in breadth first order, rewrites the child indices and
+    // the dictionary map keys to the new row numbers, and releases the free rows.
+    pub fn compact(&mut self) {
+        let mut remap: Vec<Option<u32>> = vec![None; self.trie_entries.len()];
+        let mut order = vec![0];
+        remap[0] = Some(0);
+        let mut i = 0;
+        while i < order.len() {
+            for (_, ni) in self.trie_entries[order[i]].get_all() {
+                let child = ni.index as usize;
+                if child != 0 && remap[child].is_none() {
+                    remap[child] = Some(order.len() as u32);
+                    order.push(child);
+                }
+            }
+            i += 1;
+        }
+
+        let mut old_entries = std::mem::take(&mut self.trie_entries);
+        let mut trie_entries = Vec::with_capacity(order.len());
+        for row in &order {
+            let mut entry =
+                std::mem::replace(&mut old_entries[*row], TrieEntry::TrieEntryV(TrieEntryV(Vec::new())));
+            for (c, ni) in entry.get_all() {
+                if let Some(index) = remap[ni.index as usize].filter(|_| ni.index != 0) {
+                    entry.update_index(c, index);
+                }
+            }
+            trie_entries.push(entry);
+        }
+        self.trie_entries = trie_entries;
+        self.dictionary_map = std::mem::take(&mut self.dictionary_map)
+            .into_iter()
+            .filter_map(|(row, e)| remap[row].map(|index| (index as usize, e)))
+            .collect();
+        self.free_list = Vec::new();
+    }
+
+    // Rewrites the dictionary indices after the dictionary has been compacted.
+    // Entries with the index missing from the remapping are dropped
+    pub fn remap_dictionary_indices(&mut self, remap: &HashMap<usize, usize>) {
+        self.dictionary_map.retain(|_, e| {
+            e.entries.retain_mut(|entry| match remap.get(&(entry.0 as usize)) {
+                Some(index) => {
+                    entry.0 = *index as u32;
+                    true
+                }
+                None => false,
+            });
+            !e.entries.is_empty()
+        });
+    }
+
+    pub fn delete_word(&mut self, word: &str, dictionary_index: u32, dictionary_attribute: u8, value: u16) {
         let mut curr_row = 0;
         let mut prev_row = 0;
         let mut trail: Vec<(usize, char, bool)> = Vec::new();
@@ -289,8 +561,21 @@ impl Trie {
         }
 
         let removed =
-            self.remove_dictionary_entry(prev_row, dictionary_index, dictionary_attribute);
+            self.remove_dictionary_entry(prev_row, dictionary_index, dictionary_attribute, value);
         if removed {
+            // no word ends on the row any more, the others that ended on it were deleted before this one
+            if let Some(&(row, c, _)) = trail.last() {
+                for (other, ni) in self.trie_entries[row].get_all() {
+                    if idx(other) == idx(c) || !ni.terminated {
+                        continue;
+                    }
+                    if ni.index == 0 {
+                        self.trie_entries[row].remove(other);
+                    } else {
+                        self.trie_entries[row].update_terminated(other, false);
+                    }
+                }
+            }
             for j in (0..trail.len()).rev() {
                 let (row, c, terminated) = trail[j];
                 if j == trail.len() - 1 {
@@ -303,9 +588,12 @@ impl Trie {
                     break;
                 }
                 let all_removed = self.trie_entries[row].remove(c);
-                if all_removed {
-                    self.free_list.push(row);
+                if !all_removed {
+                    // the row still has other children, the parent keeps pointing to it
+                    self.demote_if_sparse(row);
+                    break;
                 }
+                self.free_list.push(row);
                 if j > 0 {
                     let (row2, c2, _) = trail[j - 1];
                     self.trie_entries[row2].update_index(c2, 0);
@@ -1,12 +1,13 @@
 use super::*;
+use crate::validation::Violation;
 
 fn prepare_trie() -> Trie {
     let mut t = Trie::new(SearchConfig::default());
-    t.add_word("dragan", 0, 0, 0);
-    t.add_word("dragana", 1, 0, 0);
-    t.add_word("drni", 2, 0, 0);
-    t.add_word("dusan", 3, 0, 0);
-    t.add_word("nepar", 4, 0, 0);
+    t.add_word("dragan", 0, 0, 0, 0);
+    t.add_word("dragana", 1, 0, 0, 0);
+    t.add_word("drni", 2, 0, 0, 0);
+    t.add_word("dusan", 3, 0, 0, 0);
+    t.add_word("nepar", 4, 0, 0, 0);
     t
 }
 #[test]
@@ -41,7 +42,7 @@ fn test_search_exact() {
 #[test]
 fn test_search_exact_basic() {
     let mut t = Trie::new(SearchConfig::default());
-    t.add_word("dragan", 0, 0, 0);
+    t.add_word("dragan", 0, 0, 0, 0);
     let p = t
         .search("DRAGAN",false)
         .iter()
@@ -53,8 +54,8 @@ fn test_search_exact_basic() {
 #[test]
 fn test_search_exact_basic_two() {
     let mut t = Trie::new(SearchConfig::default());
-    t.add_word("dragan", 0, 0, 0);
-    t.add_word("dragana", 1, 0, 0);
+    t.add_word("dragan", 0, 0, 0, 0);
+    t.add_word("dragana", 1, 0, 0, 0);
     let p = t
         .search("DRAGAN", false)
         .iter()
@@ -77,19 +78,19 @@ fn test_add_with_dictionary_index() {
         (
             "DRAGAN".to_string(),
             DictionaryMapEntry {
-                entries: vec![(0, 0, 0, 6)],
+                entries: vec![(0, 0, 0, 0, 6)],
             },
         ),
         (
             "DRAGANA".to_string(),
             DictionaryMapEntry {
-                entries: vec![(1, 0, 0, 7)],
+                entries: vec![(1, 0, 0, 0, 7)],
             },
         ),
         (
             "DRNI".to_string(),
             DictionaryMapEntry {
-                entries: vec![(2, 0, 0, 4)],
+                entries: vec![(2, 0, 0, 0, 4)],
             },
         ),
     ];
@@ -100,8 +101,8 @@ fn test_add_with_dictionary_index() {
 #[test]
 fn test_add_with_dictionary_index_with_duplicate_word() {
     let mut tr = prepare_trie();
-    tr.add_word("dragan", 5, 0, 0);
-    tr.add_word("dragana", 7, 0, 0);
+    tr.add_word("dragan", 5, 0, 0, 0);
+    tr.add_word("dragana", 7, 0, 0, 0);
     let mut p = tr
         .search("DR", false)
         .iter()
@@ -112,19 +113,19 @@ fn test_add_with_dictionary_index_with_duplicate_word() {
         (
             "DRAGAN".to_string(),
             DictionaryMapEntry {
-                entries: vec![(0, 0, 0, 6), (5, 0, 0, 6)], // not adding to dictionary entry, why??
+                entries: vec![(0, 0, 0, 0, 6), (5, 0, 0, 0, 6)], // not adding to dictionary entry, why??
             },
         ),
         (
             "DRAGANA".to_string(),
             DictionaryMapEntry {
-                entries: vec![(1, 0, 0, 7), (7, 0, 0, 7)],
+                entries: vec![(1, 0, 0, 0, 7), (7, 0, 0, 0, 7)],
             },
         ),
         (
             "DRNI".to_string(),
             DictionaryMapEntry {
-                entries: vec![(2, 0, 0, 4)],
+                entries: vec![(2, 0, 0, 0, 4)],
             },
         ),
     ];
@@ -136,7 +137,7 @@ fn test_add_with_dictionary_index_with_duplicate_word() {
 #[test]
 fn test_word_with_parent() {
     let mut tr = prepare_trie();
-    tr.add_word("dragan miocinovic", 5, 0, 0);
+    tr.add_word("dragan miocinovic", 5, 0, 0, 0);
     //println!("{:#?}", tr);
     let p = tr
         .search("DRAGAN MIOC", false)
@@ -162,7 +163,7 @@ fn should_be_empty() {
 #[test]
 fn basic_deletion() {
     let mut trie = prepare_trie();
-    trie.delete_word("dragana", 1, 0);
+    trie.delete_word("dragana", 1, 0, 0);
     let p = trie
         .search("DRAGAN", false)
         .iter()
@@ -175,7 +176,7 @@ fn basic_deletion() {
 #[test]
 fn delete_word_with_children() {
     let mut trie = prepare_trie();
-    trie.delete_word("dragan", 0, 0);
+    trie.delete_word("dragan", 0, 0, 0);
     let p = trie
         .search("DRAGAN", false)
         .iter()
@@ -188,16 +189,16 @@ fn delete_word_with_children() {
 #[test]
 fn reusing_trie_entry_slots() {
     let mut t = Trie::new(SearchConfig::default());
-    t.add_word("petar", 0, 0, 0);
-    t.add_word("sestar", 1, 0, 0);
-    t.add_word("prevar", 2, 0, 0);
-    t.add_word("godar", 3, 0, 0);
-    t.add_word("mitar", 4, 0, 0);
+    t.add_word("petar", 0, 0, 0, 0);
+    t.add_word("sestar", 1, 0, 0, 0);
+    t.add_word("prevar", 2, 0, 0, 0);
+    t.add_word("godar", 3, 0, 0, 0);
+    t.add_word("mitar", 4, 0, 0, 0);
     let len1 = t.trie_entries.len();
-    t.delete_word("sestar", 1, 0);
+    t.delete_word("sestar", 1, 0, 0);
     println!("{:#?}\n", t);
     assert_eq!(t.free_list.len(), 5); //first letter will not be reused, it will be in first trie entry
-    t.add_word("julian", 5, 0, 0);
+    t.add_word("julian", 5, 0, 0, 0);
     let len2 = t.trie_entries.len();
     assert_eq!(len1, len2);
 
@@ -209,7 +210,7 @@ fn reusing_trie_entry_slots() {
     let tt = vec!["JULIAN".to_string()];
     assert_eq!(p, tt);
     // assert_eq!(t.free_list.len(),0);
-    t.add_word("mondays", 6, 0, 0);
+    t.add_word("mondays", 6, 0, 0, 0);
     //t.add_word("monday",6,0);
     // will it owerwrite previous word?
     let pp = t
@@ -219,3 +220,171 @@ fn reusing_trie_entry_slots() {
         .collect::<Vec<String>>();
     assert_eq!(pp, tt);
 }
+
+#[test]
+fn merge_sub_trie() {
+    let mut t = prepare_trie();
+    let mut other = Trie::new(SearchConfig::default());
+    other.add_word("dragoljub", 5, 0, 0, 0);
+    other.add_word("dragan", 6, 0, 0, 0);
+    other.add_word("zoran", 7, 0, 0, 0);
+    t.merge(other);
+    let mut p = t
+        .search("DRAG", false)
+        .iter()
+        .map(|x| x.word.clone())
+        .collect::<Vec<String>>();
+    p.sort();
+    let tt = vec![
+        "DRAGAN".to_string(),
+        "DRAGANA".to_string(),
+        "DRAGOLJUB".to_string(),
+    ];
+    assert_eq!(p, tt);
+    let p = t
+        .search("ZOR", false)
+        .iter()
+        .map(|x| (x.word.clone(), x.entries.clone()))
+        .collect::<Vec<(String, DictionaryMapEntry)>>();
+    assert_eq!(
+        p,
+        vec![(
+            "ZORAN".to_string(),
+            DictionaryMapEntry {
+                entries: vec![(7, 0, 0, 0, 5)],
+            },
+        )]
+    );
+}
+
+#[test]
+fn merge_remapped_sub_trie() {
+    let mut t = prepare_trie();
+    let mut other = Trie::new(SearchConfig::default());
+    // the indices of the sub-trie are positions in the remapped indices
+    other.add_word("zoran", 0, 0, 0, 0);
+    other.add_word("zorana", 1, 0, 0, 0);
+    t.merge_remapped(other, &[9, 4]);
+    let p = t
+        .search("ZOR", false)
+        .iter()
+        .map(|x| (x.word.clone(), x.entries.entries[0].0))
+        .collect::<Vec<(String, u32)>>();
+    assert_eq!(p, vec![("ZORAN".to_string(), 9), ("ZORANA".to_string(), 4)]);
+}
+
+#[test]
+fn values_on_the_same_row() {
+    let mut t = Trie::new(SearchConfig::default());
+    t.add_word("ann", 0, 0, 0, 0);
+    t.add_word("anx", 0, 0, 1, 0);
+    let p = t.search("ANX", false);
+    assert_eq!(p[0].entries.entries, vec![(0, 0, 0, 0, 3), (0, 0, 1, 0, 3)]);
+    t.delete_word("ann", 0, 0, 0);
+    let p = t.search("ANX", false);
+    assert_eq!(p[0].entries.entries, vec![(0, 0, 1, 0, 3)]);
+}
+
+#[test]
+#[should_panic(expected = "must be less than max_direct_entries")]
+fn thrashing_config() {
+    Trie::new(SearchConfig {
+        max_direct_entries: 4,
+        min_bitmap_entries: 4,
+        ..SearchConfig::default()
+    });
+}
+
+#[test]
+fn compact_trie() {
+    let mut t = prepare_trie();
+    t.delete_word("dusan", 3, 0, 0);
+    t.delete_word("dragana", 1, 0, 0);
+    assert!(!t.free_list.is_empty());
+    let live = t.trie_entries.len() - t.free_list.len();
+    t.compact();
+    assert_eq!(t.trie_entries.len(), live);
+    assert_eq!(t.free_list.len(), 0);
+    let mut p = t
+        .search("D", false)
+        .iter()
+        .map(|x| (x.word.clone(), x.entries.clone()))
+        .collect::<Vec<(String, DictionaryMapEntry)>>();
+    p.sort();
+    let mut tt = vec![
+        (
+            "DRAGAN".to_string(),
+            DictionaryMapEntry {
+                entries: vec![(0, 0, 0, 0, 6)],
+            },
+        ),
+        (
+            "DRNI".to_string(),
+            DictionaryMapEntry {
+                entries: vec![(2, 0, 0, 0, 4)],
+            },
+        ),
+    ];
+    tt.sort();
+    assert_eq!(p, tt);
+    t.remap_dictionary_indices(&HashMap::from([(2, 0)]));
+    let p = t
+        .search("DR", false)
+        .iter()
+        .map(|x| (x.word.clone(), x.entries.clone()))
+        .collect::<Vec<(String, DictionaryMapEntry)>>();
+    assert_eq!(
+        p,
+        vec![(
+            "DRNI".to_string(),
+            DictionaryMapEntry {
+                entries: vec![(0, 0, 0, 0, 4)],
+            },
+        )]
+    );
+}
+
+#[test]
+fn demote_sparse_entry() {
+    let mut t = Trie::new(SearchConfig::default());
+    for (i, w) in ["bab", "beb", "bib", "bob", "bub"].iter().enumerate() {
+        t.add_word(w, i as u32, 0, 0, 0);
+    }
+    let row = t.trie_entries[0].find('B').unwrap().index as usize;
+    assert!(matches!(t.trie_entries[row], TrieEntry::TrieEntryG(_)));
+    t.delete_word("bub", 4, 0, 0);
+    t.delete_word("bob", 3, 0, 0);
+    // still above min_bitmap_entries, no thrashing back and forth around max_direct_entries
+    assert!(matches!(t.trie_entries[row], TrieEntry::TrieEntryG(_)));
+    t.delete_word("bib", 2, 0, 0);
+    assert!(matches!(t.trie_entries[row], TrieEntry::TrieEntryV(_)));
+    let mut p = t
+        .search("B", false)
+        .iter()
+        .map(|x| x.word.clone())
+        .collect::<Vec<String>>();
+    p.sort();
+    assert_eq!(p, vec!["BAB".to_string(), "BEB".to_string()]);
+}
+
+#[test]
+fn validate_trie() {
+    let mut t = prepare_trie();
+    assert!(t.validate().is_ok());
+    t.delete_word("dusan", 3, 0, 0);
+    t.delete_word("dragan", 0, 0, 0);
+    assert!(t.validate().is_ok());
+    let row = t.trie_entries[0].find('N').unwrap().index as usize;
+    t.free_list.push(row);
+    t.dictionary_map.insert(row, DictionaryMapEntry { entries: vec![] });
+    let report = t.validate();
+    assert!(report
+        .violations
+        .contains(&Violation::FreeRowReferenced { row: 0, index: row }));
+    assert!(report
+        .violations
+        .contains(&Violation::OrphanDictionaryMap { row }));
+    assert!(report
+        .violations
+        .contains(&Violation::EmptyDictionaryMap { row }));
+}
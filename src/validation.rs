@@ -0,0 +1,59 @@
+/// Structural problem found by `Dictionary::validate`. Rows are trie rows, indices are dictionary indices.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Violation {
+    // child index outside of the trie
+    DanglingIndex {
+        row: usize,
+        index: usize,
+    },
+    // child index pointing to a row on the free list
+    FreeRowReferenced {
+        row: usize,
+        index: usize,
+    },
+    // child row reachable from more than one parent
+    SharedRow {
+        row: usize,
+        index: usize,
+    },
+    // popcount of a TrieEntryG bitmap doesn't match its positions
+    BitmapMismatch {
+        row: usize,
+        bitmap: usize,
+        positions: usize,
+    },
+    // terminated edge without the dictionary map entry
+    MissingDictionaryMap {
+        row: usize,
+    },
+    // dictionary map entry without a terminated edge in the row
+    OrphanDictionaryMap {
+        row: usize,
+    },
+    EmptyDictionaryMap {
+        row: usize,
+    },
+    // dictionary map pointing to a missing or deleted dictionary entry
+    StalePosting {
+        row: usize,
+        index: usize,
+    },
+    // dictionary map pointing to an attribute the dictionary entry doesn't have
+    MissingAttribute {
+        row: usize,
+        index: usize,
+        attribute: u8,
+    },
+}
+
+/// Result of an integrity check, empty if the structure is consistent
+#[derive(Debug, Clone, Default)]
+pub struct ValidationReport {
+    pub violations: Vec<Violation>,
+}
+
+impl ValidationReport {
+    pub fn is_ok(&self) -> bool {
+        self.violations.is_empty()
+    }
+}